@@ -0,0 +1,162 @@
+// common_meter_generic/src/address.rs
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::{timeout, Instant};
+use log::{debug, info, warn};
+use crate::error::ModbusError;
+use crate::settings::ModbusSettings;
+
+/// Longest host name DNS allows.
+const MAX_HOST_NAME: usize = 253;
+/// Longest label of a host name.
+const MAX_LABEL: usize = 63;
+
+/// Where a meter can be reached: an IP address or a host name, and a port.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Endpoint {
+    /// IP address without brackets, or host name
+    pub host: String,
+    pub port: u16,
+}
+
+impl Endpoint {
+    pub fn new(host: &str, port: u16) -> Result<Self, String> {
+        if port == 0 {
+            return Err("invalid port number 0".to_string());
+        }
+        if host.parse::<IpAddr>().is_err() && !is_host_name(host) {
+            return Err(format!("'{}' is neither an IP address nor a host name", host));
+        }
+        Ok(Endpoint { host: host.to_string(), port })
+    }
+
+    /// Parses `host`, `host:port`, an IPv6 address with or without brackets, or `[address]:port`;
+    /// `default_port` applies when the text has none.
+    pub fn parse(text: &str, default_port: u16) -> Result<Self, String> {
+        let text = text.trim();
+        let port = |port: &str| port.parse::<u16>().map_err(|_| format!("invalid port '{}' in '{}'", port, text));
+        if let Some(bracketed) = text.strip_prefix('[') {
+            let (host, rest) = bracketed.split_once(']').ok_or_else(|| format!("missing ']' in '{}'", text))?;
+            if host.parse::<std::net::Ipv6Addr>().is_err() {
+                return Err(format!("'{}' in brackets is not an IPv6 address", host));
+            }
+            return match rest {
+                "" => Endpoint::new(host, default_port),
+                _ => match rest.strip_prefix(':') {
+                    Some(p) => Endpoint::new(host, port(p)?),
+                    None => Err(format!("unexpected '{}' after ']' in '{}'", rest, text)),
+                },
+            };
+        }
+        if text.parse::<IpAddr>().is_ok() {
+            return Endpoint::new(text, default_port);
+        }
+        match text.split_once(':') {
+            Some((host, p)) if !p.contains(':') => Endpoint::new(host, port(p)?),
+            Some(_) => Err(format!("'{}' is not an address; IPv6 addresses with a port need brackets", text)),
+            None => Endpoint::new(text, default_port),
+        }
+    }
+
+    /// The address when the host is given as one, so no lookup is needed.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.host.parse().ok()
+    }
+
+    /// Looks the host name up, or takes the cached result when it is younger than `cache_for`.
+    pub async fn resolve(&self, cache_for: Duration) -> io::Result<Vec<SocketAddr>> {
+        if let Some(ip) = self.ip() {
+            return Ok(vec![SocketAddr::new(ip, self.port)]);
+        }
+        if let Some((addresses, at)) = resolved().lock().unwrap().get(self) {
+            if at.elapsed() < cache_for {
+                return Ok(addresses.clone());
+            }
+        }
+        let addresses: Vec<SocketAddr> = lookup_host((self.host.as_str(), self.port)).await?.collect();
+        if addresses.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} has no addresses", self.host)));
+        }
+        debug!("Resolved {} to {:?}", self.host, addresses);
+        resolved().lock().unwrap().insert(self.clone(), (addresses.clone(), Instant::now()));
+        Ok(addresses)
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ip() {
+            Some(IpAddr::V6(_)) => write!(f, "[{}]:{}", self.host, self.port),
+            _ => write!(f, "{}:{}", self.host, self.port),
+        }
+    }
+}
+
+/// Host names looked up so far, with their addresses and when they were looked up.
+type Resolved = HashMap<Endpoint, (Vec<SocketAddr>, Instant)>;
+
+fn resolved() -> &'static Mutex<Resolved> {
+    static RESOLVED: OnceLock<Mutex<Resolved>> = OnceLock::new();
+    RESOLVED.get_or_init(Default::default)
+}
+
+/// Host name as RFC 1123 has it: dot-separated labels of letters, digits and inner hyphens.
+fn is_host_name(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    !host.is_empty()
+        && host.len() <= MAX_HOST_NAME
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= MAX_LABEL
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// The addresses of the meter in the order they are tried: `meter_data.ip` and `port` first,
+/// then `modbus.addresses`, which take the meter's port unless they name their own.
+pub fn endpoints(ip: &str, port: u16, settings: &ModbusSettings) -> Result<Vec<Endpoint>, String> {
+    std::iter::once(ip)
+        .chain(settings.addresses.iter().map(String::as_str))
+        .map(|text| Endpoint::parse(text, port))
+        .collect()
+}
+
+/// Opens a TCP connection to the first of `endpoints` that takes one, trying every address a
+/// host name resolves to. Each attempt is bounded by the connect timeout.
+pub async fn connect(endpoints: &[Endpoint], settings: &ModbusSettings) -> Result<(TcpStream, Endpoint), ModbusError> {
+    let connect_timeout = settings.transaction.connect_timeout();
+    let mut error = ModbusError::ConnectionFailed("no meter address configured".to_string());
+    for endpoint in endpoints {
+        let addresses = match endpoint.resolve(settings.dns_cache()).await {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                warn!("Failed to resolve {}: {}", endpoint.host, e);
+                error = ModbusError::ConnectionFailed(format!("{}: {}", endpoint.host, e));
+                continue;
+            }
+        };
+        for address in addresses {
+            info!("Attempting to connect to the Modbus device at {}", address);
+            match timeout(connect_timeout, TcpStream::connect(address)).await {
+                Ok(Ok(stream)) => return Ok((stream, endpoint.clone())),
+                Ok(Err(e)) => {
+                    warn!("Failed to connect to {}: {}", address, e);
+                    error = ModbusError::ConnectionFailed(format!("{}: {}", address, e));
+                }
+                Err(_) => {
+                    warn!("Connection attempt to {} timed out after {:?}", address, connect_timeout);
+                    error = ModbusError::Timeout;
+                }
+            }
+        }
+    }
+    Err(error)
+}
@@ -0,0 +1,247 @@
+// common_meter_generic/src/aggregation.rs
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use log::{info, warn};
+use crate::reading::{Quality, Snapshot};
+use crate::settings::AggregationSettings;
+
+/// Statistics of one register over one wall-clock interval.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Aggregate {
+    pub meter_id: String,
+    pub name: String,
+    pub unit: Option<String>,
+    pub interval: Duration,
+    /// Interval start, aligned to a multiple of `interval` since the Unix epoch (UTC)
+    pub start: SystemTime,
+    /// Good readings within the interval
+    pub samples: u32,
+    /// Mean of the samples; `None` without any
+    pub average: Option<f32>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    /// Fraction of the interval spanned by consecutive good readings at most `max_gap_secs` apart
+    pub coverage: f32,
+    /// Times the readings were interrupted by a bad reading or a pause longer than `max_gap_secs`
+    pub gaps: u32,
+    /// Energy of a power register in kWh over the covered part of the interval
+    pub energy_kwh: Option<f64>,
+}
+
+impl Aggregate {
+    /// Whether readings covered the interval without interruption.
+    pub fn is_complete(&self) -> bool {
+        self.gaps == 0 && self.coverage >= 0.999
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Accumulator {
+    /// Seconds since the epoch
+    start: u64,
+    samples: u32,
+    sum: f64,
+    min: f64,
+    max: f64,
+    covered: f64,
+    gaps: u32,
+    /// Watt-seconds
+    energy: f64,
+}
+
+impl Accumulator {
+    fn new(start: u64) -> Self {
+        Accumulator { start, samples: 0, sum: 0.0, min: f64::INFINITY, max: f64::NEG_INFINITY, covered: 0.0, gaps: 0, energy: 0.0 }
+    }
+}
+
+/// Aggregation state of one register for one interval length.
+#[derive(Debug)]
+struct Series {
+    length: u64,
+    current: Option<Accumulator>,
+    /// Last good reading as (seconds since the epoch, value), cleared by a gap
+    last: Option<(f64, f64)>,
+}
+
+/// Turns the snapshots of `statemachine_read` into interval aggregates.
+#[derive(Debug)]
+pub struct Aggregator {
+    settings: AggregationSettings,
+    series: HashMap<(String, u64), Series>,
+    units: HashMap<String, Option<String>>,
+    meter_id: String,
+}
+
+impl Aggregator {
+    /// Fails for settings that do not pass [`AggregationSettings::validate`].
+    pub fn new(settings: AggregationSettings) -> Result<Self, String> {
+        settings.validate()?;
+        Ok(Aggregator { settings, series: HashMap::new(), units: HashMap::new(), meter_id: String::new() })
+    }
+
+    /// Feeds one snapshot and returns the aggregates of the intervals it completed, including
+    /// empty ones for intervals without any reading.
+    pub fn push(&mut self, snapshot: &Snapshot) -> Vec<Aggregate> {
+        self.meter_id = snapshot.meter_id.clone();
+        let mut completed = Vec::new();
+        for name in &self.settings.registers {
+            let Some(reading) = snapshot.get(name) else {
+                continue;
+            };
+            self.units.insert(name.clone(), reading.unit.clone());
+            let time = seconds(reading.timestamp);
+            let value = reading.value.filter(|_| reading.quality == Quality::Good).map(f64::from);
+            for &length in &self.settings.intervals_secs {
+                let series = self.series.entry((name.clone(), length)).or_insert(Series { length, current: None, last: None });
+                for accumulator in series.push(time, value, self.settings.max_gap_secs as f64) {
+                    completed.push((name.clone(), length, accumulator));
+                }
+            }
+        }
+        completed.into_iter().map(|(name, length, accumulator)| self.aggregate(name, length, accumulator)).collect()
+    }
+
+    fn aggregate(&self, name: String, length: u64, accumulator: Accumulator) -> Aggregate {
+        let has_samples = accumulator.samples > 0;
+        Aggregate {
+            meter_id: self.meter_id.clone(),
+            unit: self.units.get(&name).cloned().flatten(),
+            interval: Duration::from_secs(length),
+            start: UNIX_EPOCH + Duration::from_secs(accumulator.start),
+            samples: accumulator.samples,
+            average: has_samples.then(|| (accumulator.sum / accumulator.samples as f64) as f32),
+            min: has_samples.then_some(accumulator.min as f32),
+            max: has_samples.then_some(accumulator.max as f32),
+            coverage: (accumulator.covered / length as f64).min(1.0) as f32,
+            gaps: accumulator.gaps,
+            energy_kwh: self.settings.energy.contains(&name).then(|| accumulator.energy / 3_600_000.0),
+            name,
+        }
+    }
+
+    /// Aggregates `snapshots` until the channel closes, logging every aggregate and appending it
+    /// to the aggregation file, if one is configured.
+    pub async fn run(mut self, mut snapshots: broadcast::Receiver<Snapshot>) {
+        loop {
+            let snapshot = match snapshots.recv().await {
+                Ok(snapshot) => snapshot,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Aggregation missed {} snapshots", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            for aggregate in self.push(&snapshot) {
+                info!(
+                    "Aggregate {} over {:?}: avg {:?}, min {:?}, max {:?}, {} samples, {:.0}% covered, {} gaps{}",
+                    aggregate.name,
+                    aggregate.interval,
+                    aggregate.average,
+                    aggregate.min,
+                    aggregate.max,
+                    aggregate.samples,
+                    aggregate.coverage * 100.0,
+                    aggregate.gaps,
+                    aggregate.energy_kwh.map(|kwh| format!(", {:.3} kWh", kwh)).unwrap_or_default(),
+                );
+                self.append(&aggregate);
+            }
+        }
+    }
+
+    fn append(&self, aggregate: &Aggregate) {
+        let Some(path) = &self.settings.file else {
+            return;
+        };
+        let appended = serde_json::to_string(aggregate).map_err(|e| e.to_string()).and_then(|line| {
+            let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| e.to_string())?;
+            writeln!(file, "{}", line).map_err(|e| e.to_string())
+        });
+        if let Err(e) = appended {
+            warn!("Failed to append to the aggregates {}: {}", path, e);
+        }
+    }
+}
+
+impl Series {
+    /// Adds a reading taken at `time`, `None` for a bad one. Returns the intervals it closed.
+    fn push(&mut self, time: f64, value: Option<f64>, max_gap: f64) -> Vec<Accumulator> {
+        let mut completed = Vec::new();
+        let bucket = (time as u64 / self.length) * self.length;
+        if bucket < self.current.get_or_insert_with(|| Accumulator::new(bucket)).start {
+            // The clock went back; wait for it to catch up rather than mixing intervals
+            self.last = None;
+            return completed;
+        }
+
+        let Some(value) = value else {
+            if self.last.take().is_some() {
+                self.current().gaps += 1;
+            }
+            self.roll_over(bucket, &mut completed);
+            return completed;
+        };
+        match self.last {
+            Some((last_time, last_value)) if time - last_time <= max_gap => {
+                self.integrate(last_time, last_value, time, value, &mut completed)
+            }
+            Some(_) => {
+                self.current().gaps += 1;
+                self.roll_over(bucket, &mut completed);
+            }
+            None => self.roll_over(bucket, &mut completed),
+        }
+
+        let current = self.current();
+        current.samples += 1;
+        current.sum += value;
+        current.min = current.min.min(value);
+        current.max = current.max.max(value);
+        self.last = Some((time, value));
+        completed
+    }
+
+    fn current(&mut self) -> &mut Accumulator {
+        self.current.as_mut().expect("the accumulator is created by the first reading")
+    }
+
+    /// Adds the span between two good readings, split at interval boundaries.
+    fn integrate(&mut self, mut from: f64, mut from_value: f64, to: f64, to_value: f64, completed: &mut Vec<Accumulator>) {
+        let slope = if to > from { (to_value - from_value) / (to - from) } else { 0.0 };
+        loop {
+            let length = self.length;
+            let current = self.current();
+            let end = (current.start + length) as f64;
+            if to < end {
+                current.covered += to - from;
+                current.energy += (from_value + to_value) / 2.0 * (to - from);
+                return;
+            }
+            let end_value = from_value + slope * (end - from);
+            current.covered += end - from;
+            current.energy += (from_value + end_value) / 2.0 * (end - from);
+            let next = Accumulator::new(current.start + length);
+            completed.push(std::mem::replace(current, next));
+            (from, from_value) = (end, end_value);
+        }
+    }
+
+    /// Closes the current interval and any empty ones up to `bucket`.
+    fn roll_over(&mut self, bucket: u64, completed: &mut Vec<Accumulator>) {
+        while let Some(current) = self.current.as_mut().filter(|current| current.start < bucket) {
+            let next = Accumulator::new(current.start + self.length);
+            completed.push(std::mem::replace(current, next));
+        }
+    }
+}
+
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
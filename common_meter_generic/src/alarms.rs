@@ -0,0 +1,170 @@
+// common_meter_generic/src/alarms.rs
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use crate::reading::{Quality, Snapshot};
+use crate::settings::{AlarmRule, Limit, Severity};
+
+impl AlarmRule {
+    /// Resolves the limits to values; a write register `targets` does not know gives no limit.
+    fn limits(&self, targets: &dyn Fn(&str) -> Option<f32>) -> (Option<f32>, Option<f32>) {
+        let resolve = |limit: &Option<Limit>| match limit {
+            Some(Limit::Value(value)) => Some(*value),
+            Some(Limit::WriteRegister { write_register }) => targets(write_register),
+            None => None,
+        };
+        let band = self.nominal.zip(self.tolerance_percent).map(|(nominal, percent)| nominal.abs() * percent / 100.0);
+        let high = resolve(&self.high).or_else(|| self.nominal.zip(band).map(|(nominal, band)| nominal + band));
+        let low = resolve(&self.low).or_else(|| self.nominal.zip(band).map(|(nominal, band)| nominal - band));
+        (low, high)
+    }
+}
+
+/// Whether an alarm started or ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmTransition {
+    Raised,
+    Cleared,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AlarmEvent {
+    pub transition: AlarmTransition,
+    pub alarm: ActiveAlarm,
+    /// Value that raised or cleared the alarm
+    pub value: f32,
+    pub at: SystemTime,
+}
+
+impl fmt::Display for AlarmEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let transition = match self.transition {
+            AlarmTransition::Raised => "raised",
+            AlarmTransition::Cleared => "cleared",
+        };
+        write!(
+            f,
+            "Alarm {} {} ({:?}): {} is {}, limit {}",
+            self.alarm.name, transition, self.alarm.severity, self.alarm.register, self.value, self.alarm.limit
+        )
+    }
+}
+
+/// An alarm that is currently raised.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActiveAlarm {
+    pub name: String,
+    pub register: String,
+    pub severity: Severity,
+    /// The limit that was crossed
+    pub limit: f32,
+    /// When the value first left the limits
+    pub since: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+enum RuleState {
+    Normal,
+    /// Outside the limits since, but not yet for `min_duration_secs`
+    Pending(SystemTime),
+    /// Raised on the high limit when `above`, on the low one otherwise
+    Raised { alarm: ActiveAlarm, above: bool },
+}
+
+/// Evaluates alarm rules against every read cycle.
+#[derive(Debug)]
+pub struct AlarmEngine {
+    rules: Vec<AlarmRule>,
+    states: HashMap<String, RuleState>,
+}
+
+impl AlarmEngine {
+    pub fn new(rules: Vec<AlarmRule>) -> Self {
+        AlarmEngine { rules, states: HashMap::new() }
+    }
+
+    /// Checks every rule against `snapshot` and returns the alarms raised and cleared.
+    ///
+    /// Readings that are not good leave the alarm as it is. `targets` looks up the value
+    /// configured for a write register.
+    pub fn evaluate(&mut self, snapshot: &Snapshot, targets: &dyn Fn(&str) -> Option<f32>) -> Vec<AlarmEvent> {
+        let mut events = Vec::new();
+        for rule in &self.rules {
+            let Some(reading) = snapshot.get(&rule.register).filter(|reading| reading.quality == Quality::Good) else {
+                continue;
+            };
+            let Some(value) = reading.value else {
+                continue;
+            };
+            let at = reading.timestamp;
+            let (low, high) = rule.limits(targets);
+            // The limit crossed, and whether it is the high one
+            let crossed = match (low, high) {
+                (Some(low), _) if value < low => Some((low, false)),
+                (_, Some(high)) if value > high => Some((high, true)),
+                _ => None,
+            };
+            let inside_hysteresis = low.is_none_or(|low| value >= low + rule.hysteresis) && high.is_none_or(|high| value <= high - rule.hysteresis);
+
+            let state = self.states.entry(rule.name.clone()).or_insert(RuleState::Normal);
+            match (state.clone(), crossed) {
+                (RuleState::Normal, Some((limit, above))) | (RuleState::Pending(_), Some((limit, above))) => {
+                    let since = match state {
+                        RuleState::Pending(since) => *since,
+                        _ => at,
+                    };
+                    let duration = at.duration_since(since).unwrap_or_default();
+                    if duration < Duration::from_secs(rule.min_duration_secs) {
+                        *state = RuleState::Pending(since);
+                        continue;
+                    }
+                    let alarm = ActiveAlarm {
+                        name: rule.name.clone(),
+                        register: rule.register.clone(),
+                        severity: rule.severity,
+                        limit,
+                        since,
+                    };
+                    *state = RuleState::Raised { alarm: alarm.clone(), above };
+                    events.push(AlarmEvent { transition: AlarmTransition::Raised, alarm, value, at });
+                }
+                (RuleState::Pending(_), None) => *state = RuleState::Normal,
+                (RuleState::Raised { alarm, .. }, None) if inside_hysteresis => {
+                    *state = RuleState::Normal;
+                    events.push(AlarmEvent { transition: AlarmTransition::Cleared, alarm, value, at });
+                }
+                (RuleState::Raised { alarm, above: was_above }, Some((limit, above))) if above != was_above => {
+                    // Jumped across both limits: the old alarm ends and one on the other limit starts
+                    events.push(AlarmEvent { transition: AlarmTransition::Cleared, alarm: alarm.clone(), value, at });
+                    let alarm = ActiveAlarm { limit, since: at, ..alarm };
+                    *state = RuleState::Raised { alarm: alarm.clone(), above };
+                    events.push(AlarmEvent { transition: AlarmTransition::Raised, alarm, value, at });
+                }
+                (RuleState::Raised { mut alarm, above }, Some((limit, _))) => {
+                    // A limit following a write register may have moved
+                    alarm.limit = limit;
+                    *state = RuleState::Raised { alarm, above };
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+
+    /// The raised alarms, most severe first.
+    pub fn active(&self) -> Vec<ActiveAlarm> {
+        let mut active: Vec<ActiveAlarm> = self
+            .states
+            .values()
+            .filter_map(|state| match state {
+                RuleState::Raised { alarm, .. } => Some(alarm.clone()),
+                _ => None,
+            })
+            .collect();
+        active.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.since.cmp(&b.since)));
+        active
+    }
+}
@@ -0,0 +1,273 @@
+// common_meter_generic/src/bus.rs
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep_until, timeout, Instant};
+use tokio_modbus::client::{rtu, Client, Context};
+use tokio_modbus::prelude::SlaveContext;
+use tokio_modbus::{Request, Response, Slave};
+use tokio_serial::{ClearBuffer, DataBits, SerialPort, SerialPortBuilderExt, StopBits};
+use log::{info, warn};
+use crate::address;
+use crate::client::{MeterClient, Reconnect};
+use crate::error::ModbusError;
+use crate::settings::{BusSettings, ModbusSettings, Parity};
+
+/// Requests waiting for the bus before callers have to wait to queue theirs.
+const QUEUE_CAPACITY: usize = 64;
+
+/// One request waiting for its turn on the bus.
+struct Job {
+    slave: u8,
+    group: u8,
+    /// Order of arrival, so requests of the same group are served first come, first served
+    seq: u64,
+    request: Request<'static>,
+    reply: oneshot::Sender<tokio_modbus::Result<Response>>,
+}
+
+impl Job {
+    fn fail(self, reason: &str) {
+        let error = io::Error::new(io::ErrorKind::TimedOut, format!("slave {} {}", self.slave, reason));
+        // The caller may have given up already
+        let _ = self.reply.send(Err(error.into()));
+    }
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    /// The lowest group and, within it, the oldest request is the greatest, so it is popped
+    /// first from the max-heap.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.group, other.seq).cmp(&(self.group, self.seq))
+    }
+}
+
+/// Whether a slave has been answering lately.
+#[derive(Debug, Default)]
+struct SlaveHealth {
+    unanswered: u32,
+    isolated_until: Option<Instant>,
+}
+
+impl SlaveHealth {
+    fn is_isolated(&self, now: Instant) -> bool {
+        self.isolated_until.is_some_and(|until| now < until)
+    }
+
+    /// Exception responses count as answers; only silence and garbage count against a slave.
+    fn record(&mut self, slave: u8, answered: bool, settings: &BusSettings) {
+        if answered {
+            if self.isolated_until.take().is_some() {
+                info!("Slave {} answers again", slave);
+            }
+            self.unanswered = 0;
+            return;
+        }
+        self.unanswered += 1;
+        if self.unanswered >= settings.isolate_after {
+            warn!("Isolating slave {} for {:?} after {} unanswered requests", slave, settings.isolation(), self.unanswered);
+            self.isolated_until = Some(Instant::now() + settings.isolation());
+        }
+    }
+}
+
+/// Owns a serial line shared by several RTU meters and puts their requests on it one at a
+/// time: lowest scan group first, with the frame gap kept between transactions, and slaves
+/// that stop answering isolated so they do not hold up the others.
+#[derive(Debug, Clone)]
+pub struct Bus {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Bus {
+    /// Opens the serial port from `settings`, or the converter at the meter's addresses when
+    /// none is set, and starts scheduling on it. The line is opened anew after a timeout or a
+    /// broken link.
+    pub async fn open(settings: &BusSettings, ip: &str, port: u16, modbus: &ModbusSettings) -> Result<Bus, ModbusError> {
+        let (line_settings, ip, modbus) = (settings.clone(), ip.to_string(), modbus.clone());
+        let mut open: Reconnect = Box::new(move || {
+            let (settings, ip, modbus) = (line_settings.clone(), ip.clone(), modbus.clone());
+            Box::pin(async move { open_line(&settings, &ip, port, &modbus).await })
+        });
+        let line = open().await?;
+        Ok(Bus::spawn(line, settings, open))
+    }
+
+    /// Starts scheduling requests on `line`, an RTU context, which `reopen` replaces when a
+    /// request times out or the link breaks. The bus runs until every handle to it is dropped.
+    pub fn spawn(line: Context, settings: &BusSettings, reopen: Reconnect) -> Bus {
+        let (jobs, queue) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(schedule(Line { context: Some(line), reopen }, settings.clone(), queue));
+        Bus { jobs }
+    }
+
+    /// A context whose requests go to `slave` through the bus, with the priority of `group`.
+    pub fn context(&self, slave: u8, group: u8) -> Context {
+        Context::from(Box::new(BusClient { bus: self.clone(), slave, group }) as Box<dyn Client>)
+    }
+
+    /// A client for `settings.unit_id` with the priority of `settings.scan_group`. A retry after a
+    /// timeout takes a new context; the scheduler keeps the line itself clean.
+    pub fn client(&self, settings: &ModbusSettings) -> MeterClient {
+        let (bus, slave, group) = (self.clone(), settings.unit_id(), settings.scan_group);
+        MeterClient::new(self.context(slave, group), settings).with_reconnect(Box::new(move || {
+            let context = bus.context(slave, group);
+            Box::pin(async move { Ok(context) })
+        }))
+    }
+
+    /// Whether the scheduler has stopped, e.g. because it panicked.
+    pub fn is_closed(&self) -> bool {
+        self.jobs.is_closed()
+    }
+}
+
+/// Opens the serial port, flushing whatever is left in its buffers, or connects to the converter.
+async fn open_line(settings: &BusSettings, ip: &str, port: u16, modbus: &ModbusSettings) -> Result<Context, ModbusError> {
+    match &settings.serial_port {
+        Some(path) => {
+            let parity = match settings.parity {
+                Parity::None => tokio_serial::Parity::None,
+                Parity::Even => tokio_serial::Parity::Even,
+                Parity::Odd => tokio_serial::Parity::Odd,
+            };
+            let stop_bits = if settings.stop_bits == 2 { StopBits::Two } else { StopBits::One };
+            let serial = tokio_serial::new(path, settings.baud_rate)
+                .data_bits(DataBits::Eight)
+                .parity(parity)
+                .stop_bits(stop_bits)
+                .open_native_async()
+                .map_err(|e| ModbusError::ConnectionFailed(format!("{}: {}", path, e)))?;
+            // A late answer to a request that timed out must not pass for the next one's
+            serial.clear(ClearBuffer::All).map_err(|e| ModbusError::ConnectionFailed(format!("{}: {}", path, e)))?;
+            info!("Opened RS-485 bus on {} at {} baud", path, settings.baud_rate);
+            Ok(rtu::attach(serial))
+        }
+        None => {
+            let endpoints = address::endpoints(ip, port, modbus).map_err(ModbusError::Config)?;
+            let (stream, endpoint) = address::connect(&endpoints, modbus).await?;
+            info!("Opened RS-485 bus through the converter at {}", endpoint);
+            Ok(rtu::attach(stream))
+        }
+    }
+}
+
+/// The RTU context on the line, dropped when it can no longer be trusted.
+struct Line {
+    context: Option<Context>,
+    reopen: Reconnect,
+}
+
+impl Line {
+    async fn get(&mut self) -> Result<&mut Context, ModbusError> {
+        if self.context.is_none() {
+            self.context = Some((self.reopen)().await?);
+        }
+        Ok(self.context.as_mut().expect("opened above"))
+    }
+}
+
+async fn schedule(mut line: Line, settings: BusSettings, mut jobs: mpsc::Receiver<Job>) {
+    let mut queue = BinaryHeap::new();
+    let mut slaves: HashMap<u8, SlaveHealth> = HashMap::new();
+    let mut seq = 0u64;
+    let mut next_frame_at = Instant::now();
+    loop {
+        if queue.is_empty() {
+            let Some(job) = jobs.recv().await else {
+                break;
+            };
+            queue.push(Job { seq, ..job });
+            seq += 1;
+        }
+        sleep_until(next_frame_at).await;
+        // Whatever arrived during the gap competes by group
+        while let Ok(job) = jobs.try_recv() {
+            queue.push(Job { seq, ..job });
+            seq += 1;
+        }
+        let Some(job) = queue.pop() else {
+            continue;
+        };
+        if job.reply.is_closed() {
+            continue;
+        }
+
+        let health = slaves.entry(job.slave).or_default();
+        if health.is_isolated(Instant::now()) {
+            job.fail("is isolated");
+            continue;
+        }
+        let context = match line.get().await {
+            Ok(context) => context,
+            Err(e) => {
+                warn!("Failed to reopen the RS-485 bus: {}", e);
+                let error = io::Error::new(io::ErrorKind::NotConnected, format!("RS-485 bus not open: {}", e));
+                let _ = job.reply.send(Err(error.into()));
+                next_frame_at = Instant::now() + settings.response_timeout();
+                continue;
+            }
+        };
+        context.set_slave(Slave(job.slave));
+        let result = match timeout(settings.response_timeout(), context.call(job.request)).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, format!("slave {} did not answer", job.slave)).into()),
+        };
+        if let Err(e) = &result {
+            // The answer may still come, or the link is gone; either way the next request
+            // goes out on a fresh line
+            warn!("Reopening the RS-485 bus after slave {} failed: {}", job.slave, e);
+            line.context = None;
+        }
+        next_frame_at = Instant::now() + settings.frame_gap();
+        health.record(job.slave, result.is_ok(), &settings);
+        let _ = job.reply.send(result);
+    }
+    info!("RS-485 bus closed");
+}
+
+/// Hands the requests of one slave to the bus scheduler.
+#[derive(Debug)]
+struct BusClient {
+    bus: Bus,
+    slave: u8,
+    group: u8,
+}
+
+impl SlaveContext for BusClient {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = slave.0;
+    }
+}
+
+#[async_trait]
+impl Client for BusClient {
+    async fn call(&mut self, request: Request<'_>) -> tokio_modbus::Result<Response> {
+        if let Request::Disconnect = request {
+            // The line belongs to the bus
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "RS-485 bus stays open").into());
+        }
+        let (reply, response) = oneshot::channel();
+        let job = Job { slave: self.slave, group: self.group, seq: 0, request: request.into_owned(), reply };
+        let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "RS-485 bus closed");
+        self.bus.jobs.send(job).await.map_err(|_| closed())?;
+        response.await.map_err(|_| closed())?
+    }
+}
@@ -0,0 +1,275 @@
+// common_meter_generic/src/client.rs
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::time::{sleep_until, timeout, Instant};
+use tokio_modbus::client::{rtu, tcp, Client, Context};
+use tokio_modbus::prelude::{Reader, Writer};
+use tokio_modbus::Slave;
+use log::{info, warn};
+use crate::address::{self, Endpoint};
+use crate::error::ModbusError;
+use crate::identity::DeviceIdentification;
+use crate::settings::{ExceptionPolicy, Framing, KeepaliveProbe, ModbusSettings, PolicyAction, ProbeTable, StringRegister, TransactionSettings, WordOrder};
+use crate::tls;
+
+/// Function code of Read Device Identification, and its MEI type.
+const ENCAPSULATED_INTERFACE: u8 = 0x2B;
+const READ_DEVICE_ID: u8 = 0x0E;
+/// Read device ID code asking for the basic objects.
+const BASIC_DEVICE_ID: u8 = 0x01;
+/// Responses a device identification may be split into before it is given up on.
+const MAX_DEVICE_ID_PARTS: usize = 8;
+
+/// Turns a TCP stream to the meter at `host` into a Modbus context: TLS first when configured,
+/// then MBAP or RTU framing as `settings.framing` says.
+pub async fn attach(stream: TcpStream, host: &str, settings: &ModbusSettings) -> Result<Context, ModbusError> {
+    match &settings.tls {
+        Some(tls) => Ok(frame(tls::handshake(stream, host, tls).await?, settings)),
+        None => Ok(frame(stream, settings)),
+    }
+}
+
+fn frame<T>(stream: T, settings: &ModbusSettings) -> Context
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + fmt::Debug + 'static,
+{
+    let slave = Slave(settings.unit_id());
+    match settings.framing {
+        Framing::Tcp => tcp::attach_slave(stream, slave),
+        Framing::RtuOverTcp => rtu::attach_slave(stream, slave),
+    }
+}
+
+/// Connects to the first of `endpoints` that takes a connection and attaches a Modbus context
+/// to it, bounded by the connect timeout.
+pub async fn open(endpoints: &[Endpoint], settings: &ModbusSettings) -> Result<Context, ModbusError> {
+    let (stream, endpoint) = address::connect(endpoints, settings).await?;
+    let connect_timeout = settings.transaction.connect_timeout();
+    let context = timeout(connect_timeout, attach(stream, &endpoint.host, settings)).await.map_err(|_| ModbusError::Timeout)??;
+    info!("Modbus connection to {} established ({:?} framing)", endpoint, settings.framing);
+    Ok(context)
+}
+
+/// A boxed in-flight request borrowing the Modbus context.
+pub type Request<'c, T> = Pin<Box<dyn Future<Output = tokio_modbus::Result<T>> + Send + 'c>>;
+
+/// Opens a new context to the same meter.
+pub type Reconnect = Box<dyn FnMut() -> Pin<Box<dyn Future<Output = Result<Context, ModbusError>> + Send>> + Send>;
+
+/// A Modbus context with per-transaction timeout, inter-frame delay and retries.
+///
+/// Every request waits for the configured silence after the previous one, is bounded by
+/// `TransactionSettings::timeout_ms` and is repeated while the exception policy says `retry`.
+/// A request that timed out may still be answered later, so it is only retried on a new context
+/// from the client's [`Reconnect`]; without one a timeout is not retried.
+pub struct MeterClient {
+    context: Context,
+    transaction: TransactionSettings,
+    policy: ExceptionPolicy,
+    next_frame_at: Option<Instant>,
+    reconnect: Option<Reconnect>,
+}
+
+impl fmt::Debug for MeterClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeterClient")
+            .field("context", &self.context)
+            .field("transaction", &self.transaction)
+            .field("policy", &self.policy)
+            .field("next_frame_at", &self.next_frame_at)
+            .field("reconnect", &self.reconnect.is_some())
+            .finish()
+    }
+}
+
+impl MeterClient {
+    pub fn new(context: Context, settings: &ModbusSettings) -> Self {
+        MeterClient {
+            context,
+            transaction: settings.transaction.clone(),
+            policy: settings.exception_policy.clone(),
+            next_frame_at: None,
+            reconnect: None,
+        }
+    }
+
+    /// Connects to the first of `endpoints` that answers; retries after a timeout connect anew.
+    pub async fn connect(endpoints: Vec<Endpoint>, settings: &ModbusSettings) -> Result<Self, ModbusError> {
+        let context = open(&endpoints, settings).await?;
+        let reopen = settings.clone();
+        Ok(MeterClient::new(context, settings).with_reconnect(Box::new(move || {
+            let (endpoints, settings) = (endpoints.clone(), reopen.clone());
+            Box::pin(async move { open(&endpoints, &settings).await })
+        })))
+    }
+
+    /// Sets how a new context is opened before a retry after a timeout.
+    pub fn with_reconnect(mut self, reconnect: Reconnect) -> Self {
+        self.reconnect = Some(reconnect);
+        self
+    }
+
+    /// Picks up timing and policy changes from a reloaded configuration.
+    pub fn configure(&mut self, settings: &ModbusSettings) {
+        self.transaction = settings.transaction.clone();
+        self.policy = settings.exception_policy.clone();
+    }
+
+    pub fn policy(&self) -> &ExceptionPolicy {
+        &self.policy
+    }
+
+    pub async fn read_holding_registers(&mut self, address: u16, quantity: u16) -> Result<Vec<u16>, ModbusError> {
+        self.with_retries(|context| Box::pin(async move { context.read_holding_registers(address, quantity).await }))
+            .await
+    }
+
+    pub async fn read_input_registers(&mut self, address: u16, quantity: u16) -> Result<Vec<u16>, ModbusError> {
+        self.with_retries(|context| Box::pin(async move { context.read_input_registers(address, quantity).await }))
+            .await
+    }
+
+    pub async fn read_coils(&mut self, address: u16, quantity: u16) -> Result<Vec<bool>, ModbusError> {
+        self.with_retries(|context| Box::pin(async move { context.read_coils(address, quantity).await }))
+            .await
+    }
+
+    pub async fn read_discrete_inputs(&mut self, address: u16, quantity: u16) -> Result<Vec<bool>, ModbusError> {
+        self.with_retries(|context| Box::pin(async move { context.read_discrete_inputs(address, quantity).await }))
+            .await
+    }
+
+    /// Reads the keepalive register and checks it against the expected value, if any.
+    pub async fn probe(&mut self, probe: &KeepaliveProbe) -> Result<(), ModbusError> {
+        probe.validate().map_err(|e| ModbusError::Config(format!("keepalive: {}", e)))?;
+        let first: Option<u16> = match probe.table {
+            ProbeTable::Holding => self.read_holding_registers(probe.address, probe.count).await?.first().copied(),
+            ProbeTable::Input => self.read_input_registers(probe.address, probe.count).await?.first().copied(),
+            ProbeTable::Coil => self.read_coils(probe.address, probe.count).await?.first().map(|&c| c as u16),
+            ProbeTable::DiscreteInput => self.read_discrete_inputs(probe.address, probe.count).await?.first().map(|&c| c as u16),
+        };
+        let first = first.ok_or_else(|| ModbusError::Protocol(format!("empty response reading keepalive register {}", probe.address)))?;
+
+        match probe.expected {
+            Some(expected) if first & probe.mask != expected & probe.mask => Err(ModbusError::Protocol(format!(
+                "keepalive register {} returned {:#06x}, expected {:#06x} (mask {:#06x})",
+                probe.address, first, expected, probe.mask
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn write_multiple_registers(&mut self, address: u16, words: &[u16]) -> Result<(), ModbusError> {
+        self.with_retries(|context| {
+            let words = words.to_vec();
+            Box::pin(async move { context.write_multiple_registers(address, &words).await })
+        })
+        .await
+    }
+
+    /// Reads an IEEE 754 float from two holding registers in word order `order`.
+    pub async fn read_float(&mut self, address: u16, order: WordOrder) -> Result<f32, ModbusError> {
+        let words = self.read_holding_registers(address, 2).await?;
+        order.decode(&words).ok_or_else(|| ModbusError::Protocol(format!("short response reading register {}", address)))
+    }
+
+    /// Writes an IEEE 754 float to two holding registers in word order `order`.
+    pub async fn write_float(&mut self, address: u16, value: f32, order: WordOrder) -> Result<(), ModbusError> {
+        self.write_multiple_registers(address, &order.encode(value)).await
+    }
+
+    /// Reads the text held in a string register.
+    pub async fn read_string(&mut self, register: &StringRegister) -> Result<String, ModbusError> {
+        let words = self.read_holding_registers(register.address, register.length).await?;
+        Ok(register.decode(&words))
+    }
+
+    /// Asks the meter for its vendor, product code and revision (function 43/14).
+    pub async fn read_device_identification(&mut self) -> Result<DeviceIdentification, ModbusError> {
+        let mut objects = BTreeMap::new();
+        let mut object_id = 0;
+        for _ in 0..MAX_DEVICE_ID_PARTS {
+            let data = self
+                .with_retries(|context| {
+                    Box::pin(async move {
+                        let pdu = vec![READ_DEVICE_ID, BASIC_DEVICE_ID, object_id];
+                        let response = context.call(tokio_modbus::Request::Custom(ENCAPSULATED_INTERFACE, Cow::Owned(pdu))).await?;
+                        Ok(response.map(|response| match response {
+                            tokio_modbus::Response::Custom(_, data) => data.to_vec(),
+                            _ => Vec::new(),
+                        }))
+                    })
+                })
+                .await?;
+            match parse_device_identification(&data, &mut objects)? {
+                Some(next) => object_id = next,
+                None => return Ok(DeviceIdentification::from_objects(&objects)),
+            }
+        }
+        Err(ModbusError::Protocol(format!("device identification not complete after {} responses", MAX_DEVICE_ID_PARTS)))
+    }
+
+    /// Runs `request` on the context with timeout, inter-frame delay and policy-driven retries.
+    pub async fn with_retries<T, F>(&mut self, mut request: F) -> Result<T, ModbusError>
+    where
+        F: for<'c> FnMut(&'c mut Context) -> Request<'c, T>,
+    {
+        let mut attempt = 0;
+        loop {
+            if let Some(next_frame_at) = self.next_frame_at {
+                sleep_until(next_frame_at).await;
+            }
+            let result = timeout(self.transaction.timeout(), request(&mut self.context)).await;
+            self.next_frame_at = Some(Instant::now() + self.transaction.inter_frame_delay());
+
+            let error: ModbusError = match result {
+                Ok(Ok(Ok(value))) => return Ok(value),
+                Ok(Ok(Err(exception))) => exception.into(),
+                Ok(Err(e)) => e.into(),
+                Err(_) => ModbusError::Timeout,
+            };
+            if self.policy.action_for(&error) != PolicyAction::Retry || attempt >= self.policy.max_retries {
+                return Err(error);
+            }
+            // The late answer to a request that timed out must not be taken for the retry's
+            let timed_out = matches!(error, ModbusError::Timeout);
+            if timed_out && self.reconnect.is_none() {
+                return Err(error);
+            }
+            attempt += 1;
+            warn!("Modbus transaction failed ({}), retry {} of {}", error, attempt, self.policy.max_retries);
+            tokio::time::sleep(self.policy.retry_delay()).await;
+            if let (true, Some(reconnect)) = (timed_out, self.reconnect.as_mut()) {
+                self.context = reconnect().await?;
+            }
+        }
+    }
+}
+
+/// Adds the objects of one Read Device Identification response to `objects` and returns the
+/// object id to continue with when the meter has more to send.
+fn parse_device_identification(data: &[u8], objects: &mut BTreeMap<u8, String>) -> Result<Option<u8>, ModbusError> {
+    let short = || ModbusError::Decode("short device identification response".to_string());
+    let [mei_type, _code, _conformity, more_follows, next_object_id, count, listed @ ..] = data else {
+        return Err(short());
+    };
+    if *mei_type != READ_DEVICE_ID {
+        return Err(ModbusError::Protocol(format!("unexpected MEI type {:#04x} in device identification", mei_type)));
+    }
+    let mut rest = listed;
+    for _ in 0..*count {
+        let [id, length, tail @ ..] = rest else {
+            return Err(short());
+        };
+        let value = tail.get(..*length as usize).ok_or_else(short)?;
+        objects.insert(*id, String::from_utf8_lossy(value).trim().to_string());
+        rest = &tail[value.len()..];
+    }
+    Ok((*more_follows == 0xFF).then_some(*next_object_id))
+}
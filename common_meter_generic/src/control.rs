@@ -0,0 +1,115 @@
+// common_meter_generic/src/control.rs
+
+use std::collections::HashMap;
+use std::fmt;
+use crate::reading::{Quality, Snapshot};
+use crate::settings::{ControlLoop, ControlSettings};
+
+/// Why a setpoint is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetpointKind {
+    /// A control step toward the target
+    Control,
+    /// The configured value, written when the measured value can no longer be trusted
+    Fallback,
+}
+
+/// A value to write to a write register.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setpoint {
+    pub write_register: String,
+    pub value: f32,
+    pub kind: SetpointKind,
+}
+
+impl fmt::Display for Setpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            SetpointKind::Control => write!(f, "Setpoint {} = {}", self.write_register, self.value),
+            SetpointKind::Fallback => write!(f, "Setpoint {} = {} (fallback)", self.write_register, self.value),
+        }
+    }
+}
+
+/// Computes setpoints for the control loops from the read cycles.
+///
+/// A loop is cut off as soon as its measured value is not a good reading, and its write
+/// register goes back to the configured value. It resumes from there with the next good one.
+#[derive(Debug)]
+pub struct Controller {
+    settings: ControlSettings,
+    /// Last setpoint per write register; none while the loop is cut off or has not started
+    setpoints: HashMap<String, f32>,
+    cut_off: HashMap<String, bool>,
+}
+
+impl Controller {
+    pub fn new(settings: ControlSettings) -> Self {
+        Controller { settings, setpoints: HashMap::new(), cut_off: HashMap::new() }
+    }
+
+    /// Runs one control step on `snapshot` and returns the setpoints that changed.
+    ///
+    /// `configured` looks up the value configured for a write register.
+    pub fn update(&mut self, snapshot: &Snapshot, configured: &dyn Fn(&str) -> Option<f32>) -> Vec<Setpoint> {
+        let mut setpoints = Vec::new();
+        for control in &self.settings.loops {
+            let measured = snapshot
+                .get(&control.measured)
+                .filter(|reading| reading.quality == Quality::Good)
+                .and_then(|reading| reading.value);
+            let Some(measured) = measured else {
+                setpoints.extend(cut_off(control, &mut self.setpoints, &mut self.cut_off, configured));
+                continue;
+            };
+            self.cut_off.insert(control.write_register.clone(), false);
+
+            let Some(current) = self.setpoints.get(&control.write_register).copied().or_else(|| configured(&control.write_register)) else {
+                continue;
+            };
+            let error = control.target - measured;
+            if error.abs() <= control.deadband {
+                continue;
+            }
+            let step = control.max_step.map_or(control.gain * error, |max_step| (control.gain * error).clamp(-max_step, max_step));
+            let mut value = current + step;
+            if let Some(max) = control.max {
+                value = value.min(max);
+            }
+            if let Some(min) = control.min {
+                value = value.max(min);
+            }
+            if !value.is_finite() || value == current {
+                continue;
+            }
+            self.setpoints.insert(control.write_register.clone(), value);
+            setpoints.push(Setpoint { write_register: control.write_register.clone(), value, kind: SetpointKind::Control });
+        }
+        setpoints
+    }
+
+    /// Cuts off every loop, for when the read cycles stopped arriving.
+    pub fn cut_off(&mut self, configured: &dyn Fn(&str) -> Option<f32>) -> Vec<Setpoint> {
+        self.settings
+            .loops
+            .iter()
+            .filter_map(|control| cut_off(control, &mut self.setpoints, &mut self.cut_off, configured))
+            .collect()
+    }
+}
+
+/// Returns the fallback setpoint of `control` unless it is cut off already.
+fn cut_off(
+    control: &ControlLoop,
+    setpoints: &mut HashMap<String, f32>,
+    cut_off: &mut HashMap<String, bool>,
+    configured: &dyn Fn(&str) -> Option<f32>,
+) -> Option<Setpoint> {
+    if cut_off.insert(control.write_register.clone(), true) == Some(true) {
+        return None;
+    }
+    // Nothing to undo before the first control step
+    setpoints.remove(&control.write_register)?;
+    let value = configured(&control.write_register)?;
+    Some(Setpoint { write_register: control.write_register.clone(), value, kind: SetpointKind::Fallback })
+}
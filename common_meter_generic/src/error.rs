@@ -0,0 +1,84 @@
+// common_meter_generic/src/error.rs
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use tokio_modbus::Exception;
+
+/// Error type shared by the state machines and the command-line tools.
+#[derive(Debug)]
+pub enum ModbusError {
+    /// A connection attempt or transaction did not finish in time.
+    Timeout,
+    /// The TCP connection could not be established.
+    ConnectionFailed(String),
+    /// No Modbus context is available.
+    NotConnected,
+    /// The meter answered with a Modbus exception response.
+    Exception(Exception),
+    /// The underlying transport failed (socket closed, reset, ...).
+    Transport(io::Error),
+    /// The response did not match the request.
+    Protocol(String),
+    /// The response could not be decoded into register values.
+    Decode(String),
+    /// The configuration does not allow the operation.
+    Config(String),
+}
+
+impl ModbusError {
+    /// Returns the exception code if the meter answered with an exception response.
+    pub fn exception(&self) -> Option<Exception> {
+        match self {
+            ModbusError::Exception(code) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ModbusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModbusError::Timeout => write!(f, "Operation timed out"),
+            ModbusError::ConnectionFailed(msg) => write!(f, "Failed to connect: {}", msg),
+            ModbusError::NotConnected => write!(f, "Modbus context not available"),
+            ModbusError::Exception(code) => write!(f, "Modbus exception: {:?}", code),
+            ModbusError::Transport(e) => write!(f, "Transport error: {}", e),
+            ModbusError::Protocol(msg) => write!(f, "Protocol error: {}", msg),
+            ModbusError::Decode(msg) => write!(f, "Decode error: {}", msg),
+            ModbusError::Config(msg) => write!(f, "Configuration error: {}", msg),
+        }
+    }
+}
+
+impl Error for ModbusError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ModbusError::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Exception> for ModbusError {
+    fn from(code: Exception) -> Self {
+        ModbusError::Exception(code)
+    }
+}
+
+impl From<io::Error> for ModbusError {
+    fn from(e: io::Error) -> Self {
+        ModbusError::Transport(e)
+    }
+}
+
+impl From<tokio_modbus::Error> for ModbusError {
+    fn from(e: tokio_modbus::Error) -> Self {
+        match e {
+            // What the bus scheduler reports for a slave that did not answer
+            tokio_modbus::Error::Transport(e) if e.kind() == io::ErrorKind::TimedOut => ModbusError::Timeout,
+            tokio_modbus::Error::Transport(e) => ModbusError::Transport(e),
+            tokio_modbus::Error::Protocol(e) => ModbusError::Protocol(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// common_meter_generic/src/expression.rs
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Arithmetic over register names, e.g. `sqrt(active^2 + reactive^2)`.
+///
+/// Supports numbers, names (letters, digits and `_`), `+ - * / ^`, parentheses and the
+/// functions `sqrt`, `abs`, `min`, `max` and `avg`; `min`, `max` and `avg` take any number
+/// of arguments. `^` binds tighter than unary minus and is right-associative.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    Avg,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sqrt" => Some(Function::Sqrt),
+            "abs" => Some(Function::Abs),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "avg" => Some(Function::Avg),
+            _ => None,
+        }
+    }
+
+    /// Whether the function accepts `count` arguments.
+    fn accepts(self, count: usize) -> bool {
+        match self {
+            Function::Sqrt | Function::Abs => count == 1,
+            Function::Min | Function::Max | Function::Avg => count >= 1,
+        }
+    }
+}
+
+/// Where and why an expression could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpressionError {
+    /// Byte offset into the expression
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let mut parser = Parser { source, position: 0 };
+        let root = parser.expression()?;
+        parser.skip_whitespace();
+        if parser.position < source.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(Expression { source: source.to_string(), root })
+    }
+
+    /// Names the expression reads, in order of appearance.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.root.collect_variables(&mut names);
+        names
+    }
+
+    /// Evaluates the expression; `None` when `lookup` has no value for one of the names.
+    pub fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        self.root.evaluate(lookup)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl TryFrom<String> for Expression {
+    type Error = ExpressionError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Expression::parse(&source)
+    }
+}
+
+impl From<Expression> for String {
+    fn from(expression: Expression) -> Self {
+        expression.source
+    }
+}
+
+impl Node {
+    fn collect_variables<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Node::Number(_) => {}
+            Node::Variable(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            Node::Negate(operand) => operand.collect_variables(names),
+            Node::Binary(_, left, right) => {
+                left.collect_variables(names);
+                right.collect_variables(names);
+            }
+            Node::Call(_, arguments) => arguments.iter().for_each(|argument| argument.collect_variables(names)),
+        }
+    }
+
+    fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        Some(match self {
+            Node::Number(value) => *value,
+            Node::Variable(name) => lookup(name)?,
+            Node::Negate(operand) => -operand.evaluate(lookup)?,
+            Node::Binary(operator, left, right) => {
+                let (left, right) = (left.evaluate(lookup)?, right.evaluate(lookup)?);
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                    Operator::Power => left.powf(right),
+                }
+            }
+            Node::Call(function, arguments) => {
+                let values = arguments.iter().map(|argument| argument.evaluate(lookup)).collect::<Option<Vec<f64>>>()?;
+                match function {
+                    Function::Sqrt => values[0].sqrt(),
+                    Function::Abs => values[0].abs(),
+                    Function::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+                    Function::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                    Function::Avg => values.iter().sum::<f64>() / values.len() as f64,
+                }
+            }
+        })
+    }
+}
+
+struct Parser<'s> {
+    source: &'s str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ExpressionError {
+        ExpressionError { position: self.position, message: message.to_string() }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.source[self.position..].chars().next()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes the longest run of characters matching `accept`.
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> &str {
+        let start = self.position;
+        let length = self.source[start..].find(|c| !accept(c)).unwrap_or(self.source.len() - start);
+        self.position += length;
+        &self.source[start..self.position]
+    }
+
+    fn expression(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.term()?;
+        loop {
+            let operator = if self.eat('+') {
+                Operator::Add
+            } else if self.eat('-') {
+                Operator::Subtract
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(operator, Box::new(node), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.unary()?;
+        loop {
+            let operator = if self.eat('*') {
+                Operator::Multiply
+            } else if self.eat('/') {
+                Operator::Divide
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(operator, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, ExpressionError> {
+        if self.eat('-') {
+            return Ok(Node::Negate(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.eat('^') {
+            return Ok(Node::Binary(Operator::Power, Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Node, ExpressionError> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let node = self.expression()?;
+                if !self.eat(')') {
+                    return Err(self.error("expected `)`"));
+                }
+                Ok(node)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.position;
+                let number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                number.parse().map(Node::Number).map_err(|_| ExpressionError { position: start, message: format!("invalid number `{}`", number) })
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.position;
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_').to_string();
+                if !self.eat('(') {
+                    return Ok(Node::Variable(name));
+                }
+                let function = Function::from_name(&name)
+                    .ok_or_else(|| ExpressionError { position: start, message: format!("unknown function `{}`", name) })?;
+                let mut arguments = vec![self.expression()?];
+                while self.eat(',') {
+                    arguments.push(self.expression()?);
+                }
+                if !self.eat(')') {
+                    return Err(self.error("expected `,` or `)`"));
+                }
+                if !function.accepts(arguments.len()) {
+                    return Err(ExpressionError { position: start, message: format!("wrong number of arguments for `{}`", name) });
+                }
+                Ok(Node::Call(function, arguments))
+            }
+            Some(_) => Err(self.error("expected a number, a name or `(`")),
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+}
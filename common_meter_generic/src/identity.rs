@@ -0,0 +1,80 @@
+// common_meter_generic/src/identity.rs
+
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::Serialize;
+use log::warn;
+use crate::client::MeterClient;
+use crate::settings::StringRegister;
+
+/// What a meter tells about itself, such as serial number and firmware version, keyed by the
+/// names of the profile's identity registers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Identity(pub BTreeMap<String, String>);
+
+impl Identity {
+    /// Reads every identity register of a profile. Registers that cannot be read are left out,
+    /// so a meter without some of them still connects.
+    pub async fn read(client: &mut MeterClient, registers: &BTreeMap<String, StringRegister>) -> Identity {
+        let mut identity = Identity::default();
+        for (name, register) in registers {
+            match client.read_string(register).await {
+                Ok(text) => {
+                    identity.0.insert(name.clone(), text);
+                }
+                Err(e) => warn!("Failed to read identity register {} at {}: {}", name, register.address, e),
+            }
+        }
+        identity
+    }
+
+    /// Adds vendor, product code and revision from Read Device Identification.
+    pub fn insert_device(&mut self, device: &DeviceIdentification) {
+        self.0.insert("vendor".to_string(), device.vendor.clone());
+        self.0.insert("product_code".to_string(), device.product_code.clone());
+        self.0.insert("revision".to_string(), device.revision.clone());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, text)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", name, text)?;
+        }
+        Ok(())
+    }
+}
+
+/// The basic objects of Read Device Identification (function 43, MEI type 14).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceIdentification {
+    pub vendor: String,
+    pub product_code: String,
+    pub revision: String,
+}
+
+impl DeviceIdentification {
+    /// Picks the basic objects 0 to 2 out of the objects a meter returned, keyed by object id.
+    pub fn from_objects(objects: &BTreeMap<u8, String>) -> Self {
+        let object = |id: u8| objects.get(&id).cloned().unwrap_or_default();
+        DeviceIdentification { vendor: object(0), product_code: object(1), revision: object(2) }
+    }
+}
+
+impl fmt::Display for DeviceIdentification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} (revision {})", self.vendor, self.product_code, self.revision)
+    }
+}
@@ -0,0 +1,253 @@
+// common_meter_generic/src/information_model.rs
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::SystemTime;
+use serde::Serialize;
+use crate::reading::{Decoded, Quality, Snapshot};
+use crate::settings::{MeterProfile, OpcUaSettings, RegisterFormat};
+
+/// Namespace index of the gateway's nodes; 0 is the OPC UA namespace itself and 1 the server's own.
+const NAMESPACE_INDEX: u16 = 2;
+
+/// Browse name of the variable holding the state of the Modbus connection.
+pub const CONNECTION_STATE: &str = "ConnectionState";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeClass {
+    Object,
+    Variable,
+}
+
+/// Type of a variable's value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataType {
+    Float,
+    String,
+}
+
+/// OPC UA status of a variable's value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum StatusCode {
+    Good,
+    /// Not read this cycle; the value is the last good one.
+    UncertainLastUsableValue,
+    /// Read, but outside the register's range.
+    UncertainEngineeringUnitsExceeded,
+    BadCommunicationError,
+    /// Nothing read since the gateway started.
+    BadWaitingForInitialData,
+}
+
+impl StatusCode {
+    /// The numeric code from the OPC UA specification, part 6.
+    pub fn code(self) -> u32 {
+        match self {
+            StatusCode::Good => 0x0000_0000,
+            StatusCode::UncertainLastUsableValue => 0x4090_0000,
+            StatusCode::UncertainEngineeringUnitsExceeded => 0x4094_0000,
+            StatusCode::BadCommunicationError => 0x8005_0000,
+            StatusCode::BadWaitingForInitialData => 0x8032_0000,
+        }
+    }
+}
+
+impl From<Quality> for StatusCode {
+    fn from(quality: Quality) -> Self {
+        match quality {
+            Quality::Good => StatusCode::Good,
+            Quality::Stale => StatusCode::UncertainLastUsableValue,
+            Quality::OutOfRange => StatusCode::UncertainEngineeringUnitsExceeded,
+            Quality::CommError => StatusCode::BadCommunicationError,
+        }
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Variant {
+    Float(f32),
+    String(String),
+}
+
+/// Value of a variable with its status and when the meter was read.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DataValue {
+    pub value: Option<Variant>,
+    pub status: StatusCode,
+    pub source_timestamp: Option<SystemTime>,
+}
+
+impl DataValue {
+    fn waiting() -> Self {
+        DataValue { value: None, status: StatusCode::BadWaitingForInitialData, source_timestamp: None }
+    }
+}
+
+/// Range of the values a variable is expected to take, from the register's `min` and `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EuRange {
+    pub low: f32,
+    pub high: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Node {
+    /// String node id, e.g. `ns=2;s=EEM-MA370.voltage_L1_N`
+    pub node_id: String,
+    pub browse_name: String,
+    pub class: NodeClass,
+    /// Type of a variable's value: text for the connection state and string registers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<DataType>,
+    /// Node id of the object the node is a component of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Engineering units property
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// EURange property
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<EuRange>,
+    /// Whether clients may write the value, which goes to the write register of that name
+    pub writable: bool,
+    /// Modbus address of the register behind a variable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<u16>,
+}
+
+/// The OPC UA address space of one meter: an object with a variable per read register,
+/// carrying the register's unit and range, writable variables for the write registers, and
+/// the connection state. Values follow the snapshots of the read state machine; the protocol
+/// binding serves the nodes and hands writes to [`InformationModel::write_target`].
+#[derive(Debug, Clone)]
+pub struct InformationModel {
+    namespace_uri: String,
+    nodes: Vec<Node>,
+    values: HashMap<String, DataValue>,
+}
+
+impl InformationModel {
+    /// Builds the nodes of a meter of `meter_type` from its register maps, given as names and
+    /// addresses. A register both read and written is one writable variable.
+    pub fn new(settings: &OpcUaSettings, meter_type: &str, profile: &MeterProfile, read: &[(&str, u16)], write: &[(&str, u16)]) -> Self {
+        let object = settings.object_name.as_deref().unwrap_or(meter_type);
+        let object_id = node_id(object);
+        let mut nodes = vec![Node {
+            node_id: object_id.clone(),
+            browse_name: object.to_string(),
+            class: NodeClass::Object,
+            data_type: None,
+            parent: None,
+            unit: None,
+            range: None,
+            writable: false,
+            address: None,
+        }];
+        let variable = |name: &str, address: Option<u16>, writable: bool| {
+            let info = profile.register(name);
+            let text = name == CONNECTION_STATE || info.format == RegisterFormat::String;
+            Node {
+                node_id: node_id(&format!("{}.{}", object, name)),
+                browse_name: name.to_string(),
+                class: NodeClass::Variable,
+                data_type: Some(if text { DataType::String } else { DataType::Float }),
+                parent: Some(object_id.clone()),
+                unit: info.unit.clone(),
+                range: info.min.zip(info.max).map(|(low, high)| EuRange { low, high }),
+                writable,
+                address,
+            }
+        };
+        nodes.push(variable(CONNECTION_STATE, None, false));
+        for &(name, address) in read {
+            let writable = write.iter().any(|&(written, _)| written == name);
+            nodes.push(variable(name, Some(address), writable));
+        }
+        for &(name, address) in write {
+            if !read.iter().any(|&(r, _)| r == name) {
+                nodes.push(variable(name, Some(address), true));
+            }
+        }
+
+        let values = nodes
+            .iter()
+            .filter(|node| node.class == NodeClass::Variable)
+            .map(|node| (node.node_id.clone(), DataValue::waiting()))
+            .collect();
+        InformationModel { namespace_uri: settings.namespace_uri.clone(), nodes, values }
+    }
+
+    pub fn namespace_uri(&self) -> &str {
+        &self.namespace_uri
+    }
+
+    /// The object first, then its variables in the order of the register maps.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn node(&self, node_id: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.node_id == node_id)
+    }
+
+    fn variable(&self, browse_name: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.class == NodeClass::Variable && node.browse_name == browse_name)
+    }
+
+    pub fn value(&self, node_id: &str) -> Option<&DataValue> {
+        self.values.get(node_id)
+    }
+
+    /// Node id of the connection state variable.
+    pub fn connection_state(&self) -> &str {
+        &self.nodes[1].node_id
+    }
+
+    /// Takes the values of a read cycle, with their quality as the status.
+    pub fn update(&mut self, snapshot: &Snapshot) {
+        for reading in &snapshot.readings {
+            let Some(node) = self.variable(&reading.name) else {
+                continue;
+            };
+            let text = match &reading.decoded {
+                Some(Decoded::Text(text)) => Some(Variant::String(text.clone())),
+                _ => None,
+            };
+            let data = DataValue {
+                value: reading.value.map(Variant::Float).or(text),
+                status: reading.quality.into(),
+                source_timestamp: Some(reading.timestamp),
+            };
+            self.values.insert(node.node_id.clone(), data);
+        }
+    }
+
+    /// Sets the connection state variable, e.g. to the state of the Modbus state machine.
+    pub fn set_connection_state(&mut self, state: &str, at: SystemTime) {
+        let data = DataValue { value: Some(Variant::String(state.to_string())), status: StatusCode::Good, source_timestamp: Some(at) };
+        self.values.insert(self.connection_state().to_string(), data);
+    }
+
+    /// The write register a client's write to `node_id` goes to, as name and address. The
+    /// value still has to pass the `WriteGuard` of the write path.
+    pub fn write_target(&self, node_id: &str) -> Result<(&str, u16), String> {
+        let node = self.node(node_id).ok_or_else(|| format!("unknown node {}", node_id))?;
+        match (node.writable, node.address) {
+            (true, Some(address)) => Ok((&node.browse_name, address)),
+            _ => Err(format!("{} is not writable", node_id)),
+        }
+    }
+}
+
+fn node_id(identifier: &str) -> String {
+    format!("ns={};s={}", NAMESPACE_INDEX, identifier)
+}
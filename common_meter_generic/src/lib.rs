@@ -0,0 +1,30 @@
+pub mod address;
+pub mod aggregation;
+pub mod alarms;
+pub mod bus;
+pub mod client;
+pub mod control;
+pub mod error;
+pub mod expression;
+pub mod identity;
+pub mod information_model;
+pub mod reading;
+pub mod reconcile;
+pub mod settings;
+pub mod tls;
+pub mod writes;
+
+pub use address::Endpoint;
+pub use aggregation::{Aggregate, Aggregator};
+pub use alarms::{ActiveAlarm, AlarmEngine, AlarmEvent, AlarmTransition};
+pub use bus::Bus;
+pub use client::MeterClient;
+pub use control::{Controller, Setpoint, SetpointKind};
+pub use error::ModbusError;
+pub use expression::Expression;
+pub use identity::{DeviceIdentification, Identity};
+pub use information_model::InformationModel;
+pub use reading::{Decoded, Quality, Reading, Snapshot};
+pub use reconcile::{Drift, Reconciler};
+pub use settings::Settings;
+pub use writes::{AuditRecord, WriteError, WriteGuard, WriteOutcome};
@@ -0,0 +1,243 @@
+// common_meter_generic/src/reading.rs
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+use serde::Serialize;
+use crate::identity::Identity;
+use crate::settings::{DerivedChannel, RegisterFormat, RegisterInfo};
+
+/// How far a reading can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quality {
+    /// Read this cycle and within the register's range.
+    Good,
+    /// Not read this cycle; the value is the last good one.
+    Stale,
+    /// Not read this cycle and no earlier value to fall back on.
+    CommError,
+    /// Read this cycle, but outside the register's range or not a number.
+    OutOfRange,
+}
+
+impl Quality {
+    /// Orders qualities from good to no value at all.
+    fn severity(self) -> u8 {
+        match self {
+            Quality::Good => 0,
+            Quality::Stale => 1,
+            Quality::OutOfRange => 2,
+            Quality::CommError => 3,
+        }
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Quality::Good => "good",
+            Quality::Stale => "stale",
+            Quality::CommError => "comm-error",
+            Quality::OutOfRange => "out-of-range",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Structured meaning of a status or enumeration register.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decoded {
+    /// Names of the bits set; unnamed bits as `bit<n>`
+    Flags(Vec<String>),
+    /// Label of the value; `None` for a value the enumeration does not list
+    Label(Option<String>),
+    /// Text of a `string` register
+    Text(String),
+}
+
+impl Decoded {
+    /// Decodes `raw` as the text of a string register or against the bits or enumeration of
+    /// `info`; `None` if it defines none of them.
+    pub fn new(info: &RegisterInfo, raw: &[u16]) -> Option<Self> {
+        if info.format == RegisterFormat::String {
+            return info.text(raw).map(Decoded::Text);
+        }
+        if info.bits.is_empty() && info.labels.is_empty() || raw.len() < info.words() {
+            return None;
+        }
+        let word = info.format.integer(raw);
+        if !info.labels.is_empty() {
+            return Some(Decoded::Label(word.and_then(|word| info.labels.get(&word).cloned())));
+        }
+        let word = word?;
+        let flags = (0..32u8)
+            .filter(|bit| word & 1 << bit != 0)
+            .map(|bit| info.bits.get(&bit).cloned().unwrap_or_else(|| format!("bit{}", bit)))
+            .collect();
+        Some(Decoded::Flags(flags))
+    }
+}
+
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decoded::Flags(flags) => write!(f, "[{}]", flags.join(", ")),
+            Decoded::Label(Some(label)) => f.write_str(label),
+            Decoded::Label(None) => f.write_str("unknown"),
+            Decoded::Text(text) => write!(f, "{:?}", text),
+        }
+    }
+}
+
+/// One register of one meter at one point in time.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reading {
+    pub meter_id: String,
+    pub name: String,
+    pub address: u16,
+    /// Words as they came from the meter; empty without a value
+    pub raw: Vec<u16>,
+    /// The raw words decoded as the register's format says
+    pub value: Option<f32>,
+    /// Flags or enumeration label, for registers that define them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<Decoded>,
+    pub unit: Option<String>,
+    /// When the request carrying this register was sent
+    pub timestamp: SystemTime,
+    /// Round trip of that request, retries included
+    pub latency: Duration,
+    pub quality: Quality,
+}
+
+impl Reading {
+    /// Decodes the words of a register as `info.format` says and grades the value against
+    /// `info`. An enumeration value without a label is out of range; a string has no value but
+    /// its text, and is good once decoded.
+    pub fn decode(meter_id: &str, name: &str, address: u16, raw: &[u16], info: &RegisterInfo, timestamp: SystemTime, latency: Duration) -> Self {
+        let raw = &raw[..raw.len().min(info.words())];
+        let value = info.format.decode(raw);
+        let decoded = Decoded::new(info, raw);
+        let quality = match value {
+            Some(_) if matches!(decoded, Some(Decoded::Label(None))) => Quality::OutOfRange,
+            Some(value) if info.contains(value) => Quality::Good,
+            Some(_) => Quality::OutOfRange,
+            None if matches!(decoded, Some(Decoded::Text(_))) => Quality::Good,
+            None => Quality::CommError,
+        };
+        Reading {
+            meter_id: meter_id.to_string(),
+            name: name.to_string(),
+            address,
+            raw: if quality != Quality::CommError { raw.to_vec() } else { Vec::new() },
+            value,
+            decoded,
+            unit: info.unit.clone(),
+            timestamp,
+            latency,
+            quality,
+        }
+    }
+
+    /// Stands in for a register that could not be read: the last good reading marked stale,
+    /// or an empty comm-error reading.
+    pub fn missing(meter_id: &str, name: &str, address: u16, info: &RegisterInfo, last: Option<&Reading>) -> Self {
+        match last {
+            Some(last) if matches!(last.quality, Quality::Good | Quality::Stale) => Reading { quality: Quality::Stale, ..last.clone() },
+            _ => Reading {
+                meter_id: meter_id.to_string(),
+                name: name.to_string(),
+                address,
+                raw: Vec::new(),
+                value: None,
+                decoded: None,
+                unit: info.unit.clone(),
+                timestamp: SystemTime::now(),
+                latency: Duration::ZERO,
+                quality: Quality::CommError,
+            },
+        }
+    }
+
+    /// Evaluates a derived channel over `readings`. The result is as good as its worst input:
+    /// a comm error without value when an input has none, otherwise out of range or stale when
+    /// an input is, and out of range when it falls outside the channel's own range.
+    ///
+    /// Derived readings have no raw words and address 0.
+    pub fn derive(meter_id: &str, channel: &DerivedChannel, readings: &[Reading]) -> Self {
+        let inputs: Vec<&Reading> = channel
+            .expression
+            .variables()
+            .into_iter()
+            .filter_map(|name| readings.iter().find(|reading| reading.name == name))
+            .collect();
+        let value = channel.expression.evaluate(&|name| {
+            readings.iter().find(|reading| reading.name == name).and_then(|reading| reading.value).map(f64::from)
+        });
+
+        let input_quality = inputs.iter().map(|input| input.quality).max_by_key(|quality| quality.severity()).unwrap_or(Quality::Good);
+        let (value, quality) = match value.map(|value| value as f32) {
+            None => (None, Quality::CommError),
+            Some(value) if !channel.info.contains(value) => (Some(value), Quality::OutOfRange),
+            Some(value) => (Some(value), input_quality),
+        };
+        Reading {
+            meter_id: meter_id.to_string(),
+            name: channel.name.clone(),
+            address: 0,
+            raw: Vec::new(),
+            value,
+            decoded: None,
+            unit: channel.info.unit.clone(),
+            timestamp: inputs.iter().map(|input| input.timestamp).max().unwrap_or_else(SystemTime::now),
+            latency: inputs.iter().map(|input| input.latency).max().unwrap_or_default(),
+            quality,
+        }
+    }
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Name: {}, Address: {}, Value: ", self.name, self.address)?;
+        match self.value {
+            Some(value) => write!(f, "{}", value)?,
+            None => f.write_str("-")?,
+        }
+        if let Some(decoded) = &self.decoded {
+            write!(f, " {}", decoded)?;
+        }
+        if let Some(unit) = &self.unit {
+            write!(f, " {}", unit)?;
+        }
+        write!(f, " ({})", self.quality)
+    }
+}
+
+/// Every configured register of a meter after one read cycle.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Snapshot {
+    pub meter_id: String,
+    /// When the cycle started
+    pub timestamp: SystemTime,
+    /// Duration of the whole cycle
+    pub latency: Duration,
+    /// Identity the meter reported on the current connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<Identity>,
+    /// Path the meter was read on, for meters with redundant paths
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub readings: Vec<Reading>,
+}
+
+impl Snapshot {
+    pub fn get(&self, name: &str) -> Option<&Reading> {
+        self.readings.iter().find(|reading| reading.name == name)
+    }
+
+    /// Whether every register was read fresh and in range.
+    pub fn is_good(&self) -> bool {
+        self.readings.iter().all(|reading| reading.quality == Quality::Good)
+    }
+}
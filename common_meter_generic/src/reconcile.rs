@@ -0,0 +1,107 @@
+// common_meter_generic/src/reconcile.rs
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use log::warn;
+use crate::settings::WriteSettings;
+
+/// Drift events a subscriber may fall behind before it misses some.
+const DRIFT_CAPACITY: usize = 16;
+
+/// A write register found changed on the meter after it had been confirmed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drift {
+    pub name: String,
+    pub address: u16,
+    /// Value written and confirmed before
+    pub expected: f32,
+    /// Value read back from the meter
+    pub actual: f32,
+    pub at: SystemTime,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {} drifted from {} to {}", self.name, self.address, self.expected, self.actual)
+    }
+}
+
+/// Decides which write registers need writing: only values that differ on the meter are
+/// written, and confirmed ones are read back again every `recheck_secs` to catch drift.
+#[derive(Debug)]
+pub struct Reconciler {
+    tolerance: f32,
+    recheck: Duration,
+    /// Value confirmed on the meter per register, and when
+    confirmed: HashMap<String, (f32, Instant)>,
+    drift: broadcast::Sender<Drift>,
+}
+
+impl Default for Reconciler {
+    fn default() -> Self {
+        Self::new(&WriteSettings::default())
+    }
+}
+
+impl Reconciler {
+    pub fn new(settings: &WriteSettings) -> Self {
+        Reconciler {
+            tolerance: settings.tolerance,
+            recheck: settings.recheck(),
+            confirmed: HashMap::new(),
+            drift: broadcast::channel(DRIFT_CAPACITY).0,
+        }
+    }
+
+    /// Picks up tolerance and recheck changes from a reloaded configuration.
+    pub fn configure(&mut self, settings: &WriteSettings) {
+        self.tolerance = settings.tolerance;
+        self.recheck = settings.recheck();
+    }
+
+    /// Drift reports from now on.
+    pub fn subscribe_drift(&self) -> broadcast::Receiver<Drift> {
+        self.drift.subscribe()
+    }
+
+    /// Whether register `name` has to be read back: its value changed or the recheck is due.
+    pub fn is_due(&self, name: &str, value: f32, now: Instant) -> bool {
+        match self.confirmed.get(name) {
+            Some(&(confirmed, at)) => !self.matches(confirmed, value) || now.duration_since(at) >= self.recheck,
+            None => true,
+        }
+    }
+
+    /// Compares the value read back (`None` if it could not be read) with `value` and returns
+    /// whether it has to be written. A confirmed value that changed on the meter is reported
+    /// as drift.
+    pub fn needs_write(&mut self, name: &str, address: u16, value: f32, current: Option<f32>, now: Instant) -> bool {
+        let Some(current) = current else {
+            return true;
+        };
+        if self.matches(current, value) {
+            self.confirm(name, value, now);
+            return false;
+        }
+        if let Some(&(expected, _)) = self.confirmed.get(name).filter(|&&(confirmed, _)| self.matches(confirmed, value)) {
+            let drift = Drift { name: name.to_string(), address, expected, actual: current, at: SystemTime::now() };
+            warn!("{}", drift);
+            // Nobody listening is fine
+            let _ = self.drift.send(drift);
+        }
+        self.confirmed.remove(name);
+        true
+    }
+
+    /// Records that the meter holds `value` in register `name`.
+    pub fn confirm(&mut self, name: &str, value: f32, now: Instant) {
+        self.confirmed.insert(name.to_string(), (value, now));
+    }
+
+    fn matches(&self, a: f32, b: f32) -> bool {
+        (a - b).abs() <= self.tolerance
+    }
+}
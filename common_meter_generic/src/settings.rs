@@ -0,0 +1,931 @@
+// common_meter_generic/src/settings.rs
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio_modbus::Exception;
+use anyhow::{Context, Result};
+use crate::error::ModbusError;
+use crate::expression::Expression;
+use crate::identity::DeviceIdentification;
+
+/// Gateway settings read from the same YAML file as `config_meter_generic::config::Config`.
+///
+/// `Config` ignores sections it does not know, so everything here is optional and falls back
+/// to defaults when the section is missing.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub modbus: ModbusSettings,
+    /// Meter profiles keyed by `meter_data.meter_type`.
+    pub profiles: HashMap<String, MeterProfile>,
+    /// Virtual registers computed after every read cycle, in this order.
+    pub derived: Vec<DerivedChannel>,
+    pub aggregation: AggregationSettings,
+    pub alarms: AlarmSettings,
+    pub control: ControlSettings,
+    pub writes: WriteSettings,
+    pub opcua: OpcUaSettings,
+}
+
+/// Interval statistics computed from the read cycles.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AggregationSettings {
+    /// Interval lengths, each aligned to multiples of itself since the Unix epoch (UTC).
+    pub intervals_secs: Vec<u64>,
+    /// Read and derived registers to aggregate.
+    pub registers: Vec<String>,
+    /// Power registers in W among `registers` to integrate to kWh.
+    pub energy: Vec<String>,
+    /// Longest pause between two good readings still counted as covered.
+    pub max_gap_secs: u64,
+    /// Appends one JSON line per aggregate to this file.
+    pub file: Option<String>,
+}
+
+impl Default for AggregationSettings {
+    fn default() -> Self {
+        AggregationSettings {
+            intervals_secs: vec![15 * 60, 60 * 60, 24 * 60 * 60],
+            registers: Vec::new(),
+            energy: Vec::new(),
+            max_gap_secs: 30,
+            file: None,
+        }
+    }
+}
+
+impl AggregationSettings {
+    /// Rejects interval lengths of 0, which have no interval to align to.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.intervals_secs.contains(&0) {
+            return Err("intervals must be longer than 0 seconds".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Limit rules evaluated against every read cycle.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AlarmSettings {
+    pub rules: Vec<AlarmRule>,
+    /// Keeps the active alarms in this JSON file for `mgw_generic alarms`.
+    pub active_file: Option<String>,
+    /// Appends one JSON line per raised or cleared alarm to this file.
+    pub events_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+/// A threshold: a fixed value or the value configured for a write register.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Limit {
+    Value(f32),
+    WriteRegister { write_register: String },
+}
+
+/// Raises an alarm while a register stays outside its limits.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlarmRule {
+    pub name: String,
+    /// Read or derived register to watch.
+    pub register: String,
+    #[serde(default)]
+    pub severity: Severity,
+    /// Alarm above this value.
+    #[serde(default)]
+    pub high: Option<Limit>,
+    /// Alarm below this value.
+    #[serde(default)]
+    pub low: Option<Limit>,
+    /// With `tolerance_percent`, sets `high` and `low` where they are not given.
+    #[serde(default)]
+    pub nominal: Option<f32>,
+    #[serde(default)]
+    pub tolerance_percent: Option<f32>,
+    /// How far back inside the limits the value must come before the alarm clears.
+    #[serde(default)]
+    pub hysteresis: f32,
+    /// How long the value must stay outside the limits before the alarm is raised.
+    #[serde(default)]
+    pub min_duration_secs: u64,
+}
+
+/// Safety rails applied to every write to the meter, and how configured values are kept.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WriteSettings {
+    /// Logs and audits writes without sending them.
+    pub dry_run: bool,
+    /// Allowed range per write register, keyed by name.
+    pub limits: HashMap<String, RegisterInfo>,
+    /// Appends one JSON line per write to this file.
+    pub audit_file: Option<String>,
+    /// Largest difference between the configured and the meter's value still left alone.
+    pub tolerance: f32,
+    /// Seconds after which a confirmed value is read back again to detect drift.
+    pub recheck_secs: u64,
+    /// Word order of the float in each write register of the write state machines, keyed by
+    /// name; `low_first` when not listed.
+    pub word_order: HashMap<String, WordOrder>,
+}
+
+impl Default for WriteSettings {
+    fn default() -> Self {
+        WriteSettings {
+            dry_run: false,
+            limits: HashMap::new(),
+            audit_file: None,
+            tolerance: 0.0,
+            recheck_secs: 300,
+            word_order: HashMap::new(),
+        }
+    }
+}
+
+impl WriteSettings {
+    pub fn recheck(&self) -> Duration {
+        Duration::from_secs(self.recheck_secs)
+    }
+
+    pub fn word_order(&self, name: &str) -> WordOrder {
+        self.word_order.get(name).copied().unwrap_or_default()
+    }
+}
+
+/// How the meter appears in the OPC UA address space.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OpcUaSettings {
+    /// Namespace of the gateway's nodes.
+    pub namespace_uri: String,
+    /// Browse name of the meter object; `meter_data.meter_type` when unset.
+    pub object_name: Option<String>,
+    /// Address and port `run` serves OPC UA on; no server when unset. Writes are only taken
+    /// on a loopback address.
+    pub listen: Option<String>,
+    /// Where the server keeps its certificate, created on first start, and trusted clients.
+    pub pki_dir: String,
+}
+
+impl Default for OpcUaSettings {
+    fn default() -> Self {
+        OpcUaSettings {
+            namespace_uri: "urn:mgw_generic:meters".to_string(),
+            object_name: None,
+            listen: None,
+            pki_dir: "pki".to_string(),
+        }
+    }
+}
+
+/// Closed-loop adjustment of write registers from the read cycles.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ControlSettings {
+    pub loops: Vec<ControlLoop>,
+    /// Falls back to the configured values when no read cycle arrives for this long.
+    pub max_silence_secs: u64,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        ControlSettings { loops: Vec::new(), max_silence_secs: 30 }
+    }
+}
+
+impl ControlSettings {
+    pub fn max_silence(&self) -> Duration {
+        Duration::from_secs(self.max_silence_secs)
+    }
+}
+
+/// Moves the setpoint in a write register until a measured register reaches its target.
+///
+/// Every read cycle the setpoint changes by `gain` times the error (target minus measured
+/// value), at most by `max_step`, and stays within `min` and `max`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ControlLoop {
+    /// Write register holding the setpoint; starts from, and falls back to, its configured value.
+    pub write_register: String,
+    /// Read or derived register the loop steers.
+    pub measured: String,
+    pub target: f32,
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+    /// Errors up to this size leave the setpoint alone.
+    #[serde(default)]
+    pub deadband: f32,
+    /// Largest change of the setpoint per read cycle.
+    #[serde(default)]
+    pub max_step: Option<f32>,
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+fn is_zero(value: &u16) -> bool {
+    *value == 0
+}
+
+/// A virtual register computed from read registers and earlier derived channels.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DerivedChannel {
+    pub name: String,
+    pub expression: Expression,
+    /// Unit and plausible range, as for read registers.
+    #[serde(flatten)]
+    pub info: RegisterInfo,
+}
+
+/// Settings that depend on the meter model rather than on the installation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeterProfile {
+    pub keepalive: KeepaliveProbe,
+    /// Unit and plausible range per read register, keyed by register name.
+    pub registers: HashMap<String, RegisterInfo>,
+    /// Addresses writes may go to; any address when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub writable: Option<Vec<u16>>,
+    /// Text registers read once per connection, e.g. `serial_number` or `firmware`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub identity: BTreeMap<String, StringRegister>,
+    /// Device the profile is meant for; other devices are refused when device identification is on.
+    #[serde(skip_serializing_if = "DeviceMatch::is_empty")]
+    pub device: DeviceMatch,
+}
+
+impl MeterProfile {
+    /// Returns what the profile knows about register `name`; nothing if it is not listed.
+    pub fn register(&self, name: &str) -> RegisterInfo {
+        self.registers.get(name).cloned().unwrap_or_default()
+    }
+}
+
+/// Order of the two words of a float written by the write state machines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WordOrder {
+    /// Low word first, as the write state machines have always written
+    #[default]
+    LowFirst,
+    /// High word first, the order read registers are decoded in
+    HighFirst,
+}
+
+impl WordOrder {
+    pub fn encode(self, value: f32) -> [u16; 2] {
+        let bits = value.to_bits();
+        match self {
+            WordOrder::LowFirst => [bits as u16, (bits >> 16) as u16],
+            WordOrder::HighFirst => [(bits >> 16) as u16, bits as u16],
+        }
+    }
+
+    /// Decodes the float from the first two words of `raw`.
+    pub fn decode(self, raw: &[u16]) -> Option<f32> {
+        let (high, low) = match (self, raw) {
+            (WordOrder::LowFirst, [low, high, ..]) => (high, low),
+            (WordOrder::HighFirst, [high, low, ..]) => (high, low),
+            _ => return None,
+        };
+        Some(f32::from_bits(((*high as u32) << 16) | *low as u32))
+    }
+}
+
+/// Describes the value of a read register.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegisterInfo {
+    pub unit: Option<String>,
+    /// Values below are flagged out of range.
+    pub min: Option<f32>,
+    /// Values above are flagged out of range.
+    pub max: Option<f32>,
+    #[serde(skip_serializing_if = "RegisterFormat::is_float")]
+    pub format: RegisterFormat,
+    /// Number of registers of a `string`, so at most twice as many characters.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub length: u16,
+    /// The first character of each pair of a `string` is in the low byte instead of the high byte.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub byte_swap: bool,
+    /// Names of the bits of a status register, keyed by bit number (0 is the least significant).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub bits: BTreeMap<u8, String>,
+    /// Labels of the values of an enumeration register.
+    #[serde(rename = "enum", skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<u32, String>,
+}
+
+impl RegisterInfo {
+    /// Number of words the register occupies.
+    pub fn words(&self) -> usize {
+        match self.format {
+            RegisterFormat::Uint16 => 1,
+            RegisterFormat::Float32 | RegisterFormat::Uint32 => 2,
+            RegisterFormat::String => self.length as usize,
+        }
+    }
+
+    /// Decodes the text of a `string` register; `None` for other formats or too few words.
+    pub fn text(&self, raw: &[u16]) -> Option<String> {
+        if self.format != RegisterFormat::String || raw.len() < self.words() {
+            return None;
+        }
+        Some(StringRegister { address: 0, length: self.length, byte_swap: self.byte_swap }.decode(raw))
+    }
+
+    /// Rejects a `string` without length, which would read nothing.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.format == RegisterFormat::String && self.length == 0 {
+            return Err("a string needs a length of at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether `value` is a finite number within the configured bounds.
+    pub fn contains(&self, value: f32) -> bool {
+        value.is_finite() && self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+
+    /// Bit number of the flag called `name`.
+    pub fn bit(&self, name: &str) -> Option<u8> {
+        self.bits.iter().find(|(_, flag)| flag.as_str() == name).map(|(&bit, _)| bit)
+    }
+
+    /// Sets or clears flag `name` in `word`, leaving the other bits as they are.
+    pub fn with_flag(&self, word: u32, name: &str, set: bool) -> Result<u32, String> {
+        if !matches!(self.format, RegisterFormat::Uint16 | RegisterFormat::Uint32) {
+            return Err("flags can only be written to uint16 or uint32 registers".to_string());
+        }
+        let bit = self.bit(name).ok_or_else(|| format!("no flag '{}'", name))?;
+        if u32::from(bit) >= self.words() as u32 * 16 {
+            return Err(format!("bit {} does not fit the register", bit));
+        }
+        Ok(if set { word | 1 << bit } else { word & !(1 << bit) })
+    }
+}
+
+/// How the words of a register encode its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegisterFormat {
+    /// IEEE 754 float over two words, high word first
+    #[default]
+    Float32,
+    Uint16,
+    /// Unsigned integer over two words, high word first
+    Uint32,
+    /// ASCII text over `length` words, two characters per word; has no numeric value
+    String,
+}
+
+impl RegisterFormat {
+    pub fn is_float(&self) -> bool {
+        *self == RegisterFormat::Float32
+    }
+
+    /// Decodes the value from the first words of `raw`; `None` for a string.
+    pub fn decode(self, raw: &[u16]) -> Option<f32> {
+        match (self, raw) {
+            (RegisterFormat::Float32, [high, low, ..]) => Some(f32::from_bits(((*high as u32) << 16) | *low as u32)),
+            _ => self.integer(raw).map(|value| value as f32),
+        }
+    }
+
+    /// The words as an integer, for bit fields and enumerations. A float must be a whole number.
+    pub fn integer(self, raw: &[u16]) -> Option<u32> {
+        match (self, raw) {
+            (RegisterFormat::Uint16, [word, ..]) => Some(*word as u32),
+            (RegisterFormat::Uint32, [high, low, ..]) => Some(((*high as u32) << 16) | *low as u32),
+            (RegisterFormat::Float32, _) => self
+                .decode(raw)
+                .filter(|value| value.fract() == 0.0 && *value >= 0.0 && *value <= u32::MAX as f32)
+                .map(|value| value as u32),
+            _ => None,
+        }
+    }
+
+    /// Encodes an integer for writing back.
+    pub fn encode(self, value: u32) -> Vec<u16> {
+        match self {
+            RegisterFormat::Uint16 => vec![value as u16],
+            RegisterFormat::Uint32 => vec![(value >> 16) as u16, value as u16],
+            RegisterFormat::Float32 => {
+                let bits = (value as f32).to_bits();
+                vec![(bits >> 16) as u16, bits as u16]
+            }
+            RegisterFormat::String => Vec::new(),
+        }
+    }
+}
+
+/// Vendor and product code a profile expects from Read Device Identification.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceMatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_code: Option<String>,
+}
+
+impl DeviceMatch {
+    pub fn is_empty(&self) -> bool {
+        self.vendor.is_none() && self.product_code.is_none()
+    }
+
+    /// Whether `device` is the expected one; names compare case-insensitively and unset fields
+    /// match any device.
+    pub fn matches(&self, device: &DeviceIdentification) -> bool {
+        let same = |expected: &Option<String>, actual: &str| {
+            expected.as_ref().is_none_or(|expected| expected.trim().eq_ignore_ascii_case(actual.trim()))
+        };
+        same(&self.vendor, &device.vendor) && same(&self.product_code, &device.product_code)
+    }
+}
+
+/// ASCII text packed two characters per holding register.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StringRegister {
+    pub address: u16,
+    /// Number of registers, so at most twice as many characters.
+    pub length: u16,
+    /// The first character of each pair is in the low byte instead of the high byte.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub byte_swap: bool,
+}
+
+impl StringRegister {
+    /// Decodes the text, which ends at the first NUL; surrounding blanks are trimmed.
+    pub fn decode(&self, words: &[u16]) -> String {
+        let bytes: Vec<u8> = words
+            .iter()
+            .take(self.length as usize)
+            .flat_map(|word| if self.byte_swap { word.to_le_bytes() } else { word.to_be_bytes() })
+            .take_while(|&byte| byte != 0)
+            .collect();
+        String::from_utf8_lossy(&bytes).trim().to_string()
+    }
+}
+
+/// Register table a probe reads from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeTable {
+    Holding,
+    Input,
+    Coil,
+    DiscreteInput,
+}
+
+/// How the Modbus state machine checks that an established connection is still usable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeepaliveProbe {
+    pub address: u16,
+    pub table: ProbeTable,
+    pub count: u16,
+    /// Value the first probed word must have after masking; any value is accepted when unset.
+    pub expected: Option<u16>,
+    pub mask: u16,
+    /// Seconds between two probes while the connection is verified.
+    pub interval_secs: u64,
+    /// Count an exception response as proof of life instead of applying the exception policy.
+    pub exception_is_alive: bool,
+    /// Enables TCP keepalive on the socket with this idle time.
+    pub tcp_keepalive_secs: Option<u64>,
+}
+
+impl Default for KeepaliveProbe {
+    fn default() -> Self {
+        KeepaliveProbe {
+            address: 0,
+            table: ProbeTable::Holding,
+            count: 1,
+            expected: None,
+            mask: 0xFFFF,
+            interval_secs: 10,
+            exception_is_alive: true,
+            tcp_keepalive_secs: None,
+        }
+    }
+}
+
+impl KeepaliveProbe {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.tcp_keepalive_secs.map(Duration::from_secs)
+    }
+
+    /// Rejects probes that cannot fail, reading nothing or masking out every bit, and probes
+    /// without an interval.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.count == 0 {
+            return Err("count must be at least 1".to_string());
+        }
+        if self.mask == 0 {
+            return Err("mask must not be 0".to_string());
+        }
+        if self.interval_secs == 0 {
+            return Err("interval_secs must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Decides whether the connection survives a failed probe.
+    pub fn keeps_connection(&self, error: &ModbusError, policy: &ExceptionPolicy) -> bool {
+        if error.exception().is_some() && self.exception_is_alive {
+            return true;
+        }
+        policy.keeps_connection(error)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ModbusSettings {
+    /// Failover addresses of the meter, tried in order after `meter_data.ip`: IP addresses or
+    /// host names, with `:port` when it differs from `meter_data.port`.
+    pub addresses: Vec<String>,
+    /// How long a host name lookup is reused; looked up on every connect when 0.
+    pub dns_cache_secs: u64,
+    pub transaction: TransactionSettings,
+    pub exception_policy: ExceptionPolicy,
+    /// Ask the meter for vendor, product code and revision (function 43/14) after connecting.
+    pub device_identification: bool,
+    /// Wraps the connection in TLS (Modbus/TCP Security) when set.
+    pub tls: Option<TlsSettings>,
+    pub framing: Framing,
+    /// Slave address of the meter; 255 for Modbus TCP and 1 for RTU framing or a bus when unset.
+    pub unit_id: Option<u8>,
+    /// Queues requests on a shared RS-485 bus instead of opening a connection of their own.
+    pub bus: Option<BusSettings>,
+    /// Priority of this meter's requests on the bus; lower groups are served first.
+    pub scan_group: u8,
+    /// Redundant ways to reach the meter, in order of preference.
+    pub paths: Vec<PathSettings>,
+    /// Time a fallback path has to stay up before the preferred one is tried again;
+    /// 300 seconds when unset.
+    pub failback_secs: Option<u64>,
+}
+
+impl ModbusSettings {
+    pub fn unit_id(&self) -> u8 {
+        self.unit_id.unwrap_or(if self.is_rtu() { 1 } else { 0xFF })
+    }
+
+    pub fn failback(&self) -> Duration {
+        Duration::from_secs(self.failback_secs.unwrap_or(300))
+    }
+
+    /// The settings in effect on `path`: its own addresses, framing, unit id, TLS and bus where
+    /// it has them, the common ones otherwise.
+    pub fn on_path(&self, path: &PathSettings) -> ModbusSettings {
+        let mut settings = self.clone();
+        if path.addresses.len() > 1 {
+            settings.addresses = path.addresses[1..].to_vec();
+        } else if !path.addresses.is_empty() {
+            settings.addresses.clear();
+        }
+        settings.framing = path.framing.unwrap_or(self.framing);
+        settings.unit_id = path.unit_id.or(self.unit_id);
+        settings.tls = path.tls.clone().or_else(|| self.tls.clone());
+        settings.bus = path.bus.clone().or_else(|| self.bus.clone());
+        settings
+    }
+
+    pub fn dns_cache(&self) -> Duration {
+        Duration::from_secs(self.dns_cache_secs)
+    }
+
+    /// Whether requests go out as RTU frames, through a converter or on a bus.
+    pub fn is_rtu(&self) -> bool {
+        self.framing == Framing::RtuOverTcp || self.bus.is_some()
+    }
+}
+
+/// One of several ways to reach the same meter, e.g. Ethernet and a serial link through a
+/// gateway. Whatever a path leaves unset is taken from `modbus`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PathSettings {
+    pub name: String,
+    /// Addresses of the meter on this path, tried in order; `meter_data.ip` and
+    /// `modbus.addresses` when empty.
+    pub addresses: Vec<String>,
+    pub framing: Option<Framing>,
+    pub unit_id: Option<u8>,
+    pub tls: Option<TlsSettings>,
+    pub bus: Option<BusSettings>,
+}
+
+/// How requests are framed on the connection to the meter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// MBAP header, as spoken by Modbus TCP devices
+    #[default]
+    Tcp,
+    /// Raw RTU frames with CRC, as passed through by simple Ethernet-to-RS-485 converters
+    RtuOverTcp,
+}
+
+/// A serial line shared by several RTU meters, of which only one may be asked at a time.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BusSettings {
+    /// Serial device, e.g. `/dev/ttyUSB0`; the bus is reached through the meter's addresses
+    /// with RTU framing when unset, as behind an Ethernet-to-RS-485 converter.
+    pub serial_port: Option<String>,
+    pub baud_rate: u32,
+    pub parity: Parity,
+    pub stop_bits: u8,
+    /// Silence that ends a frame; 3.5 character times at `baud_rate` when unset, and the
+    /// 1.75 ms the specification fixes above 19200 baud.
+    pub silence_ms: Option<u64>,
+    /// Pause after a response before the next request, for transceivers slow to turn around.
+    pub turnaround_ms: u64,
+    /// How long a slave has to start answering before the request counts as unanswered.
+    pub response_timeout_ms: u64,
+    /// Unanswered requests in a row after which a slave is isolated.
+    pub isolate_after: u32,
+    /// How long requests to an isolated slave fail without going on the bus; after that one
+    /// request is let through to see whether it is back.
+    pub isolation_secs: u64,
+}
+
+impl Default for BusSettings {
+    fn default() -> Self {
+        BusSettings {
+            serial_port: None,
+            baud_rate: 9600,
+            parity: Parity::Even,
+            stop_bits: 1,
+            silence_ms: None,
+            turnaround_ms: 0,
+            response_timeout_ms: 1000,
+            isolate_after: 3,
+            isolation_secs: 60,
+        }
+    }
+}
+
+impl BusSettings {
+    pub fn silence(&self) -> Duration {
+        match self.silence_ms {
+            Some(silence_ms) => Duration::from_millis(silence_ms),
+            None if self.baud_rate > 19200 => Duration::from_micros(1750),
+            // 3.5 characters of 11 bits each
+            None => Duration::from_micros(38_500_000 / self.baud_rate.max(1) as u64),
+        }
+    }
+
+    /// Time the line has to stay quiet between a response and the next request.
+    pub fn frame_gap(&self) -> Duration {
+        self.silence().max(Duration::from_millis(self.turnaround_ms))
+    }
+
+    pub fn response_timeout(&self) -> Duration {
+        Duration::from_millis(self.response_timeout_ms)
+    }
+
+    pub fn isolation(&self) -> Duration {
+        Duration::from_secs(self.isolation_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parity {
+    None,
+    #[default]
+    Even,
+    Odd,
+}
+
+/// Modbus/TCP Security: TLS with certificates on both sides, usually on port 802.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TlsSettings {
+    /// PEM certificate chain the gateway presents to the meter.
+    pub cert_file: String,
+    /// PEM private key of `cert_file`.
+    pub key_file: String,
+    /// PEM certificates of the authorities the meter's certificate must be issued by.
+    pub ca_file: String,
+    /// Name the meter's certificate is checked against; the address connected to when unset.
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
+/// Timing limits applied to every Modbus transaction.
+///
+/// Retries of failed transactions are governed by `ExceptionPolicy::max_retries`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TransactionSettings {
+    /// Upper bound for establishing the TCP connection.
+    pub connect_timeout_ms: u64,
+    /// Upper bound for one request/response round trip.
+    pub timeout_ms: u64,
+    /// Minimum silence between the end of one transaction and the start of the next.
+    pub inter_frame_delay_ms: u64,
+}
+
+impl Default for TransactionSettings {
+    fn default() -> Self {
+        TransactionSettings {
+            connect_timeout_ms: 5000,
+            timeout_ms: 2000,
+            inter_frame_delay_ms: 0,
+        }
+    }
+}
+
+impl TransactionSettings {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    pub fn inter_frame_delay(&self) -> Duration {
+        Duration::from_millis(self.inter_frame_delay_ms)
+    }
+}
+
+/// What to do when a Modbus transaction fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// Repeat the transaction up to `max_retries` times.
+    Retry,
+    /// Leave the failing register out of this cycle and continue with the others.
+    Skip,
+    /// Keep the connection but wait longer before the next cycle.
+    Backoff,
+    /// Drop the connection and let the Modbus state machine establish a new one.
+    Reconnect,
+}
+
+/// Action per Modbus exception and transport failure class.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ExceptionPolicy {
+    pub illegal_function: PolicyAction,
+    pub illegal_data_address: PolicyAction,
+    pub illegal_data_value: PolicyAction,
+    pub server_device_failure: PolicyAction,
+    pub acknowledge: PolicyAction,
+    pub server_device_busy: PolicyAction,
+    pub gateway_path_unavailable: PolicyAction,
+    pub gateway_target_device: PolicyAction,
+    pub other_exception: PolicyAction,
+    pub timeout: PolicyAction,
+    pub transport: PolicyAction,
+    pub max_retries: u32,
+    pub retry_delay_ms: u64,
+    pub backoff_initial_secs: u64,
+    pub backoff_max_secs: u64,
+}
+
+impl Default for ExceptionPolicy {
+    fn default() -> Self {
+        ExceptionPolicy {
+            illegal_function: PolicyAction::Skip,
+            illegal_data_address: PolicyAction::Skip,
+            illegal_data_value: PolicyAction::Skip,
+            server_device_failure: PolicyAction::Retry,
+            acknowledge: PolicyAction::Backoff,
+            server_device_busy: PolicyAction::Backoff,
+            gateway_path_unavailable: PolicyAction::Backoff,
+            gateway_target_device: PolicyAction::Retry,
+            other_exception: PolicyAction::Retry,
+            timeout: PolicyAction::Retry,
+            transport: PolicyAction::Reconnect,
+            max_retries: 2,
+            retry_delay_ms: 500,
+            backoff_initial_secs: 10,
+            backoff_max_secs: 300,
+        }
+    }
+}
+
+impl ExceptionPolicy {
+    /// Looks up the configured action for `error`.
+    pub fn action_for(&self, error: &ModbusError) -> PolicyAction {
+        match error {
+            ModbusError::Exception(code) => match code {
+                Exception::IllegalFunction => self.illegal_function,
+                Exception::IllegalDataAddress => self.illegal_data_address,
+                Exception::IllegalDataValue => self.illegal_data_value,
+                Exception::ServerDeviceFailure => self.server_device_failure,
+                Exception::Acknowledge => self.acknowledge,
+                Exception::ServerDeviceBusy => self.server_device_busy,
+                Exception::GatewayPathUnavailable => self.gateway_path_unavailable,
+                Exception::GatewayTargetDevice => self.gateway_target_device,
+                _ => self.other_exception,
+            },
+            ModbusError::Timeout => self.timeout,
+            ModbusError::Transport(_) | ModbusError::Protocol(_) | ModbusError::ConnectionFailed(_) => self.transport,
+            ModbusError::NotConnected => PolicyAction::Reconnect,
+            ModbusError::Decode(_) | ModbusError::Config(_) => PolicyAction::Skip,
+        }
+    }
+
+    /// Whether a connection survives `error`: the meter answered with an exception response
+    /// and the policy does not ask for a reconnect. Timeouts and transport errors never do.
+    pub fn keeps_connection(&self, error: &ModbusError) -> bool {
+        error.exception().is_some() && self.action_for(error) != PolicyAction::Reconnect
+    }
+
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_millis(self.retry_delay_ms)
+    }
+
+    /// Returns the next back-off delay, doubling `current` up to `backoff_max_secs`.
+    pub fn next_backoff(&self, current: Option<Duration>) -> Duration {
+        let max = Duration::from_secs(self.backoff_max_secs);
+        match current {
+            Some(current) => (current * 2).min(max),
+            None => Duration::from_secs(self.backoff_initial_secs).min(max),
+        }
+    }
+}
+
+impl Settings {
+    /// Returns the profile for `meter_type`, or the defaults if none is configured.
+    pub fn profile(&self, meter_type: &str) -> MeterProfile {
+        self.profiles.get(meter_type).cloned().unwrap_or_default()
+    }
+
+    /// Names of the profiles whose `device` matches `device`, sorted.
+    pub fn profiles_for(&self, device: &DeviceIdentification) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .profiles
+            .iter()
+            .filter(|(_, profile)| !profile.device.is_empty() && profile.device.matches(device))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    pub fn from_file(file_path: &str) -> Result<Self> {
+        let file = File::open(file_path)
+            .with_context(|| format!("Failed to open file: {}", file_path))?;
+        let settings: Settings = serde_yaml::from_reader(file)
+            .with_context(|| format!("Failed to parse gateway settings from {}", file_path))?;
+        let problems = settings.validate();
+        if !problems.is_empty() {
+            anyhow::bail!("Invalid gateway settings in {}: {}", file_path, problems.join("; "));
+        }
+        Ok(settings)
+    }
+
+    /// Values that parse but would make the gateway misbehave at runtime.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Err(e) = self.aggregation.validate() {
+            problems.push(format!("aggregation: {}", e));
+        }
+        let mut profiles: Vec<_> = self.profiles.iter().collect();
+        profiles.sort_unstable_by_key(|(name, _)| name.as_str());
+        for (name, profile) in profiles {
+            if let Err(e) = profile.keepalive.validate() {
+                problems.push(format!("profiles: keepalive of '{}': {}", name, e));
+            }
+            let mut registers: Vec<_> = profile.registers.iter().collect();
+            registers.sort_unstable_by_key(|(register, _)| register.as_str());
+            for (register, info) in registers {
+                if let Err(e) = info.validate() {
+                    problems.push(format!("profiles: '{}' of '{}': {}", register, name, e));
+                }
+            }
+        }
+        problems
+    }
+}
@@ -0,0 +1,82 @@
+// common_meter_generic/src/tls.rs
+
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use log::info;
+use crate::error::ModbusError;
+use crate::settings::TlsSettings;
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn certificates(path: &str) -> Result<Vec<CertificateDer<'static>>, ModbusError> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| ModbusError::Config(format!("failed to load certificates from {}: {}", path, e)))?;
+    if certificates.is_empty() {
+        return Err(ModbusError::Config(format!("no certificates in {}", path)));
+    }
+    Ok(certificates)
+}
+
+fn private_key(path: &str) -> Result<PrivateKeyDer<'static>, ModbusError> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| ModbusError::Config(format!("failed to load the private key from {}: {}", path, e)))
+}
+
+fn roots(path: &str) -> Result<RootCertStore, ModbusError> {
+    let mut roots = RootCertStore::empty();
+    for certificate in certificates(path)? {
+        roots
+            .add(certificate)
+            .map_err(|e| ModbusError::Config(format!("invalid CA certificate in {}: {}", path, e)))?;
+    }
+    Ok(roots)
+}
+
+/// TLS client for the gateway: presents its certificate and only trusts meters whose
+/// certificate was issued by the configured CA.
+pub fn connector(settings: &TlsSettings) -> Result<TlsConnector, ModbusError> {
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| ModbusError::Config(e.to_string()))?
+        .with_root_certificates(roots(&settings.ca_file)?)
+        .with_client_auth_cert(certificates(&settings.cert_file)?, private_key(&settings.key_file)?)
+        .map_err(|e| ModbusError::Config(format!("unusable client certificate {}: {}", settings.cert_file, e)))?;
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// TLS server, as for the simulator: only accepts clients whose certificate was issued by the
+/// configured CA.
+pub fn acceptor(settings: &TlsSettings) -> Result<TlsAcceptor, ModbusError> {
+    let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots(&settings.ca_file)?), provider())
+        .build()
+        .map_err(|e| ModbusError::Config(e.to_string()))?;
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| ModbusError::Config(e.to_string()))?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certificates(&settings.cert_file)?, private_key(&settings.key_file)?)
+        .map_err(|e| ModbusError::Config(format!("unusable server certificate {}: {}", settings.cert_file, e)))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Runs the TLS handshake with the meter at `host` on an established TCP stream.
+pub async fn handshake(stream: TcpStream, host: &str, tls: &TlsSettings) -> Result<TlsStream<TcpStream>, ModbusError> {
+    let name = tls.server_name.as_deref().unwrap_or(host);
+    let server_name = ServerName::try_from(name.to_string())
+        .map_err(|e| ModbusError::Config(format!("invalid TLS server name {}: {}", name, e)))?;
+    let stream = connector(tls)?
+        .connect(server_name, stream)
+        .await
+        .map_err(|e| ModbusError::ConnectionFailed(format!("TLS handshake with {} failed: {}", name, e)))?;
+    info!("TLS session with {} established", name);
+    Ok(stream)
+}
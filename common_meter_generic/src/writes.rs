@@ -0,0 +1,221 @@
+// common_meter_generic/src/writes.rs
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::SystemTime;
+use serde::Serialize;
+use log::{info, warn};
+use crate::client::MeterClient;
+use crate::error::ModbusError;
+use crate::settings::{MeterProfile, RegisterInfo, WordOrder, WriteSettings};
+
+/// What became of a write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteOutcome {
+    Written,
+    /// Logged only, because of `writes.dry_run`
+    DryRun,
+    /// Refused by the limits or the allow-list
+    Rejected,
+    /// Sent, but the meter did not accept it
+    Failed,
+}
+
+impl fmt::Display for WriteOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WriteOutcome::Written => "written",
+            WriteOutcome::DryRun => "dry run",
+            WriteOutcome::Rejected => "rejected",
+            WriteOutcome::Failed => "failed",
+        })
+    }
+}
+
+/// One line of the write audit.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditRecord {
+    pub timestamp: SystemTime,
+    /// Who asked for the write, e.g. `cli` or `control`
+    pub source: String,
+    pub name: String,
+    pub address: u16,
+    /// Value read back before the write; `None` when it could not be read
+    pub old: Option<f32>,
+    pub new: f32,
+    pub outcome: WriteOutcome,
+    pub reason: Option<String>,
+}
+
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let old = self.old.map_or("?".to_string(), |old| old.to_string());
+        write!(f, "Write by {}: {} at {}: {} -> {} ({})", self.source, self.name, self.address, old, self.new, self.outcome)?;
+        match &self.reason {
+            Some(reason) => write!(f, ": {}", reason),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WriteError {
+    Rejected(String),
+    Modbus(ModbusError),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::Rejected(reason) => write!(f, "write rejected: {}", reason),
+            WriteError::Modbus(e) => write!(f, "write failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WriteError {}
+
+/// Checks writes against the configured limits and the profile's allow-list, holds them back
+/// in dry-run mode and audits every one of them.
+#[derive(Debug, Clone)]
+pub struct WriteGuard {
+    settings: WriteSettings,
+    writable: Option<Vec<u16>>,
+    source: String,
+}
+
+impl WriteGuard {
+    pub fn new(settings: &WriteSettings, profile: &MeterProfile, source: &str) -> Self {
+        WriteGuard { settings: settings.clone(), writable: profile.writable.clone(), source: source.to_string() }
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.settings.dry_run
+    }
+
+    /// Word order of the write register `name`, the one the write state machine uses as well.
+    pub fn word_order(&self, name: &str) -> WordOrder {
+        self.settings.word_order(name)
+    }
+
+    /// Forces dry-run mode, as for `write --dry-run`.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.settings.dry_run = dry_run;
+    }
+
+    /// Returns why writing `value` to register `name` at `address` is not allowed, if it is not.
+    pub fn check(&self, name: &str, address: u16, value: f32) -> Result<(), String> {
+        if !value.is_finite() {
+            return Err(format!("{} is not a finite value", value));
+        }
+        if self.writable.as_ref().is_some_and(|writable| !writable.contains(&address)) {
+            return Err(format!("address {} is not writable in the meter profile", address));
+        }
+        match self.settings.limits.get(name) {
+            Some(limits) if !limits.contains(value) => Err(format!(
+                "{} is outside the range {}..{} of {}",
+                value,
+                limits.min.map_or(String::new(), |min| min.to_string()),
+                limits.max.map_or(String::new(), |max| max.to_string()),
+                name
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decides about a write whose register currently holds `old`. Returns whether to send it;
+    /// rejected and dry-run writes are audited here, sent ones are left to `audit`.
+    pub fn admit(&self, name: &str, address: u16, old: Option<f32>, value: f32) -> Result<bool, WriteError> {
+        if let Err(reason) = self.check(name, address, value) {
+            self.audit(name, address, old, value, WriteOutcome::Rejected, Some(reason.clone()));
+            return Err(WriteError::Rejected(reason));
+        }
+        if self.settings.dry_run {
+            self.audit(name, address, old, value, WriteOutcome::DryRun, None);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Logs a write and appends it to the audit file, if one is configured.
+    pub fn audit(&self, name: &str, address: u16, old: Option<f32>, new: f32, outcome: WriteOutcome, reason: Option<String>) {
+        let record = AuditRecord {
+            timestamp: SystemTime::now(),
+            source: self.source.clone(),
+            name: name.to_string(),
+            address,
+            old,
+            new,
+            outcome,
+            reason,
+        };
+        match outcome {
+            WriteOutcome::Written | WriteOutcome::DryRun => info!("{}", record),
+            WriteOutcome::Rejected | WriteOutcome::Failed => warn!("{}", record),
+        }
+        let Some(path) = &self.settings.audit_file else {
+            return;
+        };
+        let appended = serde_json::to_string(&record).map_err(|e| e.to_string()).and_then(|line| {
+            let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| e.to_string())?;
+            writeln!(file, "{}", line).map_err(|e| e.to_string())
+        });
+        if let Err(e) = appended {
+            warn!("Failed to append to the write audit {}: {}", path, e);
+        }
+    }
+
+    /// Sets or clears flag `flag` of the status register `name` at `address` by reading the
+    /// register, changing that one bit and writing it back with the other bits as they were.
+    pub async fn write_flag(
+        &self,
+        client: &mut MeterClient,
+        name: &str,
+        address: u16,
+        info: &RegisterInfo,
+        flag: &str,
+        set: bool,
+    ) -> Result<WriteOutcome, WriteError> {
+        let words = client.read_holding_registers(address, info.words() as u16).await.map_err(WriteError::Modbus)?;
+        let old = info
+            .format
+            .integer(&words)
+            .ok_or_else(|| WriteError::Rejected(format!("{} does not hold an integer", name)))?;
+        let new = info.with_flag(old, flag, set).map_err(WriteError::Rejected)?;
+        if !self.admit(name, address, Some(old as f32), new as f32)? {
+            return Ok(WriteOutcome::DryRun);
+        }
+        match client.write_multiple_registers(address, &info.format.encode(new)).await {
+            Ok(()) => {
+                self.audit(name, address, Some(old as f32), new as f32, WriteOutcome::Written, Some(format!("{} {}", if set { "set" } else { "cleared" }, flag)));
+                Ok(WriteOutcome::Written)
+            }
+            Err(e) => {
+                self.audit(name, address, Some(old as f32), new as f32, WriteOutcome::Failed, Some(e.to_string()));
+                Err(WriteError::Modbus(e))
+            }
+        }
+    }
+
+    /// Reads the current value, then admits, sends and audits the write of a float to `address`,
+    /// both in the register's word order.
+    pub async fn write(&self, client: &mut MeterClient, name: &str, address: u16, value: f32) -> Result<WriteOutcome, WriteError> {
+        let order = self.word_order(name);
+        let old = client.read_float(address, order).await.ok();
+        if !self.admit(name, address, old, value)? {
+            return Ok(WriteOutcome::DryRun);
+        }
+        match client.write_float(address, value, order).await {
+            Ok(()) => {
+                self.audit(name, address, old, value, WriteOutcome::Written, None);
+                Ok(WriteOutcome::Written)
+            }
+            Err(e) => {
+                self.audit(name, address, old, value, WriteOutcome::Failed, Some(e.to_string()));
+                Err(WriteError::Modbus(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// common_meter_generic/tests/address.rs
+
+use tokio::net::TcpListener;
+use common_meter_generic::address::{self, Endpoint};
+use common_meter_generic::settings::ModbusSettings;
+
+fn endpoint(host: &str, port: u16) -> Endpoint {
+    Endpoint { host: host.to_string(), port }
+}
+
+#[test]
+fn parses_ip_addresses_and_host_names() {
+    assert_eq!(Endpoint::parse("10.15.1.2", 502), Ok(endpoint("10.15.1.2", 502)));
+    assert_eq!(Endpoint::parse("10.15.1.2:1502", 502), Ok(endpoint("10.15.1.2", 1502)));
+    assert_eq!(Endpoint::parse("fd00::15:2", 502), Ok(endpoint("fd00::15:2", 502)));
+    assert_eq!(Endpoint::parse("[fd00::15:2]", 502), Ok(endpoint("fd00::15:2", 502)));
+    assert_eq!(Endpoint::parse("[fd00::15:2]:802", 502), Ok(endpoint("fd00::15:2", 802)));
+    assert_eq!(Endpoint::parse("meter-7.plant.example", 502), Ok(endpoint("meter-7.plant.example", 502)));
+    assert_eq!(Endpoint::parse("meter-7:1502", 502), Ok(endpoint("meter-7", 1502)));
+
+    assert_eq!(endpoint("10.15.1.2", 502).to_string(), "10.15.1.2:502");
+    assert_eq!(endpoint("fd00::15:2", 502).to_string(), "[fd00::15:2]:502");
+}
+
+#[test]
+fn rejects_what_is_not_an_address() {
+    for text in ["", "meter_7", "-meter", "[10.15.1.2]:502", "[fd00::2", "[fd00::2]502", "meter:port", "10.15.1.2:0", "10.15.1.2:70000"] {
+        assert!(Endpoint::parse(text, 502).is_err(), "{} was accepted", text);
+    }
+    assert!(Endpoint::parse("10.15.1.2", 0).is_err());
+}
+
+#[test]
+fn tries_failover_addresses_after_the_meter_address() {
+    let settings = ModbusSettings { addresses: vec!["meter-7-backup".to_string(), "[fd00::15:2]:1502".to_string()], ..Default::default() };
+    assert_eq!(
+        address::endpoints("meter-7", 502, &settings),
+        Ok(vec![endpoint("meter-7", 502), endpoint("meter-7-backup", 502), endpoint("fd00::15:2", 1502)])
+    );
+
+    let settings = ModbusSettings { addresses: vec!["not an address".to_string()], ..Default::default() };
+    assert!(address::endpoints("meter-7", 502, &settings).is_err());
+}
+
+#[tokio::test]
+async fn connects_to_the_first_address_that_answers() {
+    // A port nobody listens on any more
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+    let ipv6 = TcpListener::bind("[::1]:0").await.unwrap();
+    let named = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let settings = ModbusSettings::default();
+
+    let endpoints = [endpoint("127.0.0.1", closed), endpoint("::1", ipv6.local_addr().unwrap().port())];
+    let (_stream, connected) = address::connect(&endpoints, &settings).await.unwrap();
+    assert_eq!(connected, endpoints[1]);
+
+    let endpoints = [endpoint("localhost", named.local_addr().unwrap().port())];
+    let (stream, connected) = address::connect(&endpoints, &settings).await.unwrap();
+    assert_eq!(connected, endpoints[0]);
+    assert_eq!(stream.peer_addr().unwrap(), named.local_addr().unwrap());
+
+    assert!(address::connect(&[endpoint("127.0.0.1", closed)], &settings).await.is_err());
+}
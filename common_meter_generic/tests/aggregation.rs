@@ -0,0 +1,128 @@
+// common_meter_generic/tests/aggregation.rs
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use common_meter_generic::{Aggregate, Aggregator, Quality, Reading, Snapshot};
+use common_meter_generic::settings::AggregationSettings;
+
+/// Start of a 15-minute interval
+const BASE: u64 = 1_700_000_100;
+
+fn aggregator() -> Aggregator {
+    Aggregator::new(AggregationSettings {
+        intervals_secs: vec![900],
+        registers: vec!["total_active_power".to_string()],
+        energy: vec!["total_active_power".to_string()],
+        max_gap_secs: 30,
+        file: None,
+    })
+    .unwrap()
+}
+
+fn snapshot(seconds: u64, value: f32, quality: Quality) -> Snapshot {
+    let timestamp = UNIX_EPOCH + Duration::from_secs(seconds);
+    let reading = Reading {
+        meter_id: "meter".to_string(),
+        name: "total_active_power".to_string(),
+        address: 32790,
+        raw: Vec::new(),
+        value: (quality != Quality::CommError).then_some(value),
+        decoded: None,
+        unit: Some("W".to_string()),
+        timestamp,
+        latency: Duration::from_millis(10),
+        quality,
+    };
+    Snapshot { meter_id: "meter".to_string(), timestamp, latency: Duration::from_millis(10), identity: None, path: None, readings: vec![reading] }
+}
+
+/// Feeds a reading every 5 s over `from..to` seconds after `BASE`.
+fn feed(aggregator: &mut Aggregator, from: u64, to: u64, value: impl Fn(u64) -> f32, quality: Quality) -> Vec<Aggregate> {
+    (from..to).step_by(5).flat_map(|t| aggregator.push(&snapshot(BASE + t, value(t), quality))).collect()
+}
+
+#[test]
+fn aggregates_a_complete_interval() {
+    let mut aggregator = aggregator();
+    let aggregates = feed(&mut aggregator, 0, 905, |t| 1000.0 + (t % 2) as f32 * 10.0, Quality::Good);
+
+    assert_eq!(aggregates.len(), 1);
+    let aggregate = &aggregates[0];
+    assert_eq!(aggregate.start, UNIX_EPOCH + Duration::from_secs(BASE));
+    assert_eq!(aggregate.interval, Duration::from_secs(900));
+    assert_eq!(aggregate.samples, 180);
+    assert_eq!((aggregate.min, aggregate.max), (Some(1000.0), Some(1010.0)));
+    assert_eq!(aggregate.average, Some(1005.0));
+    assert_eq!(aggregate.unit.as_deref(), Some("W"));
+    assert!(aggregate.is_complete());
+    // 1005 W on average for a quarter of an hour
+    assert!((aggregate.energy_kwh.unwrap() - 0.25125).abs() < 1e-9, "{:?}", aggregate.energy_kwh);
+}
+
+#[test]
+fn aligns_intervals_to_the_wall_clock() {
+    let mut aggregator = aggregator();
+    let aggregates = feed(&mut aggregator, 450, 905, |_| 500.0, Quality::Good);
+
+    assert_eq!(aggregates[0].start, UNIX_EPOCH + Duration::from_secs(BASE));
+    assert!((aggregates[0].coverage - 0.5).abs() < 0.01);
+    assert!(!aggregates[0].is_complete());
+}
+
+#[test]
+fn reports_gaps_from_bad_readings() {
+    let mut aggregator = aggregator();
+    let mut aggregates = feed(&mut aggregator, 0, 305, |_| 1000.0, Quality::Good);
+    aggregates.extend(feed(&mut aggregator, 305, 605, |_| 0.0, Quality::CommError));
+    aggregates.extend(feed(&mut aggregator, 605, 905, |_| 1000.0, Quality::Good));
+
+    assert_eq!(aggregates.len(), 1);
+    let aggregate = &aggregates[0];
+    assert_eq!(aggregate.gaps, 1);
+    assert_eq!(aggregate.samples, 120);
+    assert!((aggregate.coverage - 595.0 / 900.0).abs() < 1e-4, "{}", aggregate.coverage);
+    // Energy only over the covered part
+    assert!((aggregate.energy_kwh.unwrap() - 1000.0 * 595.0 / 3_600_000.0).abs() < 1e-9);
+}
+
+#[test]
+fn emits_empty_intervals_across_an_outage() {
+    let mut aggregator = aggregator();
+    let mut aggregates = feed(&mut aggregator, 0, 60, |_| 1000.0, Quality::Good);
+    aggregates.extend(aggregator.push(&snapshot(BASE + 2000, 1000.0, Quality::Good)));
+
+    let starts: Vec<SystemTime> = aggregates.iter().map(|aggregate| aggregate.start).collect();
+    assert_eq!(starts, vec![UNIX_EPOCH + Duration::from_secs(BASE), UNIX_EPOCH + Duration::from_secs(BASE + 900)]);
+    assert_eq!(aggregates[0].gaps, 1);
+    assert_eq!(aggregates[1].samples, 0);
+    assert_eq!((aggregates[1].average, aggregates[1].coverage), (None, 0.0));
+    assert_eq!(aggregates[1].energy_kwh, Some(0.0));
+}
+
+#[test]
+fn refuses_intervals_of_zero_seconds() {
+    let settings = AggregationSettings { intervals_secs: vec![900, 0], ..Default::default() };
+    assert_eq!(Aggregator::new(settings).unwrap_err(), "intervals must be longer than 0 seconds");
+}
+
+#[tokio::test]
+async fn appends_the_aggregates_to_the_file() {
+    let path = std::env::temp_dir().join(format!("mgw_aggregates_{}.jsonl", std::process::id()));
+    let settings = AggregationSettings {
+        intervals_secs: vec![900],
+        registers: vec!["total_active_power".to_string()],
+        file: Some(path.to_str().unwrap().to_string()),
+        ..Default::default()
+    };
+    let (snapshots, receiver) = tokio::sync::broadcast::channel(8);
+    snapshots.send(snapshot(BASE, 1000.0, Quality::Good)).unwrap();
+    snapshots.send(snapshot(BASE + 905, 1000.0, Quality::Good)).unwrap();
+    drop(snapshots);
+    Aggregator::new(settings).unwrap().run(receiver).await;
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines: Vec<serde_json::Value> = written.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["name"], "total_active_power");
+    assert_eq!(lines[0]["samples"], 1);
+}
@@ -0,0 +1,121 @@
+// common_meter_generic/tests/alarms.rs
+
+use std::time::{Duration, SystemTime};
+use common_meter_generic::{AlarmEngine, AlarmTransition, Quality, Reading, Snapshot};
+use common_meter_generic::settings::AlarmRule;
+
+fn rule(yaml: &str) -> AlarmRule {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+fn snapshot(seconds: u64, name: &str, value: f32, quality: Quality) -> Snapshot {
+    let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+    Snapshot {
+        meter_id: "meter".to_string(),
+        timestamp,
+        latency: Duration::from_millis(20),
+        identity: None,
+        path: None,
+        readings: vec![Reading {
+            meter_id: "meter".to_string(),
+            name: name.to_string(),
+            address: 1,
+            raw: Vec::new(),
+            value: Some(value),
+            decoded: None,
+            unit: None,
+            timestamp,
+            latency: Duration::from_millis(20),
+            quality,
+        }],
+    }
+}
+
+fn no_targets(_: &str) -> Option<f32> {
+    None
+}
+
+#[test]
+fn raises_after_the_minimum_duration_and_clears_with_hysteresis() {
+    let mut engine = AlarmEngine::new(vec![rule(
+        "{ name: overvoltage, register: voltage, nominal: 230, tolerance_percent: 10, hysteresis: 2, min_duration_secs: 10, severity: critical }",
+    )]);
+    let mut evaluate = |seconds, value| engine.evaluate(&snapshot(seconds, "voltage", value, Quality::Good), &no_targets);
+
+    assert!(evaluate(0, 254.0).is_empty());
+    assert!(evaluate(5, 254.0).is_empty());
+    let raised = evaluate(10, 255.0);
+    assert_eq!(raised.len(), 1);
+    assert_eq!(raised[0].transition, AlarmTransition::Raised);
+    assert_eq!(raised[0].alarm.limit, 253.0);
+    assert_eq!(raised[0].alarm.since, SystemTime::UNIX_EPOCH);
+
+    // Back inside, but not by the hysteresis yet
+    assert!(evaluate(15, 252.0).is_empty());
+    let cleared = evaluate(20, 250.0);
+    assert_eq!(cleared.len(), 1);
+    assert_eq!(cleared[0].transition, AlarmTransition::Cleared);
+    assert!(engine.active().is_empty());
+}
+
+#[test]
+fn a_short_excursion_raises_nothing() {
+    let mut engine = AlarmEngine::new(vec![rule("{ name: drift, register: frequency, low: 49.8, high: 50.2, min_duration_secs: 5 }")]);
+    let mut evaluate = |seconds, value| engine.evaluate(&snapshot(seconds, "frequency", value, Quality::Good), &no_targets);
+
+    assert!(evaluate(0, 50.3).is_empty());
+    assert!(evaluate(3, 50.0).is_empty());
+    // The duration starts over with the next excursion
+    assert!(evaluate(6, 49.7).is_empty());
+    assert_eq!(evaluate(11, 49.7).len(), 1);
+}
+
+#[test]
+fn bad_readings_leave_the_alarm_as_it_is() {
+    let mut engine = AlarmEngine::new(vec![rule("{ name: drift, register: frequency, high: 50.2 }")]);
+
+    assert_eq!(engine.evaluate(&snapshot(0, "frequency", 50.5, Quality::Good), &no_targets).len(), 1);
+    assert!(engine.evaluate(&snapshot(1, "frequency", 50.0, Quality::Stale), &no_targets).is_empty());
+    assert!(engine.evaluate(&snapshot(2, "frequency", 50.0, Quality::CommError), &no_targets).is_empty());
+    assert_eq!(engine.active().len(), 1);
+}
+
+#[test]
+fn compares_against_the_written_target() {
+    let mut engine = AlarmEngine::new(vec![
+        rule("{ name: power_factor, register: power_factor_L1, low: { write_register: power_factor_L1 }, severity: info }"),
+        rule("{ name: implausible, register: power_factor_L1, high: 2 }"),
+    ]);
+    let mut target = 0.95;
+    let targets = |name: &str| (name == "power_factor_L1").then_some(target);
+
+    let events = engine.evaluate(&snapshot(0, "power_factor_L1", 0.9, Quality::Good), &targets);
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].alarm.name.as_str(), events[0].alarm.limit), ("power_factor", 0.95));
+
+    target = 0.85;
+    let targets = |name: &str| (name == "power_factor_L1").then_some(target);
+    let events = engine.evaluate(&snapshot(1, "power_factor_L1", 0.9, Quality::Good), &targets);
+    assert_eq!(events[0].transition, AlarmTransition::Cleared);
+}
+
+#[test]
+fn follows_the_limit_the_value_is_outside_of() {
+    let mut engine = AlarmEngine::new(vec![rule("{ name: drift, register: frequency, low: 49.8, high: { write_register: frequency_max } }")]);
+    let mut high = 50.2;
+    let targets = |name: &str| (name == "frequency_max").then_some(high);
+    assert_eq!(engine.evaluate(&snapshot(0, "frequency", 50.5, Quality::Good), &targets)[0].alarm.limit, 50.2);
+
+    // The target moves while the value stays above it
+    high = 50.4;
+    let targets = |name: &str| (name == "frequency_max").then_some(high);
+    assert!(engine.evaluate(&snapshot(1, "frequency", 50.5, Quality::Good), &targets).is_empty());
+    assert_eq!(engine.active()[0].limit, 50.4);
+
+    // Straight from above the high limit to below the low one
+    let events = engine.evaluate(&snapshot(2, "frequency", 49.5, Quality::Good), &targets);
+    let transitions: Vec<(AlarmTransition, f32)> = events.iter().map(|event| (event.transition, event.alarm.limit)).collect();
+    assert_eq!(transitions, vec![(AlarmTransition::Cleared, 50.4), (AlarmTransition::Raised, 49.8)]);
+    let active = engine.active();
+    assert_eq!((active.len(), active[0].limit, active[0].since), (1, 49.8, SystemTime::UNIX_EPOCH + Duration::from_secs(2)));
+}
@@ -0,0 +1,94 @@
+// common_meter_generic/tests/control.rs
+
+use std::time::{Duration, SystemTime};
+use common_meter_generic::{Controller, Quality, Reading, SetpointKind, Snapshot};
+use common_meter_generic::settings::ControlSettings;
+
+const SETTINGS: &str = r#"
+loops:
+  - write_register: pf_setpoint
+    measured: power_factor
+    target: 0.95
+    gain: 0.5
+    deadband: 0.01
+    max_step: 0.02
+    min: 0.8
+    max: 1.0
+"#;
+
+fn controller() -> Controller {
+    Controller::new(serde_yaml::from_str::<ControlSettings>(SETTINGS).unwrap())
+}
+
+fn snapshot(value: f32, quality: Quality) -> Snapshot {
+    Snapshot {
+        meter_id: "meter".to_string(),
+        timestamp: SystemTime::UNIX_EPOCH,
+        latency: Duration::from_millis(20),
+        identity: None,
+        path: None,
+        readings: vec![Reading {
+            meter_id: "meter".to_string(),
+            name: "power_factor".to_string(),
+            address: 1,
+            raw: Vec::new(),
+            value: Some(value),
+            decoded: None,
+            unit: None,
+            timestamp: SystemTime::UNIX_EPOCH,
+            latency: Duration::from_millis(20),
+            quality,
+        }],
+    }
+}
+
+fn configured(name: &str) -> Option<f32> {
+    (name == "pf_setpoint").then_some(0.9)
+}
+
+#[test]
+fn steps_toward_the_target_within_the_limits() {
+    let mut controller = controller();
+
+    // 0.5 * 0.15 is more than max_step
+    let setpoints = controller.update(&snapshot(0.8, Quality::Good), &configured);
+    assert_eq!(setpoints.len(), 1);
+    assert_eq!((setpoints[0].write_register.as_str(), setpoints[0].kind), ("pf_setpoint", SetpointKind::Control));
+    assert!((setpoints[0].value - 0.92).abs() < 1e-6);
+
+    let setpoints = controller.update(&snapshot(0.93, Quality::Good), &configured);
+    assert!((setpoints[0].value - 0.93).abs() < 1e-6);
+
+    // Within the deadband
+    assert!(controller.update(&snapshot(0.945, Quality::Good), &configured).is_empty());
+}
+
+#[test]
+fn clamps_the_setpoint() {
+    let mut controller = controller();
+    let mut value = 0.0;
+    for _ in 0..10 {
+        if let Some(setpoint) = controller.update(&snapshot(0.5, Quality::Good), &configured).pop() {
+            value = setpoint.value;
+        }
+    }
+    assert_eq!(value, 1.0);
+}
+
+#[test]
+fn falls_back_to_the_configured_value_on_stale_readings() {
+    let mut controller = controller();
+    controller.update(&snapshot(0.8, Quality::Good), &configured);
+
+    let setpoints = controller.update(&snapshot(0.8, Quality::Stale), &configured);
+    assert_eq!(setpoints.len(), 1);
+    assert_eq!((setpoints[0].value, setpoints[0].kind), (0.9, SetpointKind::Fallback));
+    // Only once
+    assert!(controller.update(&snapshot(0.8, Quality::CommError), &configured).is_empty());
+    assert!(controller.cut_off(&configured).is_empty());
+
+    // Resumes from the configured value
+    let setpoints = controller.update(&snapshot(0.8, Quality::Good), &configured);
+    assert!((setpoints[0].value - 0.92).abs() < 1e-6);
+    assert_eq!(controller.cut_off(&configured)[0].kind, SetpointKind::Fallback);
+}
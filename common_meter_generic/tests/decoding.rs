@@ -0,0 +1,83 @@
+// common_meter_generic/tests/decoding.rs
+
+use std::time::{Duration, SystemTime};
+use common_meter_generic::{Decoded, Quality, Reading};
+use common_meter_generic::settings::{RegisterInfo, StringRegister};
+
+fn info(yaml: &str) -> RegisterInfo {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+fn decode(info: &RegisterInfo, raw: &[u16]) -> Reading {
+    Reading::decode("meter", "status", 40001, raw, info, SystemTime::UNIX_EPOCH, Duration::ZERO)
+}
+
+#[test]
+fn names_the_bits_set() {
+    let status = info("{ format: uint16, bits: { 0: phase_loss, 3: ct_reversed } }");
+    let reading = decode(&status, &[0b1000_1001, 0xFFFF]);
+
+    assert_eq!(reading.raw, vec![0b1000_1001]);
+    assert_eq!(reading.value, Some(137.0));
+    assert_eq!(reading.decoded, Some(Decoded::Flags(vec!["phase_loss".to_string(), "ct_reversed".to_string(), "bit7".to_string()])));
+    assert_eq!(reading.quality, Quality::Good);
+    assert_eq!(reading.to_string(), "Name: status, Address: 40001, Value: 137 [phase_loss, ct_reversed, bit7] (good)");
+}
+
+#[test]
+fn labels_enumeration_values() {
+    let mode = info("{ format: uint32, enum: { 1: 3P3W, 2: 3P4W } }");
+    assert_eq!(decode(&mode, &[0, 2]).decoded, Some(Decoded::Label(Some("3P4W".to_string()))));
+
+    let unknown = decode(&mode, &[0, 7]);
+    assert_eq!((unknown.decoded, unknown.quality), (Some(Decoded::Label(None)), Quality::OutOfRange));
+
+    // A mode the meter reports as a float
+    let float_mode = info("{ enum: { 2: 3P4W } }");
+    let bits = 2f32.to_bits();
+    assert_eq!(decode(&float_mode, &[(bits >> 16) as u16, bits as u16]).decoded, Some(Decoded::Label(Some("3P4W".to_string()))));
+}
+
+#[test]
+fn changes_one_flag_and_keeps_the_others() {
+    let status = info("{ format: uint16, bits: { 0: phase_loss, 3: ct_reversed, 15: locked } }");
+    assert_eq!(status.with_flag(0b1001, "ct_reversed", false), Ok(0b0001));
+    assert_eq!(status.with_flag(0b0001, "locked", true), Ok(0x8001));
+    assert!(status.with_flag(0, "unknown", true).is_err());
+
+    let float = info("{ bits: { 0: phase_loss } }");
+    assert!(float.with_flag(0, "phase_loss", true).is_err());
+}
+
+#[test]
+fn decodes_ascii_strings() {
+    let serial: StringRegister = serde_yaml::from_str("{ address: 40100, length: 4 }").unwrap();
+    // "SN 12345" padded with NULs, and a leading blank
+    assert_eq!(serial.decode(&[0x534E, 0x2031, 0x3233, 0x3435]), "SN 12345");
+    assert_eq!(serial.decode(&[0x2041, 0x4200, 0x4343, 0x0000]), "AB");
+    // Words beyond `length` are not part of the text
+    assert_eq!(serial.decode(&[0x4142, 0x4344, 0x4546, 0x4748, 0x4950]), "ABCDEFGH");
+
+    let swapped = StringRegister { byte_swap: true, ..serial };
+    assert_eq!(swapped.decode(&[0x3156, 0x322E, 0x0030, 0x0000]), "V1.20");
+}
+
+#[test]
+fn reads_string_registers_as_text() {
+    let model = info("{ format: string, length: 3 }");
+    let reading = decode(&model, &[0x454D, 0x2D4D, 0x4100, 0xFFFF]);
+    assert_eq!(reading.raw, vec![0x454D, 0x2D4D, 0x4100]);
+    assert_eq!((reading.value, reading.quality), (None, Quality::Good));
+    assert_eq!(reading.decoded, Some(Decoded::Text("EM-MA".to_string())));
+    assert_eq!(reading.to_string(), "Name: status, Address: 40001, Value: - \"EM-MA\" (good)");
+
+    let swapped = info("{ format: string, length: 2, byte_swap: true }");
+    assert_eq!(decode(&swapped, &[0x3156, 0x322E]).decoded, Some(Decoded::Text("V1.2".to_string())));
+
+    // Too few words for the whole string
+    let short = decode(&model, &[0x454D]);
+    assert_eq!((short.decoded, short.quality), (None, Quality::CommError));
+    assert_eq!(model.words(), 3);
+    assert!(model.validate().is_ok());
+    assert!(info("{ format: string }").validate().is_err());
+}
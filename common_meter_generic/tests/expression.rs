@@ -0,0 +1,75 @@
+// common_meter_generic/tests/expression.rs
+
+use std::time::{Duration, SystemTime};
+use common_meter_generic::{Expression, Quality, Reading};
+use common_meter_generic::settings::{DerivedChannel, RegisterInfo};
+
+fn evaluate(source: &str, variables: &[(&str, f64)]) -> Option<f64> {
+    let lookup = |name: &str| variables.iter().find(|(n, _)| *n == name).map(|(_, value)| *value);
+    Expression::parse(source).unwrap().evaluate(&lookup)
+}
+
+#[test]
+fn follows_the_usual_precedence() {
+    assert_eq!(evaluate("1 + 2 * 3", &[]), Some(7.0));
+    assert_eq!(evaluate("(1 + 2) * 3", &[]), Some(9.0));
+    assert_eq!(evaluate("-2^2", &[]), Some(-4.0));
+    assert_eq!(evaluate("2^3^2", &[]), Some(512.0));
+    assert_eq!(evaluate("10 - 4 - 3", &[]), Some(3.0));
+}
+
+#[test]
+fn evaluates_registers_and_functions() {
+    let phases = [("l1", 228.0), ("l2", 230.0), ("l3", 232.0)];
+    assert_eq!(evaluate("sqrt(active^2 + reactive^2)", &[("active", 3.0), ("reactive", 4.0)]), Some(5.0));
+    assert_eq!(evaluate("(max(l1, l2, l3) - min(l1, l2, l3)) / avg(l1, l2, l3) * 100", &phases).map(|v| (v * 1000.0).round()), Some(1739.0));
+    assert_eq!(evaluate("abs(l1 - l3)", &phases), Some(4.0));
+    assert_eq!(evaluate("l1 + missing", &phases), None);
+}
+
+#[test]
+fn lists_the_registers_it_reads() {
+    let expression = Expression::parse("sqrt(p_L1^2 + q_L1^2) + p_L1").unwrap();
+    assert_eq!(expression.variables(), vec!["p_L1", "q_L1"]);
+    assert_eq!(expression.to_string(), "sqrt(p_L1^2 + q_L1^2) + p_L1");
+}
+
+#[test]
+fn rejects_malformed_expressions() {
+    for (source, position) in [("1 +", 3), ("sqrt(1, 2)", 0), ("foo(1)", 0), ("(1 + 2", 6), ("1 2", 2), ("1..2", 0)] {
+        let error = Expression::parse(source).unwrap_err();
+        assert_eq!(error.position, position, "{}: {}", source, error);
+    }
+}
+
+#[test]
+fn derived_readings_are_as_good_as_their_worst_input() {
+    let reading = |name: &str, value: Option<f32>, quality| Reading {
+        meter_id: "meter".to_string(),
+        name: name.to_string(),
+        address: 1,
+        raw: Vec::new(),
+        value,
+        decoded: None,
+        unit: None,
+        timestamp: SystemTime::UNIX_EPOCH,
+        latency: Duration::from_millis(20),
+        quality,
+    };
+    let channel: DerivedChannel = serde_yaml::from_str("{ name: apparent, expression: 'sqrt(p^2 + q^2)', unit: VA, max: 10 }").unwrap();
+    let derive = |p, q| Reading::derive("meter", &channel, &[p, q]);
+
+    let good = derive(reading("p", Some(3.0), Quality::Good), reading("q", Some(4.0), Quality::Good));
+    assert_eq!((good.value, good.quality, good.unit.as_deref()), (Some(5.0), Quality::Good, Some("VA")));
+    assert_eq!(good.latency, Duration::from_millis(20));
+
+    let stale = derive(reading("p", Some(3.0), Quality::Stale), reading("q", Some(4.0), Quality::Good));
+    assert_eq!(stale.quality, Quality::Stale);
+
+    let missing = derive(reading("p", Some(3.0), Quality::Good), reading("q", None, Quality::CommError));
+    assert_eq!((missing.value, missing.quality), (None, Quality::CommError));
+
+    let too_large = derive(reading("p", Some(30.0), Quality::Good), reading("q", Some(40.0), Quality::Good));
+    assert_eq!(too_large.quality, Quality::OutOfRange);
+    assert_eq!(channel.info, RegisterInfo { unit: Some("VA".to_string()), max: Some(10.0), ..Default::default() });
+}
@@ -0,0 +1,118 @@
+// common_meter_generic/tests/information_model.rs
+
+use std::time::{Duration, SystemTime};
+use common_meter_generic::information_model::{DataType, DataValue, EuRange, NodeClass, StatusCode, Variant};
+use common_meter_generic::{InformationModel, Quality, Reading, Settings, Snapshot};
+
+const SETTINGS: &str = r#"
+opcua:
+  object_name: meter-7
+profiles:
+  Mock:
+    registers:
+      voltage_L1_N: { unit: V, min: 0, max: 300 }
+      total_active_power: { unit: W }
+"#;
+
+const READ: &[(&str, u16)] = &[("voltage_L1_N", 32774), ("total_active_power", 32790), ("power_factor_L1", 32816)];
+const WRITE: &[(&str, u16)] = &[("power_factor_L1", 32816), ("ct_ratio", 40000)];
+
+fn model() -> InformationModel {
+    let settings: Settings = serde_yaml::from_str(SETTINGS).unwrap();
+    InformationModel::new(&settings.opcua, "Mock", &settings.profile("Mock"), READ, WRITE)
+}
+
+fn reading(name: &str, value: Option<f32>, quality: Quality, timestamp: SystemTime) -> Reading {
+    Reading {
+        meter_id: "meter-7".to_string(),
+        name: name.to_string(),
+        address: 0,
+        raw: Vec::new(),
+        value,
+        decoded: None,
+        unit: None,
+        timestamp,
+        latency: Duration::from_millis(5),
+        quality,
+    }
+}
+
+#[test]
+fn exposes_the_meter_as_an_object_with_a_variable_per_register() {
+    let model = model();
+    assert_eq!(model.namespace_uri(), "urn:mgw_generic:meters");
+    let ids: Vec<(&str, NodeClass, bool)> = model.nodes().iter().map(|n| (n.node_id.as_str(), n.class, n.writable)).collect();
+    assert_eq!(
+        ids,
+        vec![
+            ("ns=2;s=meter-7", NodeClass::Object, false),
+            ("ns=2;s=meter-7.ConnectionState", NodeClass::Variable, false),
+            ("ns=2;s=meter-7.voltage_L1_N", NodeClass::Variable, false),
+            ("ns=2;s=meter-7.total_active_power", NodeClass::Variable, false),
+            ("ns=2;s=meter-7.power_factor_L1", NodeClass::Variable, true),
+            ("ns=2;s=meter-7.ct_ratio", NodeClass::Variable, true),
+        ]
+    );
+
+    let voltage = model.node("ns=2;s=meter-7.voltage_L1_N").unwrap();
+    assert_eq!(voltage.unit.as_deref(), Some("V"));
+    assert_eq!(voltage.range, Some(EuRange { low: 0.0, high: 300.0 }));
+    assert_eq!(voltage.parent.as_deref(), Some("ns=2;s=meter-7"));
+    let power = model.node("ns=2;s=meter-7.total_active_power").unwrap();
+    assert_eq!((power.unit.as_deref(), power.range), (Some("W"), None));
+    assert_eq!((voltage.data_type, model.nodes()[0].data_type), (Some(DataType::Float), None));
+    assert_eq!(model.node(model.connection_state()).unwrap().data_type, Some(DataType::String));
+
+    // The object is named after the meter type unless configured otherwise
+    let settings = Settings::default();
+    let model = InformationModel::new(&settings.opcua, "Mock", &settings.profile("Mock"), READ, WRITE);
+    assert_eq!(model.nodes()[0].browse_name, "Mock");
+}
+
+#[test]
+fn values_carry_the_quality_of_the_reading_as_status() {
+    let mut model = model();
+    let waiting = DataValue { value: None, status: StatusCode::BadWaitingForInitialData, source_timestamp: None };
+    assert_eq!(model.value("ns=2;s=meter-7.voltage_L1_N"), Some(&waiting));
+
+    let read_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    model.update(&Snapshot {
+        meter_id: "meter-7".to_string(),
+        timestamp: read_at,
+        latency: Duration::from_millis(20),
+        identity: None,
+        path: None,
+        readings: vec![
+            reading("voltage_L1_N", Some(231.5), Quality::Good, read_at),
+            reading("total_active_power", Some(1500.0), Quality::Stale, read_at - Duration::from_secs(10)),
+            reading("power_factor_L1", None, Quality::CommError, read_at),
+            reading("apparent_power_L1", Some(1600.0), Quality::Good, read_at),
+        ],
+    });
+
+    let voltage = model.value("ns=2;s=meter-7.voltage_L1_N").unwrap();
+    assert_eq!((voltage.value.clone(), voltage.status, voltage.source_timestamp), (Some(Variant::Float(231.5)), StatusCode::Good, Some(read_at)));
+    let power = model.value("ns=2;s=meter-7.total_active_power").unwrap();
+    assert_eq!(power.status, StatusCode::UncertainLastUsableValue);
+    assert_eq!(power.status.code(), 0x4090_0000);
+    let power_factor = model.value("ns=2;s=meter-7.power_factor_L1").unwrap();
+    assert_eq!((power_factor.value.clone(), power_factor.status), (None, StatusCode::BadCommunicationError));
+    // Registers the model does not know are not added
+    assert_eq!(model.value("ns=2;s=meter-7.apparent_power_L1"), None);
+    assert_eq!(model.value("ns=2;s=meter-7.ct_ratio"), Some(&waiting));
+
+    model.set_connection_state("Verify", read_at);
+    let state = model.value(model.connection_state()).unwrap();
+    assert_eq!((state.value.clone(), state.status), (Some(Variant::String("Verify".to_string())), StatusCode::Good));
+}
+
+#[test]
+fn writes_resolve_to_write_registers_only() {
+    let model = model();
+    assert_eq!(model.write_target("ns=2;s=meter-7.power_factor_L1"), Ok(("power_factor_L1", 32816)));
+    assert_eq!(model.write_target("ns=2;s=meter-7.ct_ratio"), Ok(("ct_ratio", 40000)));
+    assert!(model.write_target("ns=2;s=meter-7.voltage_L1_N").unwrap_err().contains("not writable"));
+    assert!(model.write_target("ns=2;s=meter-7.ConnectionState").unwrap_err().contains("not writable"));
+    assert!(model.write_target("ns=2;s=meter-7").unwrap_err().contains("not writable"));
+    assert!(model.write_target("ns=2;s=meter-7.nothing").unwrap_err().contains("unknown node"));
+}
@@ -0,0 +1,49 @@
+// common_meter_generic/tests/reconcile.rs
+
+use std::time::Duration;
+use tokio::time::Instant;
+use common_meter_generic::Reconciler;
+use common_meter_generic::settings::WriteSettings;
+
+fn reconciler() -> Reconciler {
+    Reconciler::new(&WriteSettings { tolerance: 0.001, recheck_secs: 60, ..Default::default() })
+}
+
+#[test]
+fn writes_only_values_that_differ() {
+    let mut reconciler = reconciler();
+    let now = Instant::now();
+
+    assert!(reconciler.is_due("pf", 0.95, now));
+    assert!(!reconciler.needs_write("pf", 32816, 0.95, Some(0.9505), now));
+    // Confirmed, so not even read back until the recheck
+    assert!(!reconciler.is_due("pf", 0.95, now + Duration::from_secs(59)));
+    assert!(reconciler.is_due("pf", 0.95, now + Duration::from_secs(60)));
+    // A new configured value is due at once
+    assert!(reconciler.is_due("pf", 0.9, now));
+
+    assert!(reconciler.needs_write("other", 32818, 1.0, Some(0.0), now));
+    assert!(reconciler.needs_write("unreadable", 32820, 1.0, None, now));
+}
+
+#[test]
+fn reports_drift_of_a_confirmed_value() {
+    let mut reconciler = reconciler();
+    let mut drift = reconciler.subscribe_drift();
+    let now = Instant::now();
+
+    // Differing before anything was confirmed is no drift
+    assert!(reconciler.needs_write("pf", 32816, 0.95, Some(0.5), now));
+    assert!(drift.try_recv().is_err());
+    reconciler.confirm("pf", 0.95, now);
+
+    let later = now + Duration::from_secs(60);
+    assert!(reconciler.needs_write("pf", 32816, 0.95, Some(0.8), later));
+    let report = drift.try_recv().unwrap();
+    assert_eq!((report.name.as_str(), report.address, report.expected, report.actual), ("pf", 32816, 0.95, 0.8));
+
+    // A changed configuration is no drift either
+    reconciler.confirm("pf", 0.95, later);
+    assert!(reconciler.needs_write("pf", 32816, 0.9, Some(0.95), later));
+    assert!(drift.try_recv().is_err());
+}
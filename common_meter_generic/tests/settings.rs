@@ -0,0 +1,41 @@
+// common_meter_generic/tests/settings.rs
+//
+// Values that parse but are rejected when the settings are loaded.
+
+use std::fs;
+use common_meter_generic::Settings;
+
+fn load(yaml: &str) -> anyhow::Result<Settings> {
+    let path = std::env::temp_dir().join(format!("mgw_settings_{}_{}.yaml", std::process::id(), yaml.len()));
+    fs::write(&path, yaml).unwrap();
+    let settings = Settings::from_file(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+    settings
+}
+
+#[test]
+fn rejects_keepalive_probes_that_cannot_fail() {
+    let settings: Settings = serde_yaml::from_str("profiles: { Mock: { keepalive: { count: 0 } } }").unwrap();
+    assert_eq!(settings.validate(), vec!["profiles: keepalive of 'Mock': count must be at least 1".to_string()]);
+    let settings: Settings = serde_yaml::from_str("profiles: { Mock: { keepalive: { expected: 1, mask: 0 } } }").unwrap();
+    assert_eq!(settings.validate(), vec!["profiles: keepalive of 'Mock': mask must not be 0".to_string()]);
+    // Would probe the meter in a tight loop
+    let settings: Settings = serde_yaml::from_str("profiles: { Mock: { keepalive: { interval_secs: 0 } } }").unwrap();
+    assert_eq!(settings.validate(), vec!["profiles: keepalive of 'Mock': interval_secs must be at least 1".to_string()]);
+
+    let error = load("profiles:\n  Mock:\n    keepalive: { address: 32774, count: 0 }\n").unwrap_err();
+    assert!(error.to_string().contains("count must be at least 1"), "{}", error);
+    assert!(load("profiles:\n  Mock:\n    keepalive: { address: 32774, count: 2, mask: 0x00FF }\n").is_ok());
+}
+
+#[test]
+fn rejects_aggregation_intervals_of_zero_seconds() {
+    let error = load("aggregation:\n  intervals_secs: [900, 0]\n").unwrap_err();
+    assert!(error.to_string().contains("aggregation: intervals must be longer than 0 seconds"), "{}", error);
+}
+
+#[test]
+fn rejects_strings_without_length() {
+    let error = load("profiles:\n  Mock:\n    registers:\n      serial_number: { format: string }\n").unwrap_err();
+    assert!(error.to_string().contains("profiles: 'serial_number' of 'Mock': a string needs a length of at least 1"), "{}", error);
+}
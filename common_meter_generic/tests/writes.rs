@@ -0,0 +1,59 @@
+// common_meter_generic/tests/writes.rs
+
+use std::fs;
+use common_meter_generic::{Settings, WriteError, WriteGuard};
+
+const SETTINGS: &str = r#"
+profiles:
+  Mock:
+    writable: [32816, 32818]
+writes:
+  limits:
+    power_factor_L1: { min: 0, max: 1 }
+"#;
+
+fn guard(audit_file: Option<&str>, dry_run: bool) -> WriteGuard {
+    let mut settings: Settings = serde_yaml::from_str(SETTINGS).unwrap();
+    settings.writes.audit_file = audit_file.map(str::to_string);
+    settings.writes.dry_run = dry_run;
+    WriteGuard::new(&settings.writes, &settings.profile("Mock"), "test")
+}
+
+#[test]
+fn rejects_values_out_of_range_and_addresses_not_allowed() {
+    let guard = guard(None, false);
+    assert_eq!(guard.check("power_factor_L1", 32816, 0.95), Ok(()));
+    assert!(guard.check("power_factor_L1", 32816, 1.5).is_err());
+    assert!(guard.check("power_factor_L1", 32816, f32::NAN).is_err());
+    // No limits for this one, but the address is not writable
+    assert_eq!(guard.check("power_factor_L2", 32818, 42.0), Ok(()));
+    assert!(guard.check("ct_ratio", 40000, 1.0).unwrap_err().contains("not writable"));
+}
+
+#[test]
+fn any_address_is_writable_without_an_allow_list() {
+    let settings = Settings::default();
+    let guard = WriteGuard::new(&settings.writes, &settings.profile("Unknown"), "test");
+    assert_eq!(guard.check("ct_ratio", 40000, 1.0), Ok(()));
+}
+
+#[test]
+fn audits_rejected_and_dry_run_writes() {
+    let path = std::env::temp_dir().join(format!("mgw_writes_{}.log", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let rejected = guard(path.to_str(), false).admit("power_factor_L1", 32816, Some(0.5), 2.0);
+    assert!(matches!(rejected, Err(WriteError::Rejected(_))));
+    assert!(!guard(path.to_str(), true).admit("power_factor_L1", 32816, Some(0.9), 0.95).unwrap());
+    assert!(guard(path.to_str(), false).admit("power_factor_L1", 32816, None, 0.95).unwrap());
+
+    let audit = fs::read_to_string(&path).unwrap();
+    let lines: Vec<serde_yaml::Value> = audit.lines().map(|line| serde_yaml::from_str(line).unwrap()).collect();
+    fs::remove_file(&path).unwrap();
+    // Admitted writes are audited once they have been sent
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["outcome"].as_str(), Some("rejected"));
+    assert_eq!((lines[0]["old"].as_f64(), lines[0]["new"].as_f64()), (Some(0.5), Some(2.0)));
+    assert_eq!(lines[1]["outcome"].as_str(), Some("dry_run"));
+    assert_eq!(lines[1]["source"].as_str(), Some("test"));
+}
@@ -0,0 +1,162 @@
+// fsm_meter_generic/src/history.rs
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+
+/// Transitions kept per machine unless the machine asks for another capacity.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// One transition taken by a machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransitionRecord<S, E> {
+    /// Wall-clock time, for display
+    pub at: SystemTime,
+    /// Monotonic time, for counting over a window
+    pub instant: Instant,
+    pub from: S,
+    pub event: E,
+    pub to: S,
+    /// Error or other cause the activity noted before firing the event
+    pub reason: Option<String>,
+}
+
+impl<S: fmt::Debug, E: fmt::Debug> fmt::Display for TransitionRecord<S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} --{:?}--> {:?}", self.from, self.event, self.to)?;
+        if let Some(reason) = &self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// How often a transition was taken within a window, e.g. "Connect failed 3× in last hour: timeout".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrences {
+    pub count: usize,
+    /// Reason of the latest occurrence that had one
+    pub last_reason: Option<String>,
+}
+
+struct Inner<S, E> {
+    records: VecDeque<TransitionRecord<S, E>>,
+    capacity: usize,
+    /// Cause noted by the running activity, attached to the next transition
+    pending_reason: Option<String>,
+}
+
+/// Bounded transition history of a machine, with subscriptions to its state and transitions.
+///
+/// Cloning gives another handle to the same history, so it can be read and subscribed to
+/// while the machine itself is locked by its task.
+pub struct History<S, E> {
+    inner: Arc<Mutex<Inner<S, E>>>,
+    state: Arc<watch::Sender<S>>,
+    transitions: broadcast::Sender<TransitionRecord<S, E>>,
+}
+
+impl<S, E> Clone for History<S, E> {
+    fn clone(&self) -> Self {
+        History { inner: self.inner.clone(), state: self.state.clone(), transitions: self.transitions.clone() }
+    }
+}
+
+impl<S, E> History<S, E>
+where
+    S: Copy + Eq + fmt::Debug + Send + Sync + 'static,
+    E: Copy + Eq + fmt::Debug + Send + Sync + 'static,
+{
+    /// Creates a history starting in `initial`, keeping the last `capacity` transitions.
+    pub fn new(initial: S, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (state, _) = watch::channel(initial);
+        let (transitions, _) = broadcast::channel(capacity);
+        History {
+            inner: Arc::new(Mutex::new(Inner { records: VecDeque::with_capacity(capacity), capacity, pending_reason: None })),
+            state: Arc::new(state),
+            transitions,
+        }
+    }
+
+    /// Notes why the activity is about to fire its event; the reason goes with the next transition.
+    pub fn note(&self, reason: impl Into<String>) {
+        self.lock().pending_reason = Some(reason.into());
+    }
+
+    /// Records a transition and publishes it to subscribers. Called by [`crate::fire`].
+    pub fn record(&self, from: S, event: E, to: S) -> TransitionRecord<S, E> {
+        let record = {
+            let mut inner = self.lock();
+            let record = TransitionRecord {
+                at: SystemTime::now(),
+                instant: Instant::now(),
+                from,
+                event,
+                to,
+                reason: inner.pending_reason.take(),
+            };
+            if inner.records.len() == inner.capacity {
+                inner.records.pop_front();
+            }
+            inner.records.push_back(record.clone());
+            record
+        };
+        self.state.send_replace(to);
+        // Nobody listening is fine
+        let _ = self.transitions.send(record.clone());
+        record
+    }
+
+    /// Drops a reason noted for a transition that was not taken.
+    pub fn discard_note(&self) {
+        self.lock().pending_reason = None;
+    }
+
+    /// The current state, updated on every transition.
+    pub fn subscribe_state(&self) -> watch::Receiver<S> {
+        self.state.subscribe()
+    }
+
+    /// Every transition from now on. A receiver falling more than the capacity behind lags.
+    pub fn subscribe_transitions(&self) -> broadcast::Receiver<TransitionRecord<S, E>> {
+        self.transitions.subscribe()
+    }
+
+    /// The kept transitions, oldest first.
+    pub fn records(&self) -> Vec<TransitionRecord<S, E>> {
+        self.lock().records.iter().cloned().collect()
+    }
+
+    /// The latest transition, if any.
+    pub fn last(&self) -> Option<TransitionRecord<S, E>> {
+        self.lock().records.back().cloned()
+    }
+
+    /// Counts the transitions out of `from` on `event` within the last `window`.
+    pub fn occurrences(&self, from: S, event: E, window: Duration) -> Occurrences {
+        let inner = self.lock();
+        let since = Instant::now().checked_sub(window);
+        let matching = inner
+            .records
+            .iter()
+            .filter(|record| record.from == from && record.event == event)
+            .filter(|record| since.is_none_or(|since| record.instant >= since));
+        let mut occurrences = Occurrences { count: 0, last_reason: None };
+        for record in matching {
+            occurrences.count += 1;
+            if record.reason.is_some() {
+                occurrences.last_reason = record.reason.clone();
+            }
+        }
+        occurrences
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner<S, E>> {
+        // The lock is never held across a panic point that leaves the deque inconsistent
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
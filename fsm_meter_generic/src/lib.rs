@@ -0,0 +1,6 @@
+pub mod history;
+pub mod machine;
+pub mod readme;
+
+pub use history::{History, Occurrences, TransitionRecord};
+pub use machine::{act, fire, step, FsmError, Machine, Timer, Transition};
@@ -0,0 +1,145 @@
+// fsm_meter_generic/src/machine.rs
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::sleep;
+use log::info;
+use crate::history::History;
+
+/// A state machine driven by events through a fixed transition table.
+///
+/// The machine keeps its own state; [`step`] runs the activity of the current state and
+/// feeds the resulting event to [`fire`], which picks the transition and runs the exit and
+/// entry actions around it.
+pub trait Machine: Send + Sized + 'static {
+    type State: Copy + Eq + fmt::Debug + Send + Sync + 'static;
+    type Event: Copy + Eq + fmt::Debug + Send + Sync + 'static;
+
+    /// Every allowed transition; the first row matching state and event whose guard passes wins.
+    const TRANSITIONS: &'static [Transition<Self>];
+
+    fn state(&self) -> Self::State;
+
+    fn set_state(&mut self, state: Self::State);
+
+    /// Where [`fire`] records the transitions taken.
+    fn history(&self) -> &History<Self::State, Self::Event>;
+
+    /// Work done in `state`. Returns the event it produced, or `None` to wait for the state's timer.
+    fn activity(&mut self, state: Self::State) -> impl Future<Output = Option<Self::Event>> + Send;
+
+    /// Event fired once the activity of `state` finished without one.
+    fn timer(&self, _state: Self::State) -> Option<Timer<Self::Event>> {
+        None
+    }
+
+    fn on_entry(&mut self, _state: Self::State) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    fn on_exit(&mut self, _state: Self::State) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// One row of a transition table.
+pub struct Transition<M: Machine> {
+    pub from: M::State,
+    pub event: M::Event,
+    pub to: M::State,
+    pub guard: Option<fn(&M) -> bool>,
+}
+
+impl<M: Machine> Transition<M> {
+    pub const fn new(from: M::State, event: M::Event, to: M::State) -> Self {
+        Transition { from, event, to, guard: None }
+    }
+
+    /// Only takes the transition while `guard` holds.
+    pub const fn when(self, guard: fn(&M) -> bool) -> Self {
+        Transition { guard: Some(guard), ..self }
+    }
+
+    fn matches(&self, machine: &M, state: M::State, event: M::Event) -> bool {
+        self.from == state && self.event == event && self.guard.is_none_or(|guard| guard(machine))
+    }
+}
+
+/// Fires `event` after `after` has elapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timer<E> {
+    pub after: Duration,
+    pub event: E,
+}
+
+impl<E> Timer<E> {
+    pub fn new(after: Duration, event: E) -> Self {
+        Timer { after, event }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsmError<S, E> {
+    /// The table has no transition for the event in this state, or every guard failed
+    NoTransition { state: S, event: E },
+}
+
+impl<S: fmt::Debug, E: fmt::Debug> fmt::Display for FsmError<S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsmError::NoTransition { state, event } => write!(f, "no transition for {:?} in state {:?}", event, state),
+        }
+    }
+}
+
+impl<S: fmt::Debug, E: fmt::Debug> std::error::Error for FsmError<S, E> {}
+
+/// Runs the activity of the current state, waits for its timer if it produced no event,
+/// and fires the event. Returns the new state.
+///
+/// A state with neither an event nor a timer stays as it is.
+pub async fn step<M: Machine>(machine: &mut M) -> Result<M::State, FsmError<M::State, M::Event>> {
+    match act(machine).await? {
+        Some(timer) => {
+            sleep(timer.after).await;
+            fire(machine, timer.event).await
+        }
+        None => Ok(machine.state()),
+    }
+}
+
+/// Runs the activity of the current state and fires the event it produced. Without one,
+/// returns the state's timer instead of waiting for it, so a caller sharing the machine can
+/// wait without holding it and [`fire`] the timer's event afterwards.
+pub async fn act<M: Machine>(machine: &mut M) -> Result<Option<Timer<M::Event>>, FsmError<M::State, M::Event>> {
+    let state = machine.state();
+    match machine.activity(state).await {
+        Some(event) => fire(machine, event).await.map(|_| None),
+        None => Ok(machine.timer(state)),
+    }
+}
+
+/// Takes the transition for `event` from the current state, running the exit action of the
+/// old state and the entry action of the new one, also when both are the same.
+///
+/// The transition is recorded in the machine's [`History`] together with any reason the
+/// activity noted; a reason noted for an event without transition is dropped.
+pub async fn fire<M: Machine>(machine: &mut M, event: M::Event) -> Result<M::State, FsmError<M::State, M::Event>> {
+    let from = machine.state();
+    let Some(to) = M::TRANSITIONS
+        .iter()
+        .find(|transition| transition.matches(machine, from, event))
+        .map(|transition| transition.to)
+    else {
+        machine.history().discard_note();
+        return Err(FsmError::NoTransition { state: from, event });
+    };
+
+    let record = machine.history().record(from, event, to);
+    info!("{}", record);
+    machine.on_exit(from).await;
+    machine.set_state(to);
+    machine.on_entry(to).await;
+    Ok(to)
+}
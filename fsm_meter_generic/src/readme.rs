@@ -0,0 +1,108 @@
+// fsm_meter_generic/src/readme.rs
+//
+// Used from build scripts to keep the transition tables in the README and the code in step.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// One row of a transition table in the README.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub from: String,
+    pub event: String,
+    pub action: String,
+    pub to: String,
+}
+
+const CHECK_HEADER: &str = r#"// Generated by build.rs from the README transition table, do not edit.
+const _: () = {
+    const fn has(from: State, event: Event, to: State) -> bool {
+        let table = <StateMachine as fsm_meter_generic::Machine>::TRANSITIONS;
+        let mut i = 0;
+        while i < table.len() {
+            let transition = &table[i];
+            if transition.from as u8 == from as u8 && transition.event as u8 == event as u8 && transition.to as u8 == to as u8 {
+                return true;
+            }
+            i += 1;
+        }
+        false
+    }
+"#;
+
+/// Returns the rows of the first table after the line `heading`.
+///
+/// The table needs the columns `Current State | Event | Action | Next State`, in this order.
+pub fn parse_table(markdown: &str, heading: &str) -> Result<Vec<Row>, String> {
+    let mut lines = markdown.lines().skip_while(|line| line.trim() != heading);
+    if lines.next().is_none() {
+        return Err(format!("heading `{}` not found", heading));
+    }
+
+    let mut rows = Vec::new();
+    let table = lines.skip_while(|line| !line.trim_start().starts_with('|')).take_while(|line| line.trim_start().starts_with('|'));
+    // The header and the separator line come first
+    for line in table.skip(2) {
+        let cells: Vec<&str> = line.trim().trim_matches('|').split('|').map(str::trim).collect();
+        match cells.as_slice() {
+            [from, event, action, to] => rows.push(Row {
+                from: from.to_string(),
+                event: event.to_string(),
+                action: action.to_string(),
+                to: to.to_string(),
+            }),
+            _ => return Err(format!("expected 4 columns under `{}`: {}", heading, line)),
+        }
+    }
+    if rows.is_empty() {
+        return Err(format!("no transition table under `{}`", heading));
+    }
+    Ok(rows)
+}
+
+/// Turns a README name like `Ping Successful` into the variant name `PingSuccessful`.
+pub fn identifier(name: &str) -> String {
+    name.split_whitespace()
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map_or(String::new(), |first| first.to_uppercase().chain(chars).collect())
+        })
+        .collect()
+}
+
+/// Generates a compile-time check that `StateMachine`'s `Machine::TRANSITIONS` has exactly the
+/// rows of the table. The code is meant to be `include!`d where `StateMachine`, `State` and
+/// `Event` are in scope; an unknown name or a missing row fails the build.
+pub fn generate_check(rows: &[Row]) -> String {
+    let mut code = String::from(CHECK_HEADER);
+    for row in rows {
+        let _ = writeln!(
+            code,
+            "    assert!(has(State::{}, Event::{}, State::{}), \"README row `{} | {} | {}` has no transition in the code\");",
+            identifier(&row.from),
+            identifier(&row.event),
+            identifier(&row.to),
+            row.from,
+            row.event,
+            row.to,
+        );
+    }
+    let _ = writeln!(
+        code,
+        "    assert!(<StateMachine as fsm_meter_generic::Machine>::TRANSITIONS.len() == {}, \"the transition table has rows missing from the README\");\n}};",
+        rows.len()
+    );
+    code
+}
+
+/// Build script entry point: checks the table under `heading` in `readme` and writes the
+/// generated code to `file_name` in `OUT_DIR`.
+pub fn write_check(readme: &str, heading: &str, file_name: &str) {
+    println!("cargo:rerun-if-changed={}", readme);
+    let markdown = std::fs::read_to_string(readme).unwrap_or_else(|e| panic!("Unable to read {}: {}", readme, e));
+    let rows = parse_table(&markdown, heading).unwrap_or_else(|e| panic!("{}: {}", readme, e));
+
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    let path = Path::new(&out_dir).join(file_name);
+    std::fs::write(&path, generate_check(&rows)).unwrap_or_else(|e| panic!("Unable to write {}: {}", path.display(), e));
+}
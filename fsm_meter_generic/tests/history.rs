@@ -0,0 +1,118 @@
+// fsm_meter_generic/tests/history.rs
+//
+// A two-state machine, run on tokio's paused clock so the failure window can be crossed
+// without waiting.
+
+use tokio::time::{advance, Duration};
+use fsm_meter_generic::{fire, History, Machine, Transition};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Connected,
+    Failed,
+}
+
+struct Link {
+    state: State,
+    history: History<State, Event>,
+}
+
+impl Machine for Link {
+    type State = State;
+    type Event = Event;
+
+    const TRANSITIONS: &'static [Transition<Self>] = &[
+        Transition::new(State::Down, Event::Connected, State::Up),
+        Transition::new(State::Up, Event::Failed, State::Down),
+    ];
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    fn history(&self) -> &History<State, Event> {
+        &self.history
+    }
+
+    async fn activity(&mut self, _state: State) -> Option<Event> {
+        None
+    }
+}
+
+fn link(capacity: usize) -> Link {
+    Link { state: State::Down, history: History::new(State::Down, capacity) }
+}
+
+/// Goes up and fails again with `reason`.
+async fn flap(link: &mut Link, reason: &str) {
+    fire(link, Event::Connected).await.unwrap();
+    link.history.note(reason);
+    fire(link, Event::Failed).await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn records_transitions_with_their_reason() {
+    let mut link = link(8);
+    let mut state = link.history.subscribe_state();
+    let mut transitions = link.history.subscribe_transitions();
+
+    flap(&mut link, "timeout").await;
+
+    let records = link.history.records();
+    assert_eq!(records.len(), 2);
+    assert_eq!((records[0].from, records[0].event, records[0].to), (State::Down, Event::Connected, State::Up));
+    assert_eq!(records[0].reason, None);
+    assert_eq!(records[1].reason.as_deref(), Some("timeout"));
+    assert_eq!(records[1].to_string(), "Up --Failed--> Down: timeout");
+
+    assert!(state.has_changed().unwrap());
+    assert_eq!(*state.borrow_and_update(), State::Down);
+    assert_eq!(transitions.recv().await.unwrap().to, State::Up);
+    assert_eq!(transitions.recv().await.unwrap().to, State::Down);
+}
+
+#[tokio::test(start_paused = true)]
+async fn keeps_only_the_latest_transitions() {
+    let mut link = link(3);
+    for _ in 0..5 {
+        flap(&mut link, "timeout").await;
+    }
+
+    let records = link.history.records();
+    assert_eq!(records.len(), 3);
+    assert_eq!(link.history.last().unwrap().event, Event::Failed);
+}
+
+#[tokio::test(start_paused = true)]
+async fn counts_failures_within_the_window() {
+    let mut link = link(16);
+    flap(&mut link, "refused").await;
+    advance(Duration::from_secs(2 * 60 * 60)).await;
+    flap(&mut link, "timeout").await;
+    flap(&mut link, "timeout").await;
+
+    let last_hour = link.history.occurrences(State::Up, Event::Failed, Duration::from_secs(60 * 60));
+    assert_eq!(last_hour.count, 2);
+    assert_eq!(last_hour.last_reason.as_deref(), Some("timeout"));
+    assert_eq!(link.history.occurrences(State::Up, Event::Failed, Duration::from_secs(3 * 60 * 60)).count, 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn drops_the_reason_of_an_event_without_transition() {
+    let mut link = link(8);
+    link.history.note("stale");
+    assert!(fire(&mut link, Event::Failed).await.is_err());
+    fire(&mut link, Event::Connected).await.unwrap();
+
+    assert_eq!(link.history.last().unwrap().reason, None);
+}
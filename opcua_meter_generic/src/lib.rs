@@ -0,0 +1,4 @@
+pub mod nodes;
+pub mod server;
+
+pub use server::OpcUaServer;
@@ -0,0 +1,231 @@
+// opcua_meter_generic/src/nodes.rs
+
+use std::sync::{Arc, Mutex as StdMutex};
+use async_trait::async_trait;
+use chrono::{DateTime as ChronoDateTime, Utc};
+use opcua::server::address_space::{add_namespaces, AddressSpace, NodeType, ObjectBuilder, VariableBuilder};
+use opcua::server::diagnostics::NamespaceMetadata;
+use opcua::server::node_manager::memory::{InMemoryNodeManager, InMemoryNodeManagerImpl};
+use opcua::server::node_manager::{RequestContext, ServerContext, WriteNode};
+use opcua::sync::RwLock;
+use opcua::types::{
+    AttributeId, DataTypeId, DataValue, DateTime, EUInformation, ExtensionObject, LocalizedText, NodeId, ObjectId,
+    ObjectTypeId, Range, StatusCode, VariableTypeId, Variant,
+};
+use tokio::sync::Mutex;
+use common_meter_generic::information_model::{self, DataType, NodeClass};
+use common_meter_generic::{InformationModel, WriteError, WriteGuard, WriteOutcome};
+use statemachine_modbus::StateMachine as ModbusStateMachine;
+use log::{info, warn};
+
+pub type MeterNodeManager = InMemoryNodeManager<MeterNodes>;
+
+/// The meter's object and variables in the server's address space. Writes to a variable go
+/// through the [`WriteGuard`] on the Modbus state machine's connection before the node takes
+/// the value; without a guard every write is refused.
+pub struct MeterNodes {
+    namespace: NamespaceMetadata,
+    model: Arc<StdMutex<InformationModel>>,
+    guard: Option<WriteGuard>,
+    modbus: Arc<Mutex<ModbusStateMachine>>,
+}
+
+impl MeterNodes {
+    /// Adds the nodes of `model` to the address space, the object organized by the Objects folder.
+    pub fn new(
+        context: ServerContext,
+        address_space: &mut AddressSpace,
+        model: Arc<StdMutex<InformationModel>>,
+        guard: Option<WriteGuard>,
+        modbus: Arc<Mutex<ModbusStateMachine>>,
+    ) -> Self {
+        let nodes = model.lock().unwrap();
+        let namespace_uri = nodes.namespace_uri().to_string();
+        let index = add_namespaces(&context, address_space, &[namespace_uri.as_str()])[0];
+        for node in nodes.nodes() {
+            let id = node_id(index, &node.node_id);
+            let name = node.browse_name.as_str();
+            let parent = node.parent.as_deref().map(|parent| node_id(index, parent));
+            match (node.class, parent) {
+                (NodeClass::Object, _) | (_, None) => {
+                    ObjectBuilder::new(&id, name, name)
+                        .organized_by(ObjectId::ObjectsFolder)
+                        .has_type_definition(ObjectTypeId::BaseObjectType)
+                        .insert(address_space);
+                }
+                (NodeClass::Variable, Some(parent)) => {
+                    let data_type = match node.data_type {
+                        Some(DataType::String) => DataTypeId::String,
+                        _ => DataTypeId::Float,
+                    };
+                    let mut variable = VariableBuilder::new(&id, name, name)
+                        .data_type(data_type)
+                        .value_rank(-1)
+                        .component_of(parent)
+                        .has_type_definition(VariableTypeId::BaseDataVariableType);
+                    if node.writable {
+                        variable = variable.writable();
+                    }
+                    variable.insert(address_space);
+                    if let Some(NodeType::Variable(variable)) = address_space.find_mut(&id) {
+                        if let Some(value) = nodes.value(&node.node_id) {
+                            variable.set_data_value(data_value(value));
+                        }
+                    }
+                    if let Some(unit) = &node.unit {
+                        let units = EUInformation {
+                            namespace_uri: "http://www.opcfoundation.org/UA/units/un/cefact".into(),
+                            unit_id: -1,
+                            display_name: LocalizedText::from(unit.as_str()),
+                            description: LocalizedText::from(unit.as_str()),
+                        };
+                        property(address_space, &id, "EngineeringUnits", DataTypeId::EUInformation, ExtensionObject::from_message(units));
+                    }
+                    if let Some(range) = node.range {
+                        let range = Range { low: range.low as f64, high: range.high as f64 };
+                        property(address_space, &id, "EURange", DataTypeId::Range, ExtensionObject::from_message(range));
+                    }
+                }
+            }
+        }
+        info!("OPC UA namespace {} has index {}", namespace_uri, index);
+        let namespace = NamespaceMetadata { namespace_uri, namespace_index: index, ..Default::default() };
+        drop(nodes);
+        MeterNodes { namespace, model, guard, modbus }
+    }
+
+    pub fn namespace_index(&self) -> u16 {
+        self.namespace.namespace_index
+    }
+
+    /// Node id in the address space of a node of the model.
+    pub fn node_id(&self, model_id: &str) -> NodeId {
+        node_id(self.namespace.namespace_index, model_id)
+    }
+
+    /// Sends a client's write to the meter. A dry run counts as written, as with `write --dry-run`.
+    async fn write_register(&self, guard: &WriteGuard, name: &str, address: u16, value: f32) -> StatusCode {
+        let context = match self.modbus.lock().await.access_modbus_context().await {
+            Ok(Some(context)) => context,
+            _ => {
+                warn!("OPC UA write of {} to {}: no Modbus connection", value, name);
+                return StatusCode::BadNoCommunication;
+            }
+        };
+        let mut client = context.lock().await;
+        match guard.write(&mut client, name, address, value).await {
+            Ok(WriteOutcome::Written) | Ok(WriteOutcome::DryRun) => StatusCode::Good,
+            Ok(WriteOutcome::Failed) | Err(WriteError::Modbus(_)) => StatusCode::BadCommunicationError,
+            Ok(WriteOutcome::Rejected) => StatusCode::BadOutOfRange,
+            Err(WriteError::Rejected(reason)) => {
+                warn!("OPC UA write of {} to {} rejected: {}", value, name, reason);
+                StatusCode::BadOutOfRange
+            }
+        }
+    }
+
+    /// The write register behind a node and the value to write, or why the write is refused.
+    fn parse_write(&self, context: &RequestContext, address_space: &RwLock<AddressSpace>, write: &WriteNode) -> Result<(String, u16, f32), StatusCode> {
+        if self.guard.is_none() {
+            return Err(StatusCode::BadUserAccessDenied);
+        }
+        {
+            let mut address_space = address_space.write();
+            let type_tree = context.type_tree.read();
+            address_space.validate_node_write(context, write.value(), &*type_tree)?;
+        }
+        if write.value().attribute_id != AttributeId::Value {
+            return Err(StatusCode::BadNotWritable);
+        }
+        let (name, address) = {
+            let model = self.model.lock().unwrap();
+            let node = model.nodes().iter().find(|node| self.node_id(&node.node_id) == write.value().node_id);
+            let node = node.ok_or(StatusCode::BadNodeIdUnknown)?;
+            let (name, address) = model.write_target(&node.node_id).map_err(|_| StatusCode::BadNotWritable)?;
+            (name.to_string(), address)
+        };
+        match write.value().value.value {
+            Some(Variant::Float(value)) => Ok((name, address, value)),
+            Some(Variant::Double(value)) => Ok((name, address, value as f32)),
+            _ => Err(StatusCode::BadTypeMismatch),
+        }
+    }
+}
+
+#[async_trait]
+impl InMemoryNodeManagerImpl for MeterNodes {
+    async fn init(&self, _address_space: &mut AddressSpace, _context: ServerContext) {}
+
+    fn name(&self) -> &str {
+        "meter"
+    }
+
+    fn namespaces(&self) -> Vec<NamespaceMetadata> {
+        vec![self.namespace.clone()]
+    }
+
+    async fn write(&self, context: &RequestContext, address_space: &RwLock<AddressSpace>, nodes_to_write: &mut [&mut WriteNode]) -> Result<(), StatusCode> {
+        for write in nodes_to_write {
+            let (name, address, value) = match self.parse_write(context, address_space, write) {
+                Ok(target) => target,
+                Err(status) => {
+                    write.set_status(status);
+                    continue;
+                }
+            };
+            let Some(guard) = &self.guard else { continue };
+            info!("OPC UA client writes {} to {}", value, name);
+            let status = self.write_register(guard, &name, address, value).await;
+            if status.is_good() {
+                // Registers also read show the value read back with the next read cycle
+                let mut address_space = address_space.write();
+                if let Some(NodeType::Variable(variable)) = address_space.find_mut(&write.value().node_id) {
+                    let now = DateTime::now();
+                    let written = DataValue {
+                        value: Some(Variant::Float(value)),
+                        status: Some(StatusCode::Good),
+                        source_timestamp: Some(now),
+                        server_timestamp: Some(now),
+                        ..Default::default()
+                    };
+                    variable.set_data_value(written.clone());
+                    context.subscriptions.notify_data_change([(written, &write.value().node_id, AttributeId::Value)].into_iter());
+                }
+            }
+            write.set_status(status);
+        }
+        Ok(())
+    }
+}
+
+/// Adds a property to the variable `parent`.
+fn property(address_space: &mut AddressSpace, parent: &NodeId, name: &str, data_type: DataTypeId, value: ExtensionObject) {
+    let id = NodeId::new(parent.namespace, format!("{}.{}", parent.identifier, name));
+    VariableBuilder::new(&id, name, name)
+        .data_type(data_type)
+        .value(Variant::from(value))
+        .property_of(parent.clone())
+        .has_type_definition(VariableTypeId::PropertyType)
+        .insert(address_space);
+}
+
+/// `ns=2;s=meter-7.voltage_L1_N` of the model as a string node id in namespace `index`.
+fn node_id(index: u16, model_id: &str) -> NodeId {
+    let identifier = model_id.split_once(";s=").map_or(model_id, |(_, identifier)| identifier);
+    NodeId::new(index, identifier.to_string())
+}
+
+/// A value of the model as the server's data value.
+pub fn data_value(value: &information_model::DataValue) -> DataValue {
+    let variant = value.value.as_ref().map(|value| match value {
+        information_model::Variant::Float(value) => Variant::Float(*value),
+        information_model::Variant::String(text) => Variant::from(text.as_str()),
+    });
+    DataValue {
+        value: variant,
+        status: Some(StatusCode::from(value.status.code())),
+        source_timestamp: value.source_timestamp.map(|at| DateTime::from(ChronoDateTime::<Utc>::from(at))),
+        server_timestamp: Some(DateTime::now()),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,141 @@
+// opcua_meter_generic/src/server.rs
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::SystemTime;
+use anyhow::{anyhow, Context as _, Result};
+use opcua::server::node_manager::memory::InMemoryNodeManagerBuilder;
+use opcua::server::{Server, ServerBuilder, ServerHandle};
+use opcua::types::{DataValue, NodeId};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch, Mutex};
+use common_meter_generic::settings::OpcUaSettings;
+use common_meter_generic::{InformationModel, Snapshot, WriteGuard};
+use statemachine_modbus::statemachine::State as ModbusState;
+use statemachine_modbus::StateMachine as ModbusStateMachine;
+use log::{info, warn};
+use crate::nodes::{data_value, MeterNodeManager, MeterNodes};
+
+/// An OPC UA server for one meter on `opc.tcp://<listen>/`, taking anonymous clients without
+/// security. It serves the meter's [`InformationModel`], with values following the read
+/// cycles and the connection state following the Modbus state machine; writes go through
+/// the write path of the gateway, and are only taken on a loopback address.
+pub struct OpcUaServer {
+    server: Server,
+    handle: ServerHandle,
+    listener: TcpListener,
+    model: Arc<StdMutex<InformationModel>>,
+    nodes: Arc<MeterNodeManager>,
+}
+
+impl OpcUaServer {
+    /// Binds `settings.listen` and builds the address space of `model`. Client writes are
+    /// checked by `guard` and sent on the connection of the Modbus state machine; on any other
+    /// than a loopback address, where anyone on the network could write, they are refused.
+    pub async fn bind(
+        settings: &OpcUaSettings,
+        model: InformationModel,
+        guard: WriteGuard,
+        modbus: Arc<Mutex<ModbusStateMachine>>,
+    ) -> Result<Self> {
+        let listen = settings.listen.as_deref().ok_or_else(|| anyhow!("no address to listen on"))?;
+        let listener = TcpListener::bind(listen).await.with_context(|| format!("Failed to listen on {}", listen))?;
+        let address = listener.local_addr()?;
+        let guard = address.ip().is_loopback().then_some(guard);
+        if guard.is_none() {
+            warn!("OPC UA server on {} takes anonymous clients on the network, so it refuses writes", address);
+        }
+
+        let model = Arc::new(StdMutex::new(model));
+        let nodes_model = Arc::clone(&model);
+        let (server, handle) = ServerBuilder::new_anonymous("mgw_generic")
+            // Namespace index 1, owned by the server's diagnostics; the meter's nodes come next
+            .application_uri("urn:mgw_generic")
+            .product_uri("urn:mgw_generic")
+            .host(address.ip().to_string())
+            .port(address.port())
+            .discovery_urls(vec![endpoint_url(address)])
+            .pki_dir(&settings.pki_dir)
+            .create_sample_keypair(true)
+            .with_node_manager(InMemoryNodeManagerBuilder::new(move |context, address_space: &mut _| {
+                MeterNodes::new(context, address_space, nodes_model, guard, modbus)
+            }))
+            .build()
+            .map_err(|e| anyhow!("Failed to set up the OPC UA server: {}", e))?;
+        let nodes = handle
+            .node_managers()
+            .get_of_type::<MeterNodeManager>()
+            .ok_or_else(|| anyhow!("OPC UA server without the meter's nodes"))?;
+        Ok(OpcUaServer { server, handle, listener, model, nodes })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// The URL clients connect to, e.g. `opc.tcp://127.0.0.1:4840/`.
+    pub fn endpoint_url(&self) -> Result<String> {
+        Ok(endpoint_url(self.local_addr()?))
+    }
+
+    /// Node id in the server's address space of a node of the model, e.g. `ns=2;s=meter-7`.
+    pub fn node_id(&self, model_id: &str) -> NodeId {
+        self.nodes.inner().node_id(model_id)
+    }
+
+    /// Serves clients until the snapshots stop, updating the values from `snapshots` and the
+    /// connection state from `state`.
+    pub async fn run(self, mut snapshots: broadcast::Receiver<Snapshot>, mut state: watch::Receiver<ModbusState>) -> Result<()> {
+        let OpcUaServer { server, handle, listener, model, nodes } = self;
+        info!("OPC UA server listening on {}", endpoint_url(listener.local_addr()?));
+        let publish = |model: &InformationModel| {
+            let values: Vec<(NodeId, DataValue)> = model
+                .nodes()
+                .iter()
+                .filter_map(|node| Some((nodes.inner().node_id(&node.node_id), data_value(model.value(&node.node_id)?))))
+                .collect();
+            if let Err(e) = nodes.set_values(handle.subscriptions(), values.iter().map(|(id, value)| (id, None, value.clone()))) {
+                warn!("Failed to update the OPC UA values: {}", e);
+            }
+        };
+        let publish_state = |state: ModbusState| {
+            let mut updated = model.lock().unwrap();
+            updated.set_connection_state(&format!("{:?}", state), SystemTime::now());
+            publish(&updated);
+        };
+        let feed = async {
+            publish_state(*state.borrow_and_update());
+            loop {
+                tokio::select! {
+                    snapshot = snapshots.recv() => match snapshot {
+                        Ok(snapshot) => {
+                            let mut updated = model.lock().unwrap();
+                            updated.update(&snapshot);
+                            publish(&updated);
+                        }
+                        Err(RecvError::Lagged(missed)) => warn!("OPC UA server missed {} snapshots", missed),
+                        Err(RecvError::Closed) => break,
+                    },
+                    changed = state.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        publish_state(*state.borrow_and_update());
+                    }
+                }
+            }
+        };
+        tokio::select! {
+            served = server.run_with(listener) => served.map_err(|e| anyhow!("OPC UA server stopped: {}", e)),
+            _ = feed => {
+                handle.cancel();
+                Ok(())
+            }
+        }
+    }
+}
+
+fn endpoint_url(address: SocketAddr) -> String {
+    format!("opc.tcp://{}/", address)
+}
@@ -0,0 +1,261 @@
+// opcua_meter_generic/tests/server.rs
+//
+// An OPC UA client browsing, reading and writing the meter's nodes, served from the read
+// cycles of a read state machine against the simulator over an in-memory stream.
+
+use std::sync::Arc;
+use std::time::Duration;
+use opcua::client::{ClientBuilder, IdentityToken, Session};
+use opcua::crypto::SecurityPolicy;
+use opcua::types::{
+    AttributeId, BrowseDescription, BrowseDirection, BrowseResultMask, DataValue, EUInformation, MessageSecurityMode,
+    NodeId, ObjectId, ReadValueId, ReferenceTypeId, StatusCode, TimestampsToReturn, Variant, WriteValue,
+};
+use tokio::sync::Mutex;
+use tokio_modbus::client::tcp;
+use config_meter_generic::config::Config;
+use common_meter_generic::{InformationModel, MeterClient, Settings, WriteGuard};
+use opcua_meter_generic::OpcUaServer;
+use simulator_meter_generic::{SimulatedRegister, Simulator, SimulatorProfile, Waveform};
+use statemachine_modbus::statemachine::{Event, State as ModbusState, StateMachine as ModbusStateMachine};
+use statemachine_modbus::transport::{MockReachability, MockTransport};
+use statemachine_read::statemachine::{State, StateMachine};
+
+const CONFIG: &str = r#"
+meter_data:
+  ip: "10.15.1.2"
+  port: 502
+  meter_type: "Mock"
+write_registers:
+  - name: power_factor_L1
+    address: 32816
+    value: 0.95
+read_registers:
+  - name: voltage_L1_N
+    address: 32774
+debug:
+  mgw_generic: "off"
+  statemachine_modbus: "off"
+  statemachine_read: "off"
+"#;
+
+const SETTINGS: &str = r#"
+opcua:
+  object_name: meter-7
+profiles:
+  Mock:
+    registers:
+      voltage_L1_N: { unit: V, min: 0, max: 300 }
+writes:
+  limits:
+    power_factor_L1: { min: 0, max: 1 }
+  # The order the simulator decodes in
+  word_order:
+    power_factor_L1: high_first
+"#;
+
+fn simulator() -> Simulator {
+    let register = |name: &str, address, value| SimulatedRegister {
+        name: name.to_string(),
+        address,
+        waveform: Waveform::Constant { value },
+    };
+    Simulator::new(SimulatorProfile {
+        registers: vec![register("voltage_L1_N", 32774, 230.0), register("power_factor_L1", 32816, 0.9)],
+        ..Default::default()
+    })
+}
+
+/// A server on `listen` for the meter on `simulator`, fed by one read cycle, and the state machines.
+async fn serve(simulator: &Simulator, listen: &str, pki_dir: &str) -> (String, Arc<Mutex<StateMachine>>, Arc<Mutex<ModbusStateMachine>>) {
+    let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+    let mut settings: Settings = serde_yaml::from_str(SETTINGS).unwrap();
+    settings.opcua.listen = Some(listen.to_string());
+    settings.opcua.pki_dir = pki_dir.to_string();
+    let (client, server) = tokio::io::duplex(1024);
+    let serving = simulator.clone();
+    tokio::spawn(async move { serving.serve_connection(server).await });
+    let context = Arc::new(Mutex::new(MeterClient::new(tcp::attach(client), &settings.modbus)));
+
+    let read: Vec<(&str, u16)> = config.read_registers.iter().map(|r| (r.name.as_str(), r.address)).collect();
+    let write: Vec<(&str, u16)> = config.write_registers.iter().map(|r| (r.name.as_str(), r.address)).collect();
+    let model = InformationModel::new(&settings.opcua, "Mock", &settings.profile("Mock"), &read, &write);
+    let guard = WriteGuard::new(&settings.writes, &settings.profile("Mock"), "opcua");
+    let opcua = settings.opcua.clone();
+
+    let config = Arc::new(Mutex::new(config));
+    let settings = Arc::new(Mutex::new(settings));
+    let modbus = ModbusStateMachine::with_transport(
+        config.clone(),
+        settings.clone(),
+        Arc::new(MockTransport::new()),
+        Arc::new(MockReachability::new(true)),
+    );
+    let state = {
+        let mut modbus = modbus.lock().await;
+        modbus.state = ModbusState::Verify;
+        modbus.modbus_context = Some(context);
+        let state = modbus.history.subscribe_state();
+        modbus.history.record(ModbusState::Connect, Event::SocketExists, ModbusState::Verify);
+        state
+    };
+
+    let server = OpcUaServer::bind(&opcua, model, guard, Arc::clone(&modbus)).await.unwrap();
+    let url = server.endpoint_url().unwrap();
+    let read = StateMachine::new(config, settings, Arc::clone(&modbus));
+    {
+        let mut read = read.lock().await;
+        tokio::spawn(server.run(read.subscribe_snapshots(), state));
+        read.state = State::Read;
+        read.step().await;
+    }
+    (url, read, modbus)
+}
+
+async fn connect(url: &str, pki_dir: &str) -> Arc<Session> {
+    let mut client = ClientBuilder::new()
+        .application_name("mgw_generic test client")
+        .application_uri("urn:mgw_generic:test")
+        .pki_dir(pki_dir)
+        .create_sample_keypair(true)
+        .trust_server_certs(true)
+        .session_retry_limit(3)
+        .client()
+        .unwrap();
+    let endpoint = (url, SecurityPolicy::None.to_str(), MessageSecurityMode::None);
+    let (session, event_loop) = client.connect_to_matching_endpoint(endpoint, IdentityToken::Anonymous).await.unwrap();
+    event_loop.spawn();
+    assert!(session.wait_for_connection().await);
+    session
+}
+
+/// Browse names and node ids of the nodes below `node_id`.
+async fn browse(session: &Session, node_id: NodeId) -> Vec<(String, NodeId)> {
+    let description = BrowseDescription {
+        node_id,
+        browse_direction: BrowseDirection::Forward,
+        reference_type_id: ReferenceTypeId::HierarchicalReferences.into(),
+        include_subtypes: true,
+        node_class_mask: 0,
+        result_mask: BrowseResultMask::All as u32,
+    };
+    let results = session.browse(&[description], 1000, None).await.unwrap();
+    results[0]
+        .references
+        .iter()
+        .flatten()
+        .map(|reference| (reference.browse_name.name.to_string(), reference.node_id.node_id.clone()))
+        .collect()
+}
+
+fn child(children: &[(String, NodeId)], name: &str) -> NodeId {
+    children.iter().find(|(browse_name, _)| browse_name == name).unwrap_or_else(|| panic!("no node {}", name)).1.clone()
+}
+
+async fn read_value(session: &Session, node_id: &NodeId) -> DataValue {
+    let read = ReadValueId { node_id: node_id.clone(), attribute_id: AttributeId::Value as u32, ..Default::default() };
+    session.read(&[read], TimestampsToReturn::Both, 0.0).await.unwrap().remove(0)
+}
+
+async fn write_value(session: &Session, node_id: &NodeId, value: f32) -> StatusCode {
+    let write = WriteValue {
+        node_id: node_id.clone(),
+        attribute_id: AttributeId::Value as u32,
+        value: DataValue::value_only(Variant::Float(value)),
+        ..Default::default()
+    };
+    session.write(&[write]).await.unwrap()[0]
+}
+
+fn pki_dir(test: &str) -> String {
+    let path = std::env::temp_dir().join(format!("mgw_opcua_{}_{}", test, std::process::id()));
+    path.to_str().unwrap().to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serves_the_registers_units_and_connection_state() {
+    let simulator = simulator();
+    let (server_pki, client_pki) = (pki_dir("browse_server"), pki_dir("browse_client"));
+    let (url, _read, modbus) = serve(&simulator, "127.0.0.1:0", &server_pki).await;
+    let session = connect(&url, &client_pki).await;
+
+    let objects = browse(&session, ObjectId::ObjectsFolder.into()).await;
+    let meter = child(&objects, "meter-7");
+    assert_eq!(meter, NodeId::new(2, "meter-7"));
+    let variables = browse(&session, meter).await;
+    let mut names: Vec<&str> = variables.iter().map(|(name, _)| name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["ConnectionState", "power_factor_L1", "voltage_L1_N"]);
+
+    let voltage = child(&variables, "voltage_L1_N");
+    let value = read_value(&session, &voltage).await;
+    assert_eq!(value.value, Some(Variant::Float(230.0)));
+    assert_eq!(value.status, Some(StatusCode::Good));
+    assert!(value.source_timestamp.is_some());
+
+    let properties = browse(&session, voltage).await;
+    let units = read_value(&session, &child(&properties, "EngineeringUnits")).await;
+    let Some(Variant::ExtensionObject(units)) = units.value else { panic!("no engineering units") };
+    assert_eq!(units.inner_as::<EUInformation>().unwrap().display_name.text.as_ref(), "V");
+
+    let connection_state = child(&variables, "ConnectionState");
+    assert_eq!(read_value(&session, &connection_state).await.value, Some(Variant::from("Verify")));
+    // Follows the transitions of the Modbus state machine
+    modbus.lock().await.history.record(ModbusState::Verify, Event::VerificationFail, ModbusState::Idle);
+    let mut state = read_value(&session, &connection_state).await;
+    for _ in 0..50 {
+        if state.value == Some(Variant::from("Idle")) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        state = read_value(&session, &connection_state).await;
+    }
+    assert_eq!(state.value, Some(Variant::from("Idle")));
+
+    session.disconnect().await.unwrap();
+    let _ = std::fs::remove_dir_all(server_pki);
+    let _ = std::fs::remove_dir_all(client_pki);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_through_the_write_guard() {
+    let simulator = simulator();
+    let (server_pki, client_pki) = (pki_dir("write_server"), pki_dir("write_client"));
+    let (url, _read, _modbus) = serve(&simulator, "127.0.0.1:0", &server_pki).await;
+    let session = connect(&url, &client_pki).await;
+    let power_factor = NodeId::new(2, "meter-7.power_factor_L1");
+
+    assert_eq!(write_value(&session, &power_factor, 0.97).await, StatusCode::Good);
+    assert_eq!(simulator.value("power_factor_L1").await, Some(0.97));
+    assert_eq!(read_value(&session, &power_factor).await.value, Some(Variant::Float(0.97)));
+
+    // Beyond writes.limits, so the meter keeps its value
+    assert_eq!(write_value(&session, &power_factor, 1.5).await, StatusCode::BadOutOfRange);
+    assert_eq!(simulator.value("power_factor_L1").await, Some(0.97));
+
+    // Registers only read take no writes
+    let voltage = NodeId::new(2, "meter-7.voltage_L1_N");
+    assert!(write_value(&session, &voltage, 100.0).await.is_bad());
+    assert_eq!(simulator.value("voltage_L1_N").await, Some(230.0));
+
+    session.disconnect().await.unwrap();
+    let _ = std::fs::remove_dir_all(server_pki);
+    let _ = std::fs::remove_dir_all(client_pki);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_writes_beyond_the_loopback_interface() {
+    let simulator = simulator();
+    let (server_pki, client_pki) = (pki_dir("network_server"), pki_dir("network_client"));
+    let (url, _read, _modbus) = serve(&simulator, "0.0.0.0:0", &server_pki).await;
+    let session = connect(&url.replace("0.0.0.0", "127.0.0.1"), &client_pki).await;
+    let power_factor = NodeId::new(2, "meter-7.power_factor_L1");
+
+    assert_eq!(read_value(&session, &NodeId::new(2, "meter-7.voltage_L1_N")).await.value, Some(Variant::Float(230.0)));
+    assert_eq!(write_value(&session, &power_factor, 0.97).await, StatusCode::BadUserAccessDenied);
+    assert_eq!(simulator.value("power_factor_L1").await, Some(0.9));
+
+    session.disconnect().await.unwrap();
+    let _ = std::fs::remove_dir_all(server_pki);
+    let _ = std::fs::remove_dir_all(client_pki);
+}
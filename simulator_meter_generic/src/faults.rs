@@ -0,0 +1,51 @@
+// simulator_meter_generic/src/faults.rs
+
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// Misbehaviour injected into the simulator's responses.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Faults {
+    /// Delay before every response
+    pub delay_ms: u64,
+    /// Exception responses for matching requests
+    pub exceptions: Vec<ExceptionFault>,
+    /// Close the connection instead of answering every n-th request
+    pub drop_every: Option<u32>,
+    /// RTU slave addresses that never answer, as if they were off the bus
+    pub silent_slaves: Vec<u8>,
+}
+
+impl Faults {
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(self.delay_ms)
+    }
+
+    /// Returns the exception code to answer with, if a rule matches the request.
+    pub fn exception_for(&self, function: u8, address: u16, quantity: u16) -> Option<u8> {
+        self.exceptions
+            .iter()
+            .find(|fault| fault.matches(function, address, quantity))
+            .map(|fault| fault.code)
+    }
+}
+
+/// Answers requests touching `start..=end` with the exception `code`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExceptionFault {
+    /// Function code to match; all functions when unset
+    #[serde(default)]
+    pub function: Option<u8>,
+    pub start: u16,
+    pub end: u16,
+    /// Modbus exception code, e.g. 2 (illegal data address) or 6 (server device busy)
+    pub code: u8,
+}
+
+impl ExceptionFault {
+    fn matches(&self, function: u8, address: u16, quantity: u16) -> bool {
+        let last = address.saturating_add(quantity.max(1) - 1);
+        self.function.is_none_or(|f| f == function) && address <= self.end && last >= self.start
+    }
+}
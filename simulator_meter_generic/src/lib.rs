@@ -0,0 +1,9 @@
+pub mod faults;
+pub mod profile;
+pub mod server;
+pub mod waveform;
+
+pub use faults::{ExceptionFault, Faults};
+pub use profile::{SimulatedDevice, SimulatedRegister, SimulatorProfile};
+pub use server::{RequestRecord, Simulator, SimulatorHandle};
+pub use waveform::Waveform;
@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use common_meter_generic::tls;
+use common_meter_generic::settings::TlsSettings;
+use simulator_meter_generic::{Simulator, SimulatorProfile};
+
+/// Modbus TCP meter simulator serving the register map of a gateway configuration.
+#[derive(Debug, Parser)]
+#[command(name = "simulator_meter_generic", version)]
+struct Args {
+    /// Configuration or profile file providing `read_registers` and the optional `simulator` section
+    #[arg(short, long, default_value = "mgw_config.yaml")]
+    config: String,
+    /// Address to listen on
+    #[arg(short, long, default_value = "127.0.0.1:5020")]
+    listen: String,
+    /// Speak RTU frames over TCP, like an Ethernet-to-RS-485 converter
+    #[arg(long, conflicts_with = "tls_cert")]
+    rtu_over_tcp: bool,
+    /// Serve Modbus/TCP Security with this PEM certificate chain
+    #[arg(long, requires_all = ["tls_key", "tls_ca"])]
+    tls_cert: Option<String>,
+    /// PEM private key of the TLS certificate
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<String>,
+    /// PEM certificates of the authorities client certificates must be issued by
+    #[arg(long, requires = "tls_cert")]
+    tls_ca: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+
+    let profile = SimulatorProfile::from_file(&args.config)?;
+    let simulator = Simulator::new(profile);
+    let handle = match (args.tls_cert, args.tls_key, args.tls_ca) {
+        (Some(cert_file), Some(key_file), Some(ca_file)) => {
+            let acceptor = tls::acceptor(&TlsSettings { cert_file, key_file, ca_file, server_name: None })?;
+            simulator.bind_tls(&args.listen, acceptor).await
+        }
+        _ if args.rtu_over_tcp => simulator.bind_rtu_over_tcp(&args.listen).await,
+        _ => simulator.bind(&args.listen).await,
+    }
+    .with_context(|| format!("Unable to listen on {}", args.listen))?;
+    println!("Simulating meter on {}, press Ctrl+C to stop", handle.local_addr());
+
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
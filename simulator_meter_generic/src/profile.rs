@@ -0,0 +1,114 @@
+// simulator_meter_generic/src/profile.rs
+
+use std::collections::HashMap;
+use std::fs::File;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use config_meter_generic::config::ConfigRegister;
+use crate::faults::Faults;
+use crate::waveform::Waveform;
+
+/// One simulated register, served as a big-endian IEEE 754 float over two words.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedRegister {
+    pub name: String,
+    pub address: u16,
+    pub waveform: Waveform,
+}
+
+/// The register map and fault settings the simulator serves.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimulatorProfile {
+    pub registers: Vec<SimulatedRegister>,
+    pub faults: Faults,
+    /// Answer reads of unmapped addresses with an illegal data address exception instead of zeros
+    pub strict_addresses: bool,
+    /// Answer to Read Device Identification; the function is illegal without one
+    pub device: Option<SimulatedDevice>,
+}
+
+/// Basic objects served by Read Device Identification (function 43/14).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SimulatedDevice {
+    pub vendor: String,
+    pub product_code: String,
+    pub revision: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileFile {
+    #[serde(default)]
+    read_registers: Vec<ConfigRegister>,
+    #[serde(default)]
+    write_registers: Vec<WriteRegisterEntry>,
+    #[serde(default)]
+    simulator: SimulatorSection,
+}
+
+#[derive(Debug, Deserialize)]
+struct WriteRegisterEntry {
+    name: String,
+    address: u16,
+    value: f32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SimulatorSection {
+    waveforms: HashMap<String, Waveform>,
+    faults: Faults,
+    strict_addresses: bool,
+    device: Option<SimulatedDevice>,
+}
+
+impl SimulatorProfile {
+    /// Serves every register as a constant zero.
+    pub fn from_registers(registers: &[ConfigRegister]) -> Self {
+        SimulatorProfile {
+            registers: registers
+                .iter()
+                .map(|register| SimulatedRegister {
+                    name: register.name.clone(),
+                    address: register.address,
+                    waveform: Waveform::default(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Loads the register map from a gateway configuration or a `dump-profile` output.
+    ///
+    /// Read registers are served with the waveform named in the optional `simulator` section
+    /// (constant zero otherwise); write registers not also read start at their configured value.
+    pub fn from_file(file_path: &str) -> Result<Self> {
+        let file = File::open(file_path).with_context(|| format!("Unable to open {}", file_path))?;
+        let mut parsed: ProfileFile =
+            serde_yaml::from_reader(file).with_context(|| format!("Unable to parse {}", file_path))?;
+
+        let mut profile = SimulatorProfile::from_registers(&parsed.read_registers);
+        for register in &mut profile.registers {
+            if let Some(waveform) = parsed.simulator.waveforms.remove(&register.name) {
+                register.waveform = waveform;
+            }
+        }
+        for entry in parsed.write_registers {
+            if profile.registers.iter().all(|r| r.address != entry.address) {
+                profile.registers.push(SimulatedRegister {
+                    name: entry.name,
+                    address: entry.address,
+                    waveform: Waveform::Constant { value: entry.value },
+                });
+            }
+        }
+        if let Some(name) = parsed.simulator.waveforms.keys().next() {
+            anyhow::bail!("Waveform given for unknown register {}", name);
+        }
+
+        profile.faults = parsed.simulator.faults;
+        profile.strict_addresses = parsed.simulator.strict_addresses;
+        profile.device = parsed.simulator.device;
+        Ok(profile)
+    }
+}
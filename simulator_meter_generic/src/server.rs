@@ -0,0 +1,426 @@
+// simulator_meter_generic/src/server.rs
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Instant};
+use tokio_rustls::TlsAcceptor;
+use log::{info, warn};
+use crate::faults::Faults;
+use crate::profile::{SimulatedDevice, SimulatorProfile};
+use crate::waveform::Signal;
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+/// Function code of Read Device Identification, and its MEI type.
+const ENCAPSULATED_INTERFACE: u8 = 0x2B;
+const READ_DEVICE_ID: u8 = 0x0E;
+
+/// Largest register count a single read may request.
+const MAX_READ_QUANTITY: u16 = 125;
+/// Largest register count a single write may carry.
+const MAX_WRITE_QUANTITY: u16 = 123;
+
+/// A request the simulator received, kept so tests can check what the gateway asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestRecord {
+    pub function: u8,
+    pub address: u16,
+    pub quantity: u16,
+}
+
+/// Register values: waveforms, overridden by whatever the client wrote.
+#[derive(Debug)]
+struct RegisterBank {
+    signals: BTreeMap<u16, Signal>,
+    names: HashMap<String, u16>,
+    written: HashMap<u16, u16>,
+    strict_addresses: bool,
+    started: Instant,
+}
+
+impl RegisterBank {
+    fn new(profile: &SimulatorProfile) -> Self {
+        let mut signals = BTreeMap::new();
+        let mut names = HashMap::new();
+        for register in &profile.registers {
+            signals.insert(register.address, Signal::new(register.waveform.clone(), u64::from(register.address)));
+            names.insert(register.name.clone(), register.address);
+        }
+        RegisterBank {
+            signals,
+            names,
+            written: HashMap::new(),
+            strict_addresses: profile.strict_addresses,
+            started: Instant::now(),
+        }
+    }
+
+    fn read(&mut self, address: u16, quantity: u16) -> Result<Vec<u16>, u8> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        // Sample each register once per request so both words belong to the same value
+        let mut sampled: HashMap<u16, u32> = HashMap::new();
+        let mut words = Vec::with_capacity(quantity as usize);
+
+        for offset in 0..quantity {
+            let current = address.checked_add(offset).ok_or(ILLEGAL_DATA_ADDRESS)?;
+            if let Some(word) = self.written.get(&current) {
+                words.push(*word);
+                continue;
+            }
+            let (start, high) = if self.signals.contains_key(&current) {
+                (current, true)
+            } else if current > 0 && self.signals.contains_key(&(current - 1)) {
+                (current - 1, false)
+            } else if self.strict_addresses {
+                return Err(ILLEGAL_DATA_ADDRESS);
+            } else {
+                words.push(0);
+                continue;
+            };
+            let signals = &mut self.signals;
+            let bits = *sampled
+                .entry(start)
+                .or_insert_with(|| signals.get_mut(&start).map_or(0, |s| s.sample(elapsed).to_bits()));
+            words.push(if high { (bits >> 16) as u16 } else { bits as u16 });
+        }
+        Ok(words)
+    }
+
+    fn write(&mut self, address: u16, words: &[u16]) -> Result<(), u8> {
+        if address.checked_add(words.len() as u16).is_none() {
+            return Err(ILLEGAL_DATA_ADDRESS);
+        }
+        for (offset, word) in words.iter().enumerate() {
+            self.written.insert(address + offset as u16, *word);
+        }
+        Ok(())
+    }
+}
+
+/// Modbus TCP server simulating a meter.
+///
+/// Cloning is cheap and every clone serves the same registers, faults and request log.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    bank: Arc<Mutex<RegisterBank>>,
+    faults: Arc<Mutex<Faults>>,
+    requests: Arc<Mutex<Vec<RequestRecord>>>,
+    device: Option<Arc<SimulatedDevice>>,
+}
+
+impl Simulator {
+    pub fn new(profile: SimulatorProfile) -> Self {
+        Simulator {
+            bank: Arc::new(Mutex::new(RegisterBank::new(&profile))),
+            faults: Arc::new(Mutex::new(profile.faults)),
+            requests: Arc::new(Mutex::new(Vec::new())),
+            device: profile.device.map(Arc::new),
+        }
+    }
+
+    /// Starts accepting connections; use port 0 to let the system pick a free one.
+    pub async fn bind<A: ToSocketAddrs>(self, address: A) -> io::Result<SimulatorHandle> {
+        self.listen(address, None, false).await
+    }
+
+    /// Like `bind`, but every connection starts with a TLS handshake (Modbus/TCP Security).
+    pub async fn bind_tls<A: ToSocketAddrs>(self, address: A, acceptor: TlsAcceptor) -> io::Result<SimulatorHandle> {
+        self.listen(address, Some(acceptor), false).await
+    }
+
+    /// Like `bind`, but speaks RTU frames over TCP, as an Ethernet-to-RS-485 converter passes them.
+    pub async fn bind_rtu_over_tcp<A: ToSocketAddrs>(self, address: A) -> io::Result<SimulatorHandle> {
+        self.listen(address, None, true).await
+    }
+
+    async fn listen<A: ToSocketAddrs>(self, address: A, acceptor: Option<TlsAcceptor>, rtu: bool) -> io::Result<SimulatorHandle> {
+        let listener = TcpListener::bind(address).await?;
+        let local_addr = listener.local_addr()?;
+        let mode = match (&acceptor, rtu) {
+            (Some(_), _) => " (TLS)",
+            (None, true) => " (RTU over TCP)",
+            (None, false) => "",
+        };
+        info!("Simulator listening on {}{}", local_addr, mode);
+
+        let simulator = self.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        info!("Simulator accepted connection from {}", peer);
+                        let simulator = simulator.clone();
+                        let acceptor = acceptor.clone();
+                        tokio::spawn(async move {
+                            let served = match acceptor {
+                                Some(acceptor) => match acceptor.accept(stream).await {
+                                    Ok(stream) => simulator.serve_connection(stream).await,
+                                    Err(e) => Err(e),
+                                },
+                                None if rtu => simulator.serve_rtu_connection(stream).await,
+                                None => simulator.serve_connection(stream).await,
+                            };
+                            if let Err(e) = served {
+                                warn!("Simulator connection from {} ended: {}", peer, e);
+                            }
+                        });
+                    }
+                    Err(e) => warn!("Simulator failed to accept a connection: {}", e),
+                }
+            }
+        });
+
+        Ok(SimulatorHandle { local_addr, simulator: self, task })
+    }
+
+    /// Answers Modbus TCP requests on `stream` until the client disconnects or a fault drops it.
+    pub async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin>(&self, mut stream: S) -> io::Result<()> {
+        let mut served: u32 = 0;
+        loop {
+            let mut header = [0u8; 7];
+            match stream.read_exact(&mut header).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            }
+            let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+            if !(2..=254).contains(&length) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid MBAP length {}", length)));
+            }
+            let mut pdu = vec![0u8; length - 1];
+            stream.read_exact(&mut pdu).await?;
+
+            let Some(response) = self.answer(&pdu, &mut served).await else {
+                return Ok(());
+            };
+
+            let mut frame = Vec::with_capacity(7 + response.len());
+            frame.extend_from_slice(&header[0..4]);
+            frame.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+            frame.push(header[6]);
+            frame.extend_from_slice(&response);
+            stream.write_all(&frame).await?;
+        }
+    }
+
+    /// Answers RTU frames (slave address, PDU, CRC) on `stream`, as behind a TCP-to-serial
+    /// converter, for every slave address but the silent ones. Frames with a bad CRC go
+    /// unanswered, like on a serial line.
+    pub async fn serve_rtu_connection<S: AsyncRead + AsyncWrite + Unpin>(&self, mut stream: S) -> io::Result<()> {
+        let mut served: u32 = 0;
+        loop {
+            // Slave address and function code tell how long the rest of the frame is
+            let mut frame = vec![0u8; 2];
+            match stream.read_exact(&mut frame).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            }
+            let rest = match frame[1] {
+                0x03 | 0x04 | 0x06 => 6,
+                ENCAPSULATED_INTERFACE => 5,
+                0x10 => {
+                    let mut header = [0u8; 5];
+                    stream.read_exact(&mut header).await?;
+                    frame.extend_from_slice(&header);
+                    header[4] as usize + 2
+                }
+                function => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported RTU function {:#04x}", function)));
+                }
+            };
+            let start = frame.len();
+            frame.resize(start + rest, 0);
+            stream.read_exact(&mut frame[start..]).await?;
+
+            let (body, crc) = frame.split_at(frame.len() - 2);
+            if crc16(body).to_le_bytes() != crc {
+                warn!("Simulator ignoring an RTU frame with a bad CRC");
+                continue;
+            }
+            if self.faults.lock().await.silent_slaves.contains(&body[0]) {
+                continue;
+            }
+            let Some(response) = self.answer(&body[1..], &mut served).await else {
+                return Ok(());
+            };
+
+            let mut reply = Vec::with_capacity(response.len() + 3);
+            reply.push(body[0]);
+            reply.extend_from_slice(&response);
+            let crc = crc16(&reply);
+            reply.extend_from_slice(&crc.to_le_bytes());
+            stream.write_all(&reply).await?;
+        }
+    }
+
+    /// Applies the injected faults and handles one request; `None` means drop the connection.
+    async fn answer(&self, pdu: &[u8], served: &mut u32) -> Option<Vec<u8>> {
+        *served = served.wrapping_add(1);
+        let faults = self.faults.lock().await.clone();
+        if faults.drop_every.is_some_and(|n| n > 0 && served.is_multiple_of(n)) {
+            warn!("Simulator dropping the connection (fault injection)");
+            return None;
+        }
+        if faults.delay_ms > 0 {
+            sleep(faults.delay()).await;
+        }
+
+        Some(match self.handle_pdu(pdu, &faults).await {
+            Ok(response) => response,
+            Err(code) => vec![pdu[0] | 0x80, code],
+        })
+    }
+
+    async fn handle_pdu(&self, pdu: &[u8], faults: &Faults) -> Result<Vec<u8>, u8> {
+        let function = pdu[0];
+        if function == ENCAPSULATED_INTERFACE {
+            self.requests.lock().await.push(RequestRecord { function, address: 0, quantity: 0 });
+            return self.identify_device(pdu);
+        }
+        let field = |index: usize| -> Result<u16, u8> {
+            pdu.get(index..index + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .ok_or(ILLEGAL_DATA_VALUE)
+        };
+        let address = field(1)?;
+        let quantity = match function {
+            0x06 => 1,
+            _ => field(3)?,
+        };
+        self.requests.lock().await.push(RequestRecord { function, address, quantity });
+
+        if let Some(code) = faults.exception_for(function, address, quantity) {
+            warn!("Simulator answering function {:#04x} at {} with exception {:#04x}", function, address, code);
+            return Err(code);
+        }
+
+        let mut bank = self.bank.lock().await;
+        match function {
+            0x03 | 0x04 => {
+                if quantity == 0 || quantity > MAX_READ_QUANTITY {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                let words = bank.read(address, quantity)?;
+                let mut response = vec![function, (words.len() * 2) as u8];
+                for word in words {
+                    response.extend_from_slice(&word.to_be_bytes());
+                }
+                Ok(response)
+            }
+            0x06 => {
+                let value = field(3)?;
+                bank.write(address, &[value])?;
+                Ok(pdu[..5].to_vec())
+            }
+            0x10 => {
+                let byte_count = *pdu.get(5).ok_or(ILLEGAL_DATA_VALUE)? as usize;
+                if quantity == 0 || quantity > MAX_WRITE_QUANTITY || byte_count != quantity as usize * 2 {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                let data = pdu.get(6..6 + byte_count).ok_or(ILLEGAL_DATA_VALUE)?;
+                let words: Vec<u16> = data.chunks(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect();
+                bank.write(address, &words)?;
+                Ok(pdu[..5].to_vec())
+            }
+            _ => Err(ILLEGAL_FUNCTION),
+        }
+    }
+
+    /// Answers Read Device Identification with all basic objects from the requested one on.
+    fn identify_device(&self, pdu: &[u8]) -> Result<Vec<u8>, u8> {
+        let device = self.device.as_ref().ok_or(ILLEGAL_FUNCTION)?;
+        let [_, READ_DEVICE_ID, code @ 1..=4, first, ..] = *pdu else {
+            return Err(ILLEGAL_DATA_VALUE);
+        };
+        let objects = [&device.vendor, &device.product_code, &device.revision];
+        if first as usize >= objects.len() {
+            return Err(ILLEGAL_DATA_ADDRESS);
+        }
+
+        // Basic conformity level, everything in one response
+        let mut response = vec![ENCAPSULATED_INTERFACE, READ_DEVICE_ID, code, 0x01, 0x00, 0x00, 0];
+        for (id, value) in objects.iter().enumerate().skip(first as usize) {
+            response.extend_from_slice(&[id as u8, value.len() as u8]);
+            response.extend_from_slice(value.as_bytes());
+            response[6] += 1;
+        }
+        Ok(response)
+    }
+
+    /// Replaces the injected faults; takes effect with the next request.
+    pub async fn set_faults(&self, faults: Faults) {
+        *self.faults.lock().await = faults;
+    }
+
+    /// Current value of a register by name, including values written by the client.
+    pub async fn value(&self, name: &str) -> Option<f32> {
+        let mut bank = self.bank.lock().await;
+        let address = *bank.names.get(name)?;
+        let words = bank.read(address, 2).ok()?;
+        Some(f32::from_bits(((words[0] as u32) << 16) | words[1] as u32))
+    }
+
+    /// Overrides a register by name, as if the client had written it.
+    pub async fn set_value(&self, name: &str, value: f32) -> bool {
+        let mut bank = self.bank.lock().await;
+        let Some(address) = bank.names.get(name).copied() else {
+            return false;
+        };
+        let bits = value.to_bits();
+        bank.write(address, &[(bits >> 16) as u16, bits as u16]).is_ok()
+    }
+
+    /// Requests received so far, oldest first.
+    pub async fn requests(&self) -> Vec<RequestRecord> {
+        self.requests.lock().await.clone()
+    }
+
+    pub async fn clear_requests(&self) {
+        self.requests.lock().await.clear();
+    }
+}
+
+/// A running simulator; the listener stops when the handle is dropped.
+#[derive(Debug)]
+pub struct SimulatorHandle {
+    local_addr: SocketAddr,
+    simulator: Simulator,
+    task: JoinHandle<()>,
+}
+
+impl SimulatorHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn simulator(&self) -> &Simulator {
+        &self.simulator
+    }
+}
+
+impl Drop for SimulatorHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// CRC-16/MODBUS of an RTU frame, sent low byte first.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF;
+    for &byte in data {
+        crc ^= u16::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
@@ -0,0 +1,107 @@
+// simulator_meter_generic/src/waveform.rs
+
+use std::f64::consts::TAU;
+use serde::{Deserialize, Serialize};
+
+/// Shape of the value served for one simulated register.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Waveform {
+    Constant {
+        value: f32,
+    },
+    Sine {
+        offset: f32,
+        amplitude: f32,
+        period_secs: f32,
+        #[serde(default)]
+        phase_deg: f32,
+    },
+    /// Sawtooth from `from` to `to`, starting over every `period_secs`.
+    Ramp {
+        from: f32,
+        to: f32,
+        period_secs: f32,
+    },
+    /// Moves by up to `step` in either direction on every read, kept within `min..=max`.
+    RandomWalk {
+        start: f32,
+        step: f32,
+        min: f32,
+        max: f32,
+    },
+}
+
+impl Default for Waveform {
+    fn default() -> Self {
+        Waveform::Constant { value: 0.0 }
+    }
+}
+
+/// A waveform together with the state it needs between reads.
+#[derive(Debug, Clone)]
+pub struct Signal {
+    waveform: Waveform,
+    current: f32,
+    rng: XorShift,
+}
+
+impl Signal {
+    /// Creates the signal; `seed` makes random walks reproducible.
+    pub fn new(waveform: Waveform, seed: u64) -> Self {
+        let current = match &waveform {
+            Waveform::RandomWalk { start, .. } => *start,
+            _ => 0.0,
+        };
+        Signal { waveform, current, rng: XorShift::new(seed) }
+    }
+
+    pub fn waveform(&self) -> &Waveform {
+        &self.waveform
+    }
+
+    /// Returns the value at `elapsed` seconds since the simulator started.
+    pub fn sample(&mut self, elapsed: f64) -> f32 {
+        match &self.waveform {
+            Waveform::Constant { value } => *value,
+            Waveform::Sine { offset, amplitude, period_secs, phase_deg } => {
+                let angle = TAU * elapsed / f64::from(period_secs.max(f32::EPSILON)) + f64::from(*phase_deg).to_radians();
+                offset + amplitude * angle.sin() as f32
+            }
+            Waveform::Ramp { from, to, period_secs } => {
+                let fraction = (elapsed / f64::from(period_secs.max(f32::EPSILON))).fract() as f32;
+                from + (to - from) * fraction
+            }
+            Waveform::RandomWalk { step, min, max, .. } => {
+                let delta = (self.rng.next_unit() * 2.0 - 1.0) * step;
+                self.current = (self.current + delta).clamp(*min, *max);
+                self.current
+            }
+        }
+    }
+}
+
+/// Small xorshift64 generator, good enough for test data and free of extra dependencies.
+#[derive(Debug, Clone)]
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // The all-zero state would stay zero forever
+        XorShift(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Uniform value in `0.0..1.0`.
+    fn next_unit(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
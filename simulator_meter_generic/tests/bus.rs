@@ -0,0 +1,136 @@
+// simulator_meter_generic/tests/bus.rs
+//
+// Several slaves sharing one RTU line through the bus scheduler; the simulator answers every
+// slave address on it.
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+use tokio_modbus::client::rtu;
+use tokio_modbus::prelude::Reader;
+use common_meter_generic::settings::BusSettings;
+use common_meter_generic::Bus;
+use config_meter_generic::config::ConfigRegister;
+use simulator_meter_generic::{Faults, RequestRecord, Simulator, SimulatorHandle, SimulatorProfile};
+
+/// A bus on a converter link to the simulator, with the number of times the link was opened.
+async fn bus(faults: Faults, settings: &BusSettings) -> (SimulatorHandle, Bus) {
+    let (handle, bus, _) = counted_bus(faults, settings).await;
+    (handle, bus)
+}
+
+async fn counted_bus(faults: Faults, settings: &BusSettings) -> (SimulatorHandle, Bus, Arc<AtomicUsize>) {
+    let simulator = Simulator::new(SimulatorProfile::from_registers(&[
+        ConfigRegister { name: "voltage_L1_N".to_string(), address: 32774 },
+        ConfigRegister { name: "voltage_L2_N".to_string(), address: 32776 },
+        ConfigRegister { name: "voltage_L3_N".to_string(), address: 32778 },
+    ]));
+    simulator.set_faults(faults).await;
+    let handle = simulator.bind_rtu_over_tcp("127.0.0.1:0").await.unwrap();
+    let (address, opened) = (handle.local_addr(), Arc::new(AtomicUsize::new(1)));
+    let line = rtu::attach(TcpStream::connect(address).await.unwrap());
+    let reopened = Arc::clone(&opened);
+    let bus = Bus::spawn(line, settings, Box::new(move || {
+        reopened.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async move { Ok(rtu::attach(TcpStream::connect(address).await?)) })
+    }));
+    (handle, bus, opened)
+}
+
+#[tokio::test]
+async fn serves_lower_scan_groups_first() {
+    let settings = BusSettings::default();
+    let (handle, bus) = bus(Faults { delay_ms: 100, ..Default::default() }, &settings).await;
+
+    // Slave 1 holds the line while slaves 2 (group 1) and 3 (group 0) queue up, in that order
+    let mut first = bus.context(1, 1);
+    let busy = tokio::spawn(async move { first.read_holding_registers(32774, 2).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let mut late = bus.context(2, 1);
+    let late = tokio::spawn(async move { late.read_holding_registers(32776, 2).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let mut urgent = bus.context(3, 0);
+    let urgent = tokio::spawn(async move { urgent.read_holding_registers(32778, 2).await });
+
+    for request in [busy, late, urgent] {
+        request.await.unwrap().unwrap().unwrap();
+    }
+    assert_eq!(
+        handle.simulator().requests().await,
+        vec![
+            RequestRecord { function: 0x03, address: 32774, quantity: 2 },
+            RequestRecord { function: 0x03, address: 32778, quantity: 2 },
+            RequestRecord { function: 0x03, address: 32776, quantity: 2 },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn isolates_a_silent_slave_without_holding_up_the_others() {
+    let settings = BusSettings { response_timeout_ms: 100, isolate_after: 2, ..Default::default() };
+    let (handle, bus) = bus(Faults { silent_slaves: vec![9], ..Default::default() }, &settings).await;
+    let mut silent = bus.context(9, 0);
+    let mut healthy = bus.context(1, 0);
+
+    let timed_out = |result: tokio_modbus::Result<Vec<u16>>| matches!(result, Err(tokio_modbus::Error::Transport(e)) if e.kind() == io::ErrorKind::TimedOut);
+    for _ in 0..2 {
+        assert!(timed_out(silent.read_holding_registers(32774, 2).await));
+        healthy.read_holding_registers(32776, 2).await.unwrap().unwrap();
+    }
+
+    // Isolated: fails at once and the line stays free for the others
+    let started = Instant::now();
+    assert!(timed_out(silent.read_holding_registers(32774, 2).await));
+    assert!(started.elapsed() < Duration::from_millis(100));
+    handle.simulator().clear_requests().await;
+    healthy.read_holding_registers(32776, 2).await.unwrap().unwrap();
+    assert_eq!(handle.simulator().requests().await, vec![RequestRecord { function: 0x03, address: 32776, quantity: 2 }]);
+}
+
+#[tokio::test]
+async fn keeps_the_line_quiet_between_transactions() {
+    assert_eq!(BusSettings { baud_rate: 9600, ..Default::default() }.silence(), Duration::from_micros(4010));
+    assert_eq!(BusSettings { baud_rate: 38400, ..Default::default() }.silence(), Duration::from_micros(1750));
+
+    let settings = BusSettings { turnaround_ms: 150, ..Default::default() };
+    assert_eq!(settings.frame_gap(), Duration::from_millis(150));
+    let (_handle, bus) = bus(Faults::default(), &settings).await;
+    let mut first = bus.context(1, 0);
+    let mut second = bus.context(2, 0);
+
+    first.read_holding_registers(32774, 2).await.unwrap().unwrap();
+    let answered = Instant::now();
+    second.read_holding_registers(32776, 2).await.unwrap().unwrap();
+    assert!(answered.elapsed() >= Duration::from_millis(150));
+}
+
+#[tokio::test]
+async fn a_late_answer_is_not_taken_for_the_next_request() {
+    let settings = BusSettings { response_timeout_ms: 100, ..Default::default() };
+    let (handle, bus, opened) = counted_bus(Faults { delay_ms: 300, ..Default::default() }, &settings).await;
+    let mut slow = bus.context(1, 0);
+    let mut next = bus.context(2, 0);
+
+    let timed_out = slow.read_holding_registers(32774, 2).await;
+    assert!(matches!(timed_out, Err(tokio_modbus::Error::Transport(e)) if e.kind() == io::ErrorKind::TimedOut));
+
+    // Slave 1's answer is still on its way on the old link; slave 2 gets its own on a new one
+    handle.simulator().set_faults(Faults::default()).await;
+    let words = next.read_holding_registers(32776, 4).await.unwrap().unwrap();
+    assert_eq!(words.len(), 4);
+    assert_eq!(opened.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn reopens_a_converter_link_that_dropped() {
+    let (_handle, bus, opened) = counted_bus(Faults { drop_every: Some(2), ..Default::default() }, &BusSettings::default()).await;
+    let mut meter = bus.context(1, 0);
+
+    meter.read_holding_registers(32774, 2).await.unwrap().unwrap();
+    assert!(matches!(meter.read_holding_registers(32774, 2).await, Err(tokio_modbus::Error::Transport(_))));
+    meter.read_holding_registers(32774, 2).await.unwrap().unwrap();
+    assert_eq!(opened.load(Ordering::SeqCst), 2);
+}
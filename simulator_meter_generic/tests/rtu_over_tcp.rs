@@ -0,0 +1,93 @@
+// simulator_meter_generic/tests/rtu_over_tcp.rs
+//
+// Talks to the simulator through RTU framing over TCP, as behind an Ethernet-to-RS-485 converter.
+
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_modbus::client::rtu;
+use common_meter_generic::settings::{ExceptionPolicy, Framing, KeepaliveProbe, ModbusSettings, TransactionSettings, WordOrder};
+use common_meter_generic::{MeterClient, ModbusError};
+use config_meter_generic::config::ConfigRegister;
+use simulator_meter_generic::{Faults, RequestRecord, Simulator, SimulatorProfile};
+use statemachine_modbus::transport::{TcpTransport, Transport};
+
+async fn meter(voltage: f32) -> Simulator {
+    let simulator = Simulator::new(SimulatorProfile::from_registers(&[ConfigRegister {
+        name: "voltage_L1_N".to_string(),
+        address: 32774,
+    }]));
+    simulator.set_value("voltage_L1_N", voltage).await;
+    simulator
+}
+
+/// A converter whose first connection answers 300 ms late with 1.0, and later connections at
+/// once with 2.0, so it shows which connection an answer came from.
+async fn late_converter() -> (SocketAddr, ModbusSettings) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let (late, prompt) = (meter(1.0).await, meter(2.0).await);
+    late.set_faults(Faults { delay_ms: 300, ..Default::default() }).await;
+    tokio::spawn(async move {
+        let mut first = true;
+        while let Ok((stream, _)) = listener.accept().await {
+            let simulator = if std::mem::take(&mut first) { late.clone() } else { prompt.clone() };
+            tokio::spawn(async move { simulator.serve_rtu_connection(stream).await });
+        }
+    });
+
+    let settings = ModbusSettings {
+        framing: Framing::RtuOverTcp,
+        transaction: TransactionSettings { timeout_ms: 200, ..Default::default() },
+        exception_policy: ExceptionPolicy { max_retries: 1, retry_delay_ms: 0, ..Default::default() },
+        ..Default::default()
+    };
+    (address, settings)
+}
+
+#[tokio::test]
+async fn retries_after_a_timeout_on_a_new_connection() {
+    let (address, settings) = late_converter().await;
+    let mut client = TcpTransport
+        .connect(&address.ip().to_string(), address.port(), &settings, &KeepaliveProbe::default())
+        .await
+        .unwrap();
+
+    // Not the late answer to the request that timed out
+    assert_eq!(client.read_float(32774, WordOrder::HighFirst).await.unwrap(), 2.0);
+}
+
+#[tokio::test]
+async fn does_not_retry_a_timeout_without_a_new_connection() {
+    let (address, settings) = late_converter().await;
+    let mut client = MeterClient::new(rtu::attach(TcpStream::connect(address).await.unwrap()), &settings);
+
+    assert!(matches!(client.read_float(32774, WordOrder::HighFirst).await, Err(ModbusError::Timeout)));
+}
+
+#[tokio::test]
+async fn reads_and_writes_through_rtu_framing() {
+    let simulator = Simulator::new(SimulatorProfile::from_registers(&[
+        ConfigRegister { name: "voltage_L1_N".to_string(), address: 32774 },
+        ConfigRegister { name: "power_factor_L1".to_string(), address: 32816 },
+    ]));
+    simulator.set_value("voltage_L1_N", 231.5).await;
+    let handle = simulator.bind_rtu_over_tcp("127.0.0.1:0").await.unwrap();
+
+    let settings = ModbusSettings { framing: Framing::RtuOverTcp, unit_id: Some(7), ..Default::default() };
+    let address = handle.local_addr();
+    let mut client: MeterClient = TcpTransport
+        .connect(&address.ip().to_string(), address.port(), &settings, &KeepaliveProbe::default())
+        .await
+        .unwrap();
+
+    assert_eq!(client.read_float(32774, WordOrder::HighFirst).await.unwrap(), 231.5);
+    client.write_float(32816, 0.95, WordOrder::HighFirst).await.unwrap();
+    assert_eq!(handle.simulator().value("power_factor_L1").await, Some(0.95));
+    assert_eq!(
+        handle.simulator().requests().await,
+        vec![
+            RequestRecord { function: 0x03, address: 32774, quantity: 2 },
+            RequestRecord { function: 0x10, address: 32816, quantity: 2 },
+        ]
+    );
+}
@@ -0,0 +1,216 @@
+// simulator_meter_generic/tests/statemachines.rs
+//
+// Runs the Modbus and read state machines against the simulator on localhost.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_modbus::client::tcp;
+use config_meter_generic::config::Config;
+use common_meter_generic::{MeterClient, Settings};
+use simulator_meter_generic::{ExceptionFault, Faults, RequestRecord, Simulator, SimulatorHandle, SimulatorProfile};
+use statemachine_modbus::statemachine::{State as ModbusState, StateMachine as ModbusStateMachine};
+use statemachine_read::statemachine::{State as ReadState, StateMachine as ReadStateMachine};
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+
+fn write_config(name: &str, port: u16) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("simulator_{}_{}.yaml", std::process::id(), name));
+    let yaml = format!(
+        r#"
+meter_data:
+  ip: "127.0.0.1"
+  port: {port}
+  meter_type: "Simulator"
+write_registers:
+  - name: power_factor_L1
+    address: 32816
+    value: 0.9
+read_registers:
+  - name: voltage_L1_N
+    address: 32774
+  - name: voltage_L2_N
+    address: 32776
+  - name: grid_frequency
+    address: 32780
+  - name: power_factor_L1
+    address: 32816
+simulator:
+  waveforms:
+    voltage_L1_N: {{ kind: sine, offset: 230.0, amplitude: 5.0, period_secs: 10.0 }}
+    voltage_L2_N: {{ kind: random_walk, start: 230.0, step: 1.0, min: 225.0, max: 235.0 }}
+    grid_frequency: {{ kind: constant, value: 50.0 }}
+modbus:
+  transaction:
+    timeout_ms: 300
+  exception_policy:
+    max_retries: 1
+    retry_delay_ms: 10
+    backoff_initial_secs: 1
+profiles:
+  Simulator:
+    keepalive:
+      address: 32774
+      count: 2
+      interval_secs: 1
+debug:
+  mgw_generic: "off"
+  statemachine_modbus: "off"
+  statemachine_read: "off"
+"#
+    );
+    std::fs::write(&path, yaml).expect("write test configuration");
+    path
+}
+
+struct Gateway {
+    simulator: SimulatorHandle,
+    modbus: Arc<Mutex<ModbusStateMachine>>,
+    read: Arc<Mutex<ReadStateMachine>>,
+    settings: Settings,
+}
+
+/// Starts a simulator on a free port and state machines configured to poll it.
+async fn start(name: &str) -> Gateway {
+    let placeholder = write_config(name, 0);
+    let profile = SimulatorProfile::from_file(placeholder.to_str().unwrap()).expect("load simulator profile");
+    let simulator = Simulator::new(profile).bind("127.0.0.1:0").await.expect("bind simulator");
+
+    let path = write_config(name, simulator.local_addr().port());
+    let path = path.to_str().unwrap();
+    let config = Arc::new(Mutex::new(Config::from_file(path).expect("load config")));
+    let settings = Settings::from_file(path).expect("load settings");
+    let shared_settings = Arc::new(Mutex::new(settings.clone()));
+
+    let modbus = ModbusStateMachine::new(config.clone(), shared_settings.clone());
+    let read = ReadStateMachine::new(config, shared_settings, modbus.clone());
+    Gateway { simulator, modbus, read, settings }
+}
+
+impl Gateway {
+    fn simulator(&self) -> &Simulator {
+        self.simulator.simulator()
+    }
+
+    /// Skips Idle and Ping, which sleep and shell out to `ping`, and connects right away.
+    async fn connect(&self) {
+        let mut modbus = self.modbus.lock().await;
+        modbus.state = ModbusState::Connect;
+        modbus.step().await;
+        assert_eq!(modbus.state, ModbusState::Verify);
+        assert!(modbus.modbus_context.is_some());
+    }
+
+    async fn read_once(&self) {
+        let mut read = self.read.lock().await;
+        read.state = ReadState::Read;
+        read.step().await;
+        assert_eq!(read.state, ReadState::Idle);
+    }
+
+    async fn is_connected(&self) -> bool {
+        self.modbus.lock().await.modbus_context.is_some()
+    }
+}
+
+fn holding(address: u16, quantity: u16) -> RequestRecord {
+    RequestRecord { function: READ_HOLDING_REGISTERS, address, quantity }
+}
+
+#[tokio::test]
+async fn connects_verifies_and_reads_the_configured_block() {
+    let gateway = start("read_block").await;
+    gateway.connect().await;
+    assert!(gateway.simulator().requests().await.is_empty());
+
+    gateway.modbus.lock().await.step().await;
+    assert_eq!(gateway.modbus.lock().await.state, ModbusState::Verify);
+    assert_eq!(gateway.simulator().requests().await, vec![holding(32774, 2)]);
+
+    gateway.simulator().clear_requests().await;
+    gateway.read_once().await;
+    assert_eq!(gateway.simulator().requests().await, vec![holding(32774, 44)]);
+    assert!(gateway.is_connected().await);
+}
+
+#[tokio::test]
+async fn writes_are_served_back() {
+    let gateway = start("write").await;
+    assert_eq!(gateway.simulator().value("power_factor_L1").await, Some(0.0));
+
+    let context = tcp::connect(gateway.simulator.local_addr()).await.expect("connect");
+    let mut client = MeterClient::new(context, &gateway.settings.modbus);
+    let bits = 0.95f32.to_bits();
+    client.write_multiple_registers(32816, &[(bits >> 16) as u16, bits as u16]).await.expect("write");
+
+    assert_eq!(gateway.simulator().value("power_factor_L1").await, Some(0.95));
+    let words = client.read_holding_registers(32816, 2).await.expect("read back");
+    assert_eq!(f32::from_bits(((words[0] as u32) << 16) | words[1] as u32), 0.95);
+}
+
+#[tokio::test]
+async fn waveforms_stay_within_their_range() {
+    let gateway = start("waveforms").await;
+    assert_eq!(gateway.simulator().value("grid_frequency").await, Some(50.0));
+    for _ in 0..100 {
+        let sine = gateway.simulator().value("voltage_L1_N").await.unwrap();
+        assert!((225.0..=235.0).contains(&sine), "sine out of range: {}", sine);
+        let walk = gateway.simulator().value("voltage_L2_N").await.unwrap();
+        assert!((225.0..=235.0).contains(&walk), "random walk out of range: {}", walk);
+    }
+}
+
+#[tokio::test]
+async fn dropped_connection_triggers_reconnect() {
+    let gateway = start("drop").await;
+    gateway.connect().await;
+
+    gateway.simulator().set_faults(Faults { drop_every: Some(1), ..Default::default() }).await;
+    gateway.modbus.lock().await.step().await;
+
+    let modbus = gateway.modbus.lock().await;
+    assert_eq!(modbus.state, ModbusState::Idle);
+    assert!(modbus.modbus_context.is_none());
+}
+
+#[tokio::test]
+async fn slow_meter_times_out_and_reconnects() {
+    let gateway = start("slow").await;
+    gateway.connect().await;
+
+    gateway.simulator().set_faults(Faults { delay_ms: 1000, ..Default::default() }).await;
+    gateway.modbus.lock().await.step().await;
+
+    assert_eq!(gateway.modbus.lock().await.state, ModbusState::Idle);
+    assert!(!gateway.is_connected().await);
+}
+
+#[tokio::test]
+async fn busy_meter_keeps_the_connection() {
+    let gateway = start("busy").await;
+    gateway.connect().await;
+
+    let busy = ExceptionFault { function: None, start: 0, end: u16::MAX, code: 0x06 };
+    gateway.simulator().set_faults(Faults { exceptions: vec![busy], ..Default::default() }).await;
+    gateway.read_once().await;
+
+    assert!(gateway.is_connected().await);
+    assert_eq!(gateway.modbus.lock().await.state, ModbusState::Verify);
+}
+
+#[tokio::test]
+async fn illegal_address_falls_back_to_single_reads() {
+    let gateway = start("illegal_address").await;
+    gateway.connect().await;
+
+    let missing = ExceptionFault { function: Some(READ_HOLDING_REGISTERS), start: 32780, end: 32781, code: 0x02 };
+    gateway.simulator().set_faults(Faults { exceptions: vec![missing], ..Default::default() }).await;
+    gateway.simulator().clear_requests().await;
+    gateway.read_once().await;
+
+    assert_eq!(
+        gateway.simulator().requests().await,
+        vec![holding(32774, 44), holding(32774, 2), holding(32776, 2), holding(32780, 2), holding(32816, 2)]
+    );
+    assert!(gateway.is_connected().await);
+}
@@ -0,0 +1,63 @@
+// simulator_meter_generic/tests/tls.rs
+//
+// Connects the Modbus transport to the simulator over Modbus/TCP Security. The certificates in
+// tests/certs are issued by a throwaway test CA (its key is not kept); `rogue` is self-signed.
+
+use common_meter_generic::settings::{KeepaliveProbe, ModbusSettings, TlsSettings, WordOrder};
+use common_meter_generic::{tls, MeterClient, ModbusError};
+use config_meter_generic::config::ConfigRegister;
+use simulator_meter_generic::{Simulator, SimulatorHandle, SimulatorProfile};
+use statemachine_modbus::transport::{TcpTransport, Transport};
+
+fn certificate(name: &str) -> String {
+    format!("{}/tests/certs/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn tls_settings(identity: &str, ca: &str) -> TlsSettings {
+    TlsSettings {
+        cert_file: certificate(&format!("{}.pem", identity)),
+        key_file: certificate(&format!("{}.key", identity)),
+        ca_file: certificate(ca),
+        server_name: None,
+    }
+}
+
+async fn start_meter() -> SimulatorHandle {
+    let simulator = Simulator::new(SimulatorProfile::from_registers(&[ConfigRegister {
+        name: "voltage_L1_N".to_string(),
+        address: 32774,
+    }]));
+    simulator.set_value("voltage_L1_N", 230.0).await;
+    let acceptor = tls::acceptor(&tls_settings("meter", "ca.pem")).unwrap();
+    simulator.bind_tls("127.0.0.1:0", acceptor).await.unwrap()
+}
+
+/// Connects as the gateway would and reads the voltage.
+async fn read_voltage(handle: &SimulatorHandle, tls: TlsSettings) -> Result<f32, ModbusError> {
+    let settings = ModbusSettings { tls: Some(tls), ..Default::default() };
+    let address = handle.local_addr();
+    let mut client: MeterClient = TcpTransport
+        .connect(&address.ip().to_string(), address.port(), &settings, &KeepaliveProbe::default())
+        .await?;
+    client.read_float(32774, WordOrder::HighFirst).await
+}
+
+#[tokio::test]
+async fn reads_over_tls_with_certificates_on_both_sides() {
+    let handle = start_meter().await;
+    assert_eq!(read_voltage(&handle, tls_settings("gateway", "ca.pem")).await.unwrap(), 230.0);
+}
+
+#[tokio::test]
+async fn a_meter_certificate_from_another_authority_is_refused() {
+    let handle = start_meter().await;
+    let error = read_voltage(&handle, tls_settings("gateway", "rogue.pem")).await.unwrap_err();
+    assert!(error.to_string().contains("TLS handshake with 127.0.0.1 failed"), "{}", error);
+}
+
+#[tokio::test]
+async fn the_meter_refuses_a_gateway_certificate_from_another_authority() {
+    let handle = start_meter().await;
+    // With TLS 1.3 the meter's refusal may only show on the first request
+    assert!(read_voltage(&handle, tls_settings("rogue", "ca.pem")).await.is_err());
+}
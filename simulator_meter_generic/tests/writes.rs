@@ -0,0 +1,141 @@
+// simulator_meter_generic/tests/writes.rs
+//
+// The write state machine writes floats in each register's word order and reads them back in
+// VERIFY, against the simulator over an in-memory connection.
+
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_modbus::client::tcp;
+use config_meter_generic::config::{Config, ConfigRegister};
+use common_meter_generic::{MeterClient, Settings, WriteGuard, WriteOutcome};
+use simulator_meter_generic::{Simulator, SimulatorProfile};
+use statemachine_meter_generic::statemachine::{Event, State, StateMachine};
+
+const CONFIG: &str = r#"
+meter_data:
+  ip: "10.15.1.2"
+  port: 502
+  meter_type: "Mock"
+write_registers:
+  - name: power_factor_L1
+    address: 32816
+    value: 0.95
+  - name: ct_ratio
+    address: 40000
+    value: 200
+read_registers: []
+debug:
+  mgw_generic: "off"
+  statemachine_modbus: "off"
+  statemachine_read: "off"
+"#;
+
+const SETTINGS: &str = r#"
+writes:
+  word_order:
+    ct_ratio: high_first
+"#;
+
+/// A write state machine about to write to a fresh simulator.
+fn start() -> (StateMachine, Simulator) {
+    start_with(SETTINGS)
+}
+
+fn start_with(settings: &str) -> (StateMachine, Simulator) {
+    let (client, server) = tokio::io::duplex(1024);
+    let registers = [("power_factor_L1", 32816), ("ct_ratio", 40000)]
+        .map(|(name, address)| ConfigRegister { name: name.to_string(), address });
+    let simulator = Simulator::new(SimulatorProfile::from_registers(&registers));
+    let serving = simulator.clone();
+    tokio::spawn(async move { serving.serve_connection(server).await });
+
+    let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+    let settings: Settings = serde_yaml::from_str(settings).unwrap();
+    let shared = StateMachine::new(Arc::new(Mutex::new(config)), Arc::new(Mutex::new(settings)));
+    let mut state_machine = Arc::try_unwrap(shared).ok().expect("nobody else holds the state machine").into_inner();
+    state_machine.modbus_context = Some(Arc::new(Mutex::new(tcp::attach(client))));
+    state_machine.state = State::Write;
+    (state_machine, simulator)
+}
+
+/// The value of `value` with its two words swapped, as the simulator decodes a low word first.
+fn swapped(value: f32) -> f32 {
+    f32::from_bits(value.to_bits().rotate_left(16))
+}
+
+#[tokio::test]
+async fn writes_in_the_word_order_of_each_register_and_reads_it_back() {
+    let (mut state_machine, simulator) = start();
+
+    fsm_meter_generic::step(&mut state_machine).await.unwrap();
+    assert_eq!(state_machine.state, State::Verify);
+    // Low word first unless configured otherwise
+    assert_eq!(simulator.value("power_factor_L1").await, Some(swapped(0.95)));
+    assert_eq!(simulator.value("ct_ratio").await, Some(200.0));
+
+    fsm_meter_generic::step(&mut state_machine).await.unwrap();
+    assert_eq!(state_machine.history.last().unwrap().event, Event::VerificationPass);
+}
+
+#[tokio::test]
+async fn reports_a_value_that_reads_back_differently() {
+    let (mut state_machine, simulator) = start();
+    let mut drift = state_machine.subscribe_drift();
+
+    fsm_meter_generic::step(&mut state_machine).await.unwrap();
+    // The meter takes another value than the one written, e.g. clamped to its own range
+    simulator.set_value("ct_ratio", 100.0).await;
+    fsm_meter_generic::step(&mut state_machine).await.unwrap();
+
+    let failure = state_machine.history.last().unwrap();
+    assert_eq!((failure.event, failure.to), (Event::VerificationFail, State::Idle));
+    assert_eq!(failure.reason.as_deref(), Some("ct_ratio wrote 200, read back 100"));
+    let report = drift.try_recv().unwrap();
+    assert_eq!((report.name.as_str(), report.expected, report.actual), ("ct_ratio", 200.0, 100.0));
+}
+
+#[tokio::test]
+async fn a_rejected_register_does_not_hold_back_the_others() {
+    let (mut state_machine, simulator) = start_with(
+        r#"
+writes:
+  word_order:
+    ct_ratio: high_first
+  limits:
+    power_factor_L1: { max: 0.9 }
+"#,
+    );
+
+    fsm_meter_generic::step(&mut state_machine).await.unwrap();
+    assert_eq!(state_machine.history.last().unwrap().event, Event::WriteSuccessful);
+    assert_eq!(simulator.value("power_factor_L1").await, Some(0.0));
+    assert_eq!(simulator.value("ct_ratio").await, Some(200.0));
+}
+
+#[tokio::test]
+async fn the_write_guard_uses_the_word_order_of_the_state_machine() {
+    let (mut state_machine, simulator) = start();
+    fsm_meter_generic::step(&mut state_machine).await.unwrap();
+
+    let (client, server) = tokio::io::duplex(1024);
+    let serving = simulator.clone();
+    tokio::spawn(async move { serving.serve_connection(server).await });
+    let audit = std::env::temp_dir().join(format!("mgw_guard_word_order_{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&audit);
+    let mut settings: Settings = serde_yaml::from_str(SETTINGS).unwrap();
+    settings.writes.audit_file = audit.to_str().map(str::to_string);
+    let mut client = MeterClient::new(tcp::attach(client), &settings.modbus);
+    let guard = WriteGuard::new(&settings.writes, &settings.profile("Mock"), "test");
+
+    assert_eq!(guard.write(&mut client, "power_factor_L1", 32816, 0.9).await.unwrap(), WriteOutcome::Written);
+    assert_eq!(simulator.value("power_factor_L1").await, Some(swapped(0.9)));
+    assert_eq!(guard.write(&mut client, "ct_ratio", 40000, 150.0).await.unwrap(), WriteOutcome::Written);
+    assert_eq!(simulator.value("ct_ratio").await, Some(150.0));
+
+    // The old values are the ones the state machine wrote
+    let lines: Vec<serde_yaml::Value> =
+        std::fs::read_to_string(&audit).unwrap().lines().map(|line| serde_yaml::from_str(line).unwrap()).collect();
+    std::fs::remove_file(&audit).unwrap();
+    assert_eq!(lines[0]["old"].as_f64(), Some(0.95));
+    assert_eq!(lines[1]["old"].as_f64(), Some(200.0));
+}
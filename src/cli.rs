@@ -0,0 +1,108 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use crate::scanner::RegisterTable;
+
+/// Command-line interface of the meter gateway.
+#[derive(Debug, Parser)]
+#[command(name = "mgw_generic", version, about = "Generic Modbus meter gateway")]
+pub struct Cli {
+    /// Path to the YAML configuration file
+    #[arg(short, long, global = true, default_value = "mgw_config.yaml")]
+    pub config: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the state machines until interrupted (default)
+    Run {
+        /// Configuration file of a further meter to run in this process; meters on the same
+        /// RS-485 bus share it
+        #[arg(long = "meter", value_name = "CONFIG")]
+        meters: Vec<String>,
+    },
+    /// Poll all configured read registers once and print the values
+    Read {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Write a single value to a configured register
+    Write {
+        /// Register name as listed in the configuration
+        name: String,
+        /// Value to write (IEEE 754 float)
+        value: f32,
+        /// Check and log the write without sending it
+        #[arg(long)]
+        dry_run: bool,
+        /// Write without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Set or clear one named bit of a status register, keeping the others
+    SetFlag {
+        /// Register name as listed in the configuration
+        name: String,
+        /// Flag name from the register's `bits` in the meter profile
+        flag: String,
+        #[arg(value_enum)]
+        state: FlagState,
+        /// Check and log the write without sending it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Read the serial number, firmware and other identity information of the meter
+    Identify {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Probe an address range and draft a register list for an unknown meter
+    Scan {
+        /// First address to probe
+        #[arg(long)]
+        start: u16,
+        /// Last address to probe (inclusive)
+        #[arg(long)]
+        end: u16,
+        /// Number of registers requested per probe
+        #[arg(long, default_value_t = 2)]
+        block: u16,
+        /// Register tables to walk (defaults to holding and input)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        table: Vec<RegisterTable>,
+        /// Write the draft `read_registers` YAML to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Load and validate the configuration file, then exit
+    CheckConfig,
+    /// Print the register map of the configured meter as YAML
+    DumpProfile,
+    /// Print the OPC UA nodes the configured meter is exposed as
+    OpcuaModel {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Print the alarms active in the running gateway
+    Alarms {
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FlagState {
+    On,
+    Off,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
@@ -0,0 +1,36 @@
+use std::fs;
+use chrono::{DateTime, Local};
+use common_meter_generic::{ActiveAlarm, Settings};
+use anyhow::{bail, Context, Result};
+use crate::cli::OutputFormat;
+
+/// Prints the alarms the running gateway keeps in `alarms.active_file`.
+pub fn command_alarms(settings: &Settings, format: OutputFormat) -> Result<()> {
+    let Some(path) = &settings.alarms.active_file else {
+        bail!("No alarms.active_file configured");
+    };
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read active alarms from {}; is the gateway running?", path))?;
+    let active: Vec<ActiveAlarm> = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse active alarms in {}", path))?;
+
+    match format {
+        OutputFormat::Table => {
+            println!("{:<32} {:<9} {:<24} {:>10} {:>20}", "NAME", "SEVERITY", "REGISTER", "LIMIT", "SINCE");
+            for alarm in &active {
+                let since: DateTime<Local> = alarm.since.into();
+                println!(
+                    "{:<32} {:<9} {:<24} {:>10.3} {:>20}",
+                    alarm.name,
+                    format!("{:?}", alarm.severity),
+                    alarm.register,
+                    alarm.limit,
+                    since.format("%Y-%m-%d %H:%M:%S")
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", contents.trim_end()),
+    }
+
+    Ok(())
+}
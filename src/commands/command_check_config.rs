@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use config_meter_generic::config::Config;
+use common_meter_generic::{address, tls, Settings, WriteGuard};
+use common_meter_generic::settings::{Limit, ModbusSettings};
+use common_meter_generic::information_model::CONNECTION_STATE;
+use anyhow::{bail, Result};
+use super::command_read::{read_span, MAX_READ_QUANTITY};
+
+/// Checks how the meter is reached on one path: its addresses, unit id, TLS and bus.
+fn check_connection(section: &str, address_section: &str, ip: &str, port: u16, modbus: &ModbusSettings, problems: &mut Vec<String>) {
+    if let Err(e) = address::endpoints(ip, port, modbus) {
+        problems.push(format!("{}: {}", address_section, e));
+    }
+    if modbus.is_rtu() && modbus.unit_id() == 0 {
+        problems.push(format!("{}: unit_id 0 is the RTU broadcast address, which never answers", section));
+    }
+    if let Some(tls) = &modbus.tls {
+        if let Err(e) = tls::connector(tls) {
+            problems.push(format!("{}.tls: {}", section, e));
+        }
+    }
+    if let Some(bus) = &modbus.bus {
+        if bus.baud_rate == 0 {
+            problems.push(format!("{}.bus: baud_rate must be greater than 0", section));
+        }
+        if !matches!(bus.stop_bits, 1 | 2) {
+            problems.push(format!("{}.bus: stop_bits must be 1 or 2, not {}", section, bus.stop_bits));
+        }
+        if bus.response_timeout_ms == 0 {
+            problems.push(format!("{}.bus: response_timeout_ms must be greater than 0", section));
+        }
+        if bus.isolate_after == 0 {
+            problems.push(format!("{}.bus: isolate_after must be at least 1", section));
+        }
+        if modbus.tls.is_some() {
+            problems.push(format!("{}: tls cannot be used on an RS-485 bus", section));
+        }
+    }
+}
+
+/// Validates the loaded configuration and reports every problem found.
+pub fn command_check_config(config_path: &str, config: &Config, settings: &Settings) -> Result<()> {
+    let mut problems: Vec<String> = Vec::new();
+
+    check_connection("modbus", "meter_data/modbus.addresses", &config.meter_data.ip, config.meter_data.port, &settings.modbus, &mut problems);
+    let mut path_names = HashSet::new();
+    for path in &settings.modbus.paths {
+        if path.name.is_empty() {
+            problems.push("modbus.paths: every path needs a name".to_string());
+        } else if !path_names.insert(path.name.as_str()) {
+            problems.push(format!("modbus.paths: duplicate name '{}'", path.name));
+        }
+        let section = format!("modbus.paths.{}", path.name);
+        let ip = path.addresses.first().unwrap_or(&config.meter_data.ip);
+        let on_path = settings.modbus.on_path(path);
+        check_connection(&section, &format!("{}.addresses", section), ip, config.meter_data.port, &on_path, &mut problems);
+    }
+    if settings.modbus.failback_secs == Some(0) {
+        problems.push("modbus: failback_secs must be greater than 0".to_string());
+    }
+
+    let mut names = HashSet::new();
+    for register in &config.read_registers {
+        if !names.insert(register.name.as_str()) {
+            problems.push(format!("read_registers: duplicate name '{}'", register.name));
+        }
+    }
+
+    if let Err(e) = read_span(&config.read_registers, &settings.profile(&config.meter_data.meter_type)) {
+        problems.push(format!("read_registers: {}", e));
+    }
+
+    let guard = WriteGuard::new(&settings.writes, &settings.profile(&config.meter_data.meter_type), "check-config");
+    let mut names = HashSet::new();
+    for register in &config.write_registers {
+        if !names.insert(register.name.as_str()) {
+            problems.push(format!("write_registers: duplicate name '{}'", register.name));
+        }
+        if let Err(reason) = guard.check(&register.name, register.address, register.value) {
+            problems.push(format!("write_registers: '{}' would be rejected: {}", register.name, reason));
+        }
+    }
+    if settings.writes.tolerance < 0.0 || settings.writes.tolerance.is_nan() {
+        problems.push("writes: tolerance must be 0 or more".to_string());
+    }
+    for name in settings.writes.limits.keys() {
+        if !names.contains(name.as_str()) && !config.read_registers.iter().any(|r| &r.name == name) {
+            problems.push(format!("writes: limits for unknown register '{}'", name));
+        }
+    }
+
+    let profile = settings.profile(&config.meter_data.meter_type);
+    for (name, info) in &profile.registers {
+        if !info.bits.is_empty() && !info.labels.is_empty() {
+            problems.push(format!("profiles: '{}' defines both bits and enum", name));
+        }
+        let width = info.words() * 16;
+        for (bit, flag) in &info.bits {
+            if *bit as usize >= width {
+                problems.push(format!("profiles: bit {} ({}) of '{}' does not fit a {}-bit register", bit, flag, name, width));
+            }
+            if info.bit(flag) != Some(*bit) {
+                problems.push(format!("profiles: '{}' has more than one flag '{}'", name, flag));
+            }
+        }
+    }
+    let meter_type = &config.meter_data.meter_type;
+    if settings.modbus.device_identification
+        && !settings.profiles.contains_key(meter_type)
+        && settings.profiles.values().all(|profile| profile.device.is_empty())
+    {
+        problems.push(format!("modbus: device identification cannot pick a profile for '{}', no profile names a device", meter_type));
+    }
+    for (name, register) in &profile.identity {
+        if !(1..=MAX_READ_QUANTITY).contains(&u32::from(register.length)) {
+            problems.push(format!("profiles: identity '{}' must be 1 to {} registers long", name, MAX_READ_QUANTITY));
+        }
+    }
+
+    // Derived channels may use read registers and the channels defined before them
+    let mut known: HashSet<&str> = config.read_registers.iter().map(|r| r.name.as_str()).collect();
+    for channel in &settings.derived {
+        for name in channel.expression.variables() {
+            if !known.contains(name) {
+                problems.push(format!("derived: '{}' uses unknown register '{}'", channel.name, name));
+            }
+        }
+        if !known.insert(channel.name.as_str()) {
+            problems.push(format!("derived: '{}' is already a register or derived channel", channel.name));
+        }
+    }
+
+    let aggregation = &settings.aggregation;
+    for name in &aggregation.registers {
+        if !known.contains(name.as_str()) {
+            problems.push(format!("aggregation: unknown register '{}'", name));
+        }
+    }
+    for name in &aggregation.energy {
+        if !aggregation.registers.contains(name) {
+            problems.push(format!("aggregation: energy register '{}' is not among the aggregated registers", name));
+        }
+    }
+
+    let mut rules = HashSet::new();
+    for rule in &settings.alarms.rules {
+        if !rules.insert(rule.name.as_str()) {
+            problems.push(format!("alarms: duplicate rule '{}'", rule.name));
+        }
+        if !known.contains(rule.register.as_str()) {
+            problems.push(format!("alarms: '{}' watches unknown register '{}'", rule.name, rule.register));
+        }
+        for limit in [&rule.high, &rule.low].into_iter().flatten() {
+            if let Limit::WriteRegister { write_register } = limit {
+                if !config.write_registers.iter().any(|r| &r.name == write_register) {
+                    problems.push(format!("alarms: '{}' uses unknown write register '{}'", rule.name, write_register));
+                }
+            }
+        }
+        let has_band = rule.nominal.is_some() && rule.tolerance_percent.is_some();
+        if rule.high.is_none() && rule.low.is_none() && !has_band {
+            problems.push(format!("alarms: '{}' has no limits", rule.name));
+        }
+        if rule.hysteresis < 0.0 {
+            problems.push(format!("alarms: '{}' has a negative hysteresis", rule.name));
+        }
+    }
+
+    let mut controlled = HashSet::new();
+    for control in &settings.control.loops {
+        let name = &control.write_register;
+        if !config.write_registers.iter().any(|r| &r.name == name) {
+            problems.push(format!("control: unknown write register '{}'", name));
+        }
+        if !controlled.insert(name.as_str()) {
+            problems.push(format!("control: '{}' is controlled by more than one loop", name));
+        }
+        if !known.contains(control.measured.as_str()) {
+            problems.push(format!("control: '{}' measures unknown register '{}'", name, control.measured));
+        }
+        if control.min.zip(control.max).is_some_and(|(min, max)| min > max) {
+            problems.push(format!("control: '{}' has min above max", name));
+        }
+        if control.deadband < 0.0 || control.max_step.is_some_and(|step| step <= 0.0) {
+            problems.push(format!("control: '{}' needs a deadband of at least 0 and a max_step above 0", name));
+        }
+    }
+
+    if settings.opcua.namespace_uri.is_empty() {
+        problems.push("opcua: namespace_uri must not be empty".to_string());
+    }
+    if settings.opcua.object_name.as_deref().is_some_and(|name| name.is_empty() || name.contains('.')) {
+        problems.push("opcua: object_name must be non-empty and contain no '.'".to_string());
+    }
+    if settings.opcua.namespace_uri == "urn:mgw_generic" {
+        problems.push("opcua: namespace_uri urn:mgw_generic is the server's own".to_string());
+    }
+    if settings.opcua.listen.as_deref().is_some_and(|listen| listen.parse::<SocketAddr>().is_err()) {
+        problems.push("opcua: listen must be an IP address and port, e.g. 127.0.0.1:4840".to_string());
+    }
+    let registers = config.read_registers.iter().map(|r| &r.name).chain(config.write_registers.iter().map(|r| &r.name));
+    for name in registers.filter(|name| *name == CONNECTION_STATE) {
+        problems.push(format!("opcua: register '{}' clashes with the connection state node", name));
+    }
+
+    problems.extend(settings.validate());
+
+    if problems.is_empty() {
+        println!(
+            "{}: OK ({} read registers, {} write registers, {} derived channels, {} alarm rules)",
+            config_path,
+            config.read_registers.len(),
+            config.write_registers.len(),
+            settings.derived.len(),
+            settings.alarms.rules.len()
+        );
+        Ok(())
+    } else {
+        for problem in &problems {
+            println!("{}: {}", config_path, problem);
+        }
+        bail!("{} problem(s) found in {}", problems.len(), config_path)
+    }
+}
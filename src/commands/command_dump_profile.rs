@@ -0,0 +1,48 @@
+use config_meter_generic::config::Config;
+use common_meter_generic::Settings;
+use common_meter_generic::settings::MeterProfile;
+use anyhow::Result;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct Profile<'a> {
+    meter_type: &'a str,
+    #[serde(flatten)]
+    profile: MeterProfile,
+    read_registers: Vec<ProfileRegister<'a>>,
+    write_registers: Vec<ProfileWriteRegister<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProfileRegister<'a> {
+    name: &'a str,
+    address: u16,
+}
+
+#[derive(Debug, Serialize)]
+struct ProfileWriteRegister<'a> {
+    name: &'a str,
+    address: u16,
+    value: f32,
+}
+
+/// Prints the meter type, its resolved profile and the register map as YAML.
+pub fn command_dump_profile(config: &Config, settings: &Settings) -> Result<()> {
+    let profile = Profile {
+        meter_type: &config.meter_data.meter_type,
+        profile: settings.profile(&config.meter_data.meter_type),
+        read_registers: config
+            .read_registers
+            .iter()
+            .map(|r| ProfileRegister { name: &r.name, address: r.address })
+            .collect(),
+        write_registers: config
+            .write_registers
+            .iter()
+            .map(|r| ProfileWriteRegister { name: &r.name, address: r.address, value: r.value })
+            .collect(),
+    };
+
+    print!("{}", serde_yaml::to_string(&profile)?);
+    Ok(())
+}
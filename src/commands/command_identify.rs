@@ -0,0 +1,39 @@
+use config_meter_generic::config::Config;
+use common_meter_generic::{Identity, Settings};
+use anyhow::{bail, Result};
+use log::warn;
+use crate::cli::OutputFormat;
+use super::connection::connect;
+
+/// Reads the identity registers and, if enabled, the device identification of the configured
+/// meter once and prints them.
+pub async fn command_identify(config: &Config, settings: &Settings, format: OutputFormat) -> Result<()> {
+    let profile = settings.profile(&config.meter_data.meter_type);
+    if profile.identity.is_empty() && !settings.modbus.device_identification {
+        bail!("No identity registers in the profile of {} and device identification is off", config.meter_data.meter_type);
+    }
+
+    let mut client = connect(config, &settings.modbus).await?;
+    let mut identity = Identity::read(&mut client, &profile.identity).await;
+    if settings.modbus.device_identification {
+        match client.read_device_identification().await {
+            Ok(device) => identity.insert_device(&device),
+            Err(e) => warn!("Device identification failed: {}", e),
+        }
+    }
+    if identity.is_empty() {
+        bail!("None of the identity registers could be read");
+    }
+
+    match format {
+        OutputFormat::Table => {
+            println!("{:<24} VALUE", "NAME");
+            for (name, text) in &identity.0 {
+                println!("{:<24} {}", name, text);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&identity)?),
+    }
+
+    Ok(())
+}
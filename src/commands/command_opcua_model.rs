@@ -0,0 +1,44 @@
+use config_meter_generic::config::Config;
+use common_meter_generic::{InformationModel, Settings};
+use common_meter_generic::information_model::NodeClass;
+use anyhow::Result;
+use serde_json::json;
+use crate::cli::OutputFormat;
+
+/// Prints the OPC UA nodes of the configured meter: its object, a variable per register and
+/// the connection state.
+pub fn command_opcua_model(config: &Config, settings: &Settings, format: OutputFormat) -> Result<()> {
+    let meter_type = &config.meter_data.meter_type;
+    let read: Vec<(&str, u16)> = config.read_registers.iter().map(|r| (r.name.as_str(), r.address)).collect();
+    let write: Vec<(&str, u16)> = config.write_registers.iter().map(|r| (r.name.as_str(), r.address)).collect();
+    let model = InformationModel::new(&settings.opcua, meter_type, &settings.profile(meter_type), &read, &write);
+
+    match format {
+        OutputFormat::Table => {
+            println!("Namespace {}", model.namespace_uri());
+            println!("{:<48} {:<8} {:<8} {:<20} ACCESS", "NODE ID", "CLASS", "UNIT", "RANGE");
+            for node in model.nodes() {
+                let range = node.range.map(|r| format!("{} .. {}", r.low, r.high)).unwrap_or_default();
+                let access = match node.class {
+                    NodeClass::Variable if node.writable => "read/write",
+                    NodeClass::Variable => "read",
+                    NodeClass::Object => "",
+                };
+                println!(
+                    "{:<48} {:<8} {:<8} {:<20} {}",
+                    node.node_id,
+                    format!("{:?}", node.class),
+                    node.unit.as_deref().unwrap_or(""),
+                    range,
+                    access
+                );
+            }
+        }
+        OutputFormat::Json => {
+            let model = json!({ "namespace_uri": model.namespace_uri(), "nodes": model.nodes() });
+            println!("{}", serde_json::to_string_pretty(&model)?);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use config_meter_generic::config::{Config, ConfigRegister};
+use common_meter_generic::{Decoded, Settings};
+use common_meter_generic::settings::MeterProfile;
+use anyhow::{bail, Result};
+use serde::Serialize;
+use crate::cli::OutputFormat;
+use super::connection::connect;
+
+/// Largest number of holding registers a single Modbus read may request.
+pub(crate) const MAX_READ_QUANTITY: u32 = 125;
+
+#[derive(Debug, Serialize)]
+struct RegisterValue {
+    name: String,
+    address: u16,
+    value: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decoded: Option<Decoded>,
+}
+
+/// Reads every configured register once and prints the decoded values.
+pub async fn command_read(config: &Config, settings: &Settings, format: OutputFormat) -> Result<()> {
+    let read_registers: Vec<ConfigRegister> = config.get_read_registers();
+    if read_registers.is_empty() {
+        bail!("No registers configured for reading");
+    }
+
+    let profile = settings.profile(&config.meter_data.meter_type);
+    let (start_address, quantity) = read_span(&read_registers, &profile).map_err(anyhow::Error::msg)?;
+
+    let mut client = connect(config, &settings.modbus).await?;
+    let words = client.read_holding_registers(start_address, quantity).await?;
+
+    let mut values = Vec::with_capacity(read_registers.len());
+    for register in &read_registers {
+        let info = profile.register(&register.name);
+        let offset = (register.address - start_address) as usize;
+        let Some(raw) = words.get(offset..offset + info.words()) else {
+            bail!("Index out of bounds for register: {}", register.name);
+        };
+        values.push(RegisterValue {
+            name: register.name.clone(),
+            address: register.address,
+            value: info.format.decode(raw).unwrap_or(f32::NAN),
+            decoded: Decoded::new(&info, raw),
+        });
+    }
+
+    match format {
+        OutputFormat::Table => {
+            println!("{:<24} {:>8} {:>16}  DECODED", "NAME", "ADDRESS", "VALUE");
+            for v in &values {
+                let decoded = v.decoded.as_ref().map(|decoded| decoded.to_string()).unwrap_or_default();
+                println!("{:<24} {:>8} {:>16.3}  {}", v.name, v.address, v.value, decoded);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&values)?),
+    }
+
+    Ok(())
+}
+
+/// First address and number of words of the single read covering `registers`, each as many
+/// words long as its format in `profile` takes.
+pub(crate) fn read_span(registers: &[ConfigRegister], profile: &MeterProfile) -> Result<(u16, u16), String> {
+    let start = registers.iter().map(|r| r.address).min().ok_or("no registers configured")?;
+    let mut last = u32::from(start);
+    for register in registers {
+        let words = profile.register(&register.name).words().max(1) as u32;
+        let end = u32::from(register.address) + words - 1;
+        if end > u32::from(u16::MAX) {
+            return Err(format!("register at address {} has no room for its {} words", register.address, words));
+        }
+        last = last.max(end);
+    }
+    let quantity = last - u32::from(start) + 1;
+    if quantity > MAX_READ_QUANTITY {
+        return Err(format!(
+            "span {}..{} needs {} registers, more than the {} allowed per read",
+            start, last, quantity, MAX_READ_QUANTITY
+        ));
+    }
+    Ok((start, quantity as u16))
+}
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use config_meter_generic::config::Config;
+use common_meter_generic::{ActiveAlarm, AlarmEngine, AlarmEvent, AlarmTransition, Aggregator, Bus, Controller, InformationModel, Settings, Setpoint, SetpointKind, Snapshot, WriteError, WriteGuard};
+use common_meter_generic::settings::{BusSettings, ControlSettings, ModbusSettings, PathSettings};
+use common_meter_generic::settings::Severity;
+use anyhow::{anyhow, bail, Context as _, Result};
+use log::{error, info, warn};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
+use opcua_meter_generic::OpcUaServer;
+use statemachine_modbus::transport::{BusTransport, Path, PingReachability, TcpTransport};
+use statemachine_modbus::statemachine::{Event as ModbusEvent, State as ModbusState, StateMachine as ModbusStateMachine};
+use crate::update_log_levels::update_log_levels;
+
+/// Window over which failures are counted when the Modbus state machine falls back to Idle.
+const FAILURE_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Runs the Modbus and read state machines of the meter in `config_path` and of each of
+/// `meters` until the process is stopped.
+pub async fn command_run(config_path: &str, config: Config, settings: Settings, meters: &[String]) -> Result<()> {
+    info!("Starting the application...");
+
+    let mut buses = Buses::default();
+    let mut tasks = run_meter(config_path, config, settings, &mut buses).await?;
+    for meter_path in meters {
+        let config = Config::from_file(meter_path).with_context(|| format!("Failed to load configuration from {}", meter_path))?;
+        let settings = Settings::from_file(meter_path)?;
+        info!("Adding the meter configured in {}", meter_path);
+        tasks.extend(run_meter(meter_path, config, settings, &mut buses).await?);
+    }
+
+    // Await the state machines to complete
+    for task in tasks {
+        let _ = task.await;
+    }
+
+    info!("Application finished");
+
+    Ok(())
+}
+
+/// RS-485 buses opened so far, by serial port or converter address, so meters on the same line
+/// queue their requests on one scheduler.
+#[derive(Default)]
+struct Buses {
+    open: HashMap<String, (BusSettings, Bus)>,
+}
+
+impl Buses {
+    async fn open(&mut self, settings: &BusSettings, ip: &str, port: u16, modbus: &ModbusSettings) -> Result<Bus> {
+        let line = match &settings.serial_port {
+            Some(path) => path.clone(),
+            None => format!("{}:{}", ip, port),
+        };
+        if let Some((opened, bus)) = self.open.get(&line) {
+            if opened != settings {
+                bail!("Meters on the RS-485 bus {} have different bus settings", line);
+            }
+            if !bus.is_closed() {
+                info!("Sharing the RS-485 bus {}", line);
+                return Ok(bus.clone());
+            }
+        }
+        let bus = Bus::open(settings, ip, port, modbus).await?;
+        self.open.insert(line, (settings.clone(), bus.clone()));
+        Ok(bus)
+    }
+}
+
+/// Starts the state machines of one meter and the tasks fed by them, and returns the state
+/// machines' tasks.
+async fn run_meter(config_path: &str, config: Config, settings: Settings, buses: &mut Buses) -> Result<Vec<JoinHandle<()>>> {
+    let paths = open_paths(&config, &settings, buses).await?;
+
+    let shared_config: Arc<Mutex<Config>> = Arc::new(Mutex::new(config));
+    let shared_settings: Arc<Mutex<Settings>> = Arc::new(Mutex::new(settings));
+    info!("Shared configuration created");
+
+    // Start the log level update task
+    let config_path = config_path.to_string();
+    let shared_config_clone = Arc::clone(&shared_config);
+    let shared_settings_clone = Arc::clone(&shared_settings);
+    tokio::spawn(async move {
+        if let Err(e) = update_log_levels(&config_path, shared_config_clone, shared_settings_clone).await {
+            error!("Failed to update log levels: {:?}", e);
+        }
+    });
+
+    let state_machine_modbus =
+        statemachine_modbus::StateMachine::with_paths(Arc::clone(&shared_config), Arc::clone(&shared_settings), paths);
+    let state_machine_read = statemachine_read::StateMachine::new(
+        Arc::clone(&shared_config),
+        Arc::clone(&shared_settings),
+        Arc::clone(&state_machine_modbus),
+    );
+
+    info!("State machines created");
+
+    // Report why the connection went down, together with how often that happened lately
+    let modbus_history = state_machine_modbus.lock().await.history.clone();
+    tokio::spawn(async move {
+        let mut transitions = modbus_history.subscribe_transitions();
+        loop {
+            let record = match transitions.recv().await {
+                Ok(record) => record,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Missed {} Modbus state transitions", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if record.to != ModbusState::Idle || record.from == ModbusState::Idle {
+                continue;
+            }
+            let occurrences = modbus_history.occurrences(record.from, record.event, FAILURE_WINDOW);
+            warn!(
+                "{:?} failed {}× in last hour: {}",
+                record.from,
+                occurrences.count,
+                record.reason.as_deref().unwrap_or(describe(record.event)),
+            );
+        }
+    });
+
+    // Interval statistics; the settings are taken once at startup
+    let aggregation = shared_settings.lock().await.aggregation.clone();
+    if !aggregation.registers.is_empty() {
+        let snapshots = state_machine_read.lock().await.subscribe_snapshots();
+        // Settings::from_file has validated the intervals
+        let aggregator = Aggregator::new(aggregation).map_err(|e| anyhow!("aggregation: {}", e))?;
+        tokio::spawn(aggregator.run(snapshots));
+    }
+
+    // Threshold alarms; the rules are taken once at startup, write register limits follow the config
+    let alarms = shared_settings.lock().await.alarms.clone();
+    if !alarms.rules.is_empty() {
+        let mut snapshots = state_machine_read.lock().await.subscribe_snapshots();
+        let shared_config = Arc::clone(&shared_config);
+        tokio::spawn(async move {
+            let mut engine = AlarmEngine::new(alarms.rules);
+            if let Some(path) = &alarms.active_file {
+                if let Err(e) = write_active_alarms(path, &[]) {
+                    error!("Failed to write active alarms to {}: {:?}", path, e);
+                }
+            }
+            loop {
+                let snapshot = match snapshots.recv().await {
+                    Ok(snapshot) => snapshot,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Alarms missed {} snapshots", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let events = {
+                    let config = shared_config.lock().await;
+                    let targets = |name: &str| config.write_registers.iter().find(|r| r.name == name).map(|r| r.value);
+                    engine.evaluate(&snapshot, &targets)
+                };
+                if events.is_empty() {
+                    continue;
+                }
+                for event in &events {
+                    match (event.transition, event.alarm.severity) {
+                        (AlarmTransition::Raised, Severity::Critical) => error!("{}", event),
+                        (AlarmTransition::Raised, Severity::Warning) => warn!("{}", event),
+                        _ => info!("{}", event),
+                    }
+                }
+                if let Some(path) = &alarms.events_file {
+                    if let Err(e) = append_alarm_events(path, &events) {
+                        error!("Failed to append alarm events to {}: {:?}", path, e);
+                    }
+                }
+                if let Some(path) = &alarms.active_file {
+                    if let Err(e) = write_active_alarms(path, &engine.active()) {
+                        error!("Failed to write active alarms to {}: {:?}", path, e);
+                    }
+                }
+            }
+        });
+    }
+
+    // Closed-loop setpoints, written through the Modbus state machine's connection
+    let (control, guard) = {
+        let settings = shared_settings.lock().await;
+        let meter_type = shared_config.lock().await.meter_data.meter_type.clone();
+        (settings.control.clone(), WriteGuard::new(&settings.writes, &settings.profile(&meter_type), "control"))
+    };
+    if !control.loops.is_empty() {
+        let snapshots = state_machine_read.lock().await.subscribe_snapshots();
+        tokio::spawn(run_control(control, guard, snapshots, Arc::clone(&shared_config), Arc::clone(&state_machine_modbus)));
+    }
+
+    // OPC UA server on the information model; client writes go through their own write guard
+    let opcua = {
+        let settings = shared_settings.lock().await;
+        let config = shared_config.lock().await;
+        let meter_type = &config.meter_data.meter_type;
+        let profile = settings.profile(meter_type);
+        let read: Vec<(&str, u16)> = config.read_registers.iter().map(|r| (r.name.as_str(), r.address)).collect();
+        let write: Vec<(&str, u16)> = config.write_registers.iter().map(|r| (r.name.as_str(), r.address)).collect();
+        settings.opcua.listen.is_some().then(|| {
+            let model = InformationModel::new(&settings.opcua, meter_type, &profile, &read, &write);
+            (settings.opcua.clone(), model, WriteGuard::new(&settings.writes, &profile, "opcua"))
+        })
+    };
+    if let Some((opcua, model, guard)) = opcua {
+        // A port in use leaves the meter without OPC UA, but still read
+        match OpcUaServer::bind(&opcua, model, guard, Arc::clone(&state_machine_modbus)).await {
+            Ok(server) => {
+                let snapshots = state_machine_read.lock().await.subscribe_snapshots();
+                let state = state_machine_modbus.lock().await.history.subscribe_state();
+                tokio::spawn(async move {
+                    if let Err(e) = server.run(snapshots, state).await {
+                        error!("{:?}", e);
+                    }
+                });
+            }
+            Err(e) => error!("No OPC UA server: {:?}", e),
+        }
+    }
+
+    // Start the state machines concurrently using tokio::spawn
+    let sm1: tokio::task::JoinHandle<()> = tokio::spawn({
+        let state_machine_modbus = Arc::clone(&state_machine_modbus);
+        async move {
+            info!("Starting state machine modbus");
+            // Locked per activity so the read state machine can reach the Modbus context in between
+            statemachine_modbus::StateMachine::run_shared(state_machine_modbus).await;
+        }
+    });
+
+    let sm2: tokio::task::JoinHandle<()> = tokio::spawn({
+        let state_machine_read = Arc::clone(&state_machine_read);
+        async move {
+            info!("Starting state machine read");
+            let mut sm = state_machine_read.lock().await;
+            sm.run().await;
+        }
+    });
+
+    Ok(vec![sm1, sm2])
+}
+
+/// Runs the control loops on every snapshot, falling back to the configured values when the
+/// snapshots stop.
+async fn run_control(
+    control: ControlSettings,
+    guard: WriteGuard,
+    mut snapshots: broadcast::Receiver<Snapshot>,
+    shared_config: Arc<Mutex<Config>>,
+    state_machine_modbus: Arc<Mutex<ModbusStateMachine>>,
+) {
+    let max_silence = control.max_silence();
+    let mut controller = Controller::new(control);
+    let mut unwritten: Vec<Setpoint> = Vec::new();
+    loop {
+        let snapshot = match timeout(max_silence, snapshots.recv()).await {
+            Ok(Ok(snapshot)) => Some(snapshot),
+            Ok(Err(RecvError::Lagged(missed))) => {
+                warn!("Control missed {} snapshots", missed);
+                continue;
+            }
+            Ok(Err(RecvError::Closed)) => break,
+            Err(_) => None,
+        };
+        let setpoints = {
+            let config = shared_config.lock().await;
+            let configured = |name: &str| config.write_registers.iter().find(|r| r.name == name).map(|r| r.value);
+            match &snapshot {
+                Some(snapshot) => controller.update(snapshot, &configured),
+                None => controller.cut_off(&configured),
+            }
+        };
+        if snapshot.is_none() && !setpoints.is_empty() {
+            warn!("No readings for {:?}, control loops cut off", max_silence);
+        }
+        // A fallback that could not be written is retried until a newer setpoint replaces it
+        unwritten.retain(|old: &Setpoint| !setpoints.iter().any(|new| new.write_register == old.write_register));
+        for setpoint in unwritten.drain(..).chain(setpoints).collect::<Vec<_>>() {
+            if let Err(e) = write_setpoint(&setpoint, &guard, &shared_config, &state_machine_modbus).await {
+                error!("Failed to write {}: {:?}", setpoint, e);
+                let rejected = matches!(e.downcast_ref::<WriteError>(), Some(WriteError::Rejected(_)));
+                if setpoint.kind == SetpointKind::Fallback && !rejected {
+                    unwritten.push(setpoint);
+                }
+            }
+        }
+    }
+}
+
+/// The ways to reach the meter: each of `modbus.paths`, or the one the `modbus` settings
+/// describe. Meters on a shared RS-485 bus queue their requests there instead of connecting
+/// themselves; a path whose bus cannot be opened is left out while others remain.
+async fn open_paths(config: &Config, settings: &Settings, buses: &mut Buses) -> Result<Vec<Path>> {
+    let modbus = &settings.modbus;
+    let configured = if modbus.paths.is_empty() {
+        vec![PathSettings { name: "default".to_string(), ..Default::default() }]
+    } else {
+        modbus.paths.clone()
+    };
+    let count = configured.len();
+    let mut paths = Vec::with_capacity(count);
+    for path in configured {
+        let on_path = modbus.on_path(&path);
+        let Some(bus) = &on_path.bus else {
+            paths.push(Path::new(path, Arc::new(TcpTransport), Arc::new(PingReachability)));
+            continue;
+        };
+        let ip = path.addresses.first().unwrap_or(&config.meter_data.ip);
+        match buses.open(bus, ip, config.meter_data.port, &on_path).await {
+            Ok(bus) => {
+                let transport = Arc::new(BusTransport::new(bus));
+                paths.push(Path::new(path, transport.clone(), transport));
+            }
+            Err(e) if count > 1 => error!("Leaving out path {}: failed to open its RS-485 bus: {:#}", path.name, e),
+            Err(e) => return Err(e),
+        }
+    }
+    if paths.is_empty() {
+        bail!("None of the meter's paths could be opened");
+    }
+    Ok(paths)
+}
+
+/// Writes `setpoint` to the address configured for its write register.
+async fn write_setpoint(
+    setpoint: &Setpoint,
+    guard: &WriteGuard,
+    shared_config: &Arc<Mutex<Config>>,
+    state_machine_modbus: &Arc<Mutex<ModbusStateMachine>>,
+) -> Result<()> {
+    let address = shared_config
+        .lock()
+        .await
+        .write_registers
+        .iter()
+        .find(|r| r.name == setpoint.write_register)
+        .map(|r| r.address)
+        .ok_or_else(|| anyhow::anyhow!("write register '{}' is no longer configured", setpoint.write_register))?;
+    let context = state_machine_modbus
+        .lock()
+        .await
+        .access_modbus_context()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("no Modbus connection"))?;
+    guard.write(&mut *context.lock().await, &setpoint.write_register, address, setpoint.value).await?;
+    info!("{}", setpoint);
+    Ok(())
+}
+
+/// Replaces the active alarm file read by `mgw_generic alarms`.
+fn write_active_alarms(path: &str, active: &[ActiveAlarm]) -> Result<()> {
+    // Written aside and renamed so a reader never sees half a file
+    let temporary = format!("{}.tmp", path);
+    std::fs::write(&temporary, serde_json::to_string_pretty(active)?)?;
+    std::fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+fn append_alarm_events(path: &str, events: &[AlarmEvent]) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for event in events {
+        writeln!(file, "{}", serde_json::to_string(event)?)?;
+    }
+    Ok(())
+}
+
+/// Fallback description of an event that brought the Modbus state machine back to Idle.
+fn describe(event: ModbusEvent) -> &'static str {
+    match event {
+        ModbusEvent::PingFailed => "no answer to ping",
+        ModbusEvent::SocketDead | ModbusEvent::VerificationFail => "connection lost",
+        ModbusEvent::SocketSetupFail => "connection refused",
+        ModbusEvent::NoSocket => "no connection",
+        ModbusEvent::ReconnectRequested => "reconnect requested",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,26 @@
+use config_meter_generic::config::Config;
+use common_meter_generic::Settings;
+use anyhow::{Context, Result};
+use crate::scanner::{draft_read_registers, scan, summarize, ScanOptions};
+use super::connection::connect;
+
+/// Scans an address range, prints which blocks answer and emits a draft `read_registers` YAML.
+pub async fn command_scan(config: &Config, settings: &Settings, options: ScanOptions, output: Option<&str>) -> Result<()> {
+    let mut client = connect(config, &settings.modbus).await?;
+    let results = scan(&mut client, &options).await?;
+
+    for line in summarize(&results) {
+        println!("{}", line);
+    }
+
+    let draft = draft_read_registers(&results);
+    match output {
+        Some(path) => {
+            std::fs::write(path, draft).with_context(|| format!("Failed to write draft profile to {}", path))?;
+            println!("Draft read_registers written to {}", path);
+        }
+        None => print!("\n{}", draft),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use config_meter_generic::config::Config;
+use common_meter_generic::{Settings, WriteGuard, WriteOutcome};
+use anyhow::{anyhow, Result};
+use super::connection::connect;
+
+/// Sets or clears one named bit of a status register, keeping the other bits as they are.
+pub async fn command_set_flag(config: &Config, settings: &Settings, name: &str, flag: &str, set: bool, dry_run: bool) -> Result<()> {
+    let address = config
+        .write_registers
+        .iter()
+        .map(|r| (r.name.as_str(), r.address))
+        .chain(config.read_registers.iter().map(|r| (r.name.as_str(), r.address)))
+        .find(|(n, _)| *n == name)
+        .map(|(_, address)| address)
+        .ok_or_else(|| anyhow!("Register '{}' is not defined in the configuration", name))?;
+
+    let profile = settings.profile(&config.meter_data.meter_type);
+    let info = profile.register(name);
+    if info.bit(flag).is_none() {
+        return Err(anyhow!("Register '{}' has no flag '{}' in the meter profile", name, flag));
+    }
+
+    let mut guard = WriteGuard::new(&settings.writes, &profile, "cli");
+    if dry_run {
+        guard.set_dry_run(true);
+    }
+
+    let mut client = connect(config, &settings.modbus).await?;
+    let action = if set { "set" } else { "cleared" };
+    match guard.write_flag(&mut client, name, address, &info, flag, set).await? {
+        WriteOutcome::DryRun => println!("Dry run: {} of {} would be {}", flag, name, action),
+        _ => println!("{} of {} {}", flag, name, action),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::io::{self, BufRead, Write};
+use config_meter_generic::config::Config;
+use common_meter_generic::{Settings, WriteGuard, WriteOutcome};
+use anyhow::{anyhow, bail, Result};
+use log::info;
+use super::connection::connect;
+
+/// Writes `value` to the write register called `name`.
+///
+/// The write goes through the safety rails of `writes`; unless `yes` is set it is confirmed on
+/// the terminal first.
+pub async fn command_write(config: &Config, settings: &Settings, name: &str, value: f32, dry_run: bool, yes: bool) -> Result<()> {
+    let address = config
+        .write_registers
+        .iter()
+        .find(|r| r.name == name)
+        .map(|r| r.address)
+        .ok_or_else(|| anyhow!("Register '{}' is not among the write_registers of the configuration", name))?;
+
+    let mut guard = WriteGuard::new(&settings.writes, &settings.profile(&config.meter_data.meter_type), "cli");
+    if dry_run {
+        guard.set_dry_run(true);
+    }
+
+    let mut client = connect(config, &settings.modbus).await?;
+    if !yes && !guard.dry_run() && guard.check(name, address, value).is_ok() {
+        let old = client.read_float(address, guard.word_order(name)).await.map_or("unknown".to_string(), |old| old.to_string());
+        print!("Write {} to {} at address {} (currently {})? [y/N] ", value, name, address, old);
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            bail!("Write not confirmed; pass --yes to write without asking");
+        }
+    }
+
+    info!("Writing value {} to register {} at address {}", value, name, address);
+    match guard.write(&mut client, name, address, value).await? {
+        WriteOutcome::DryRun => println!("Dry run: {} would be written to {} at address {}", value, name, address),
+        _ => println!("Wrote {} to {} at address {}", value, name, address),
+    }
+
+    Ok(())
+}
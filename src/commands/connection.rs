@@ -0,0 +1,40 @@
+use config_meter_generic::config::Config;
+use common_meter_generic::{address, Bus, MeterClient};
+use common_meter_generic::settings::ModbusSettings;
+use anyhow::{anyhow, Context as _, Result};
+use log::{info, warn};
+
+/// Opens a Modbus connection to the configured meter for one-shot commands, on the first of
+/// `modbus.paths` that works when the meter has redundant ones.
+pub async fn connect(config: &Config, settings: &ModbusSettings) -> Result<MeterClient> {
+    let port = config.meter_data.port;
+    let mut error = None;
+    for path in &settings.paths {
+        let ip = path.addresses.first().unwrap_or(&config.meter_data.ip);
+        match connect_to(ip, port, &settings.on_path(path)).await {
+            Ok(client) => {
+                info!("Connected on path {}", path.name);
+                return Ok(client);
+            }
+            Err(e) => {
+                warn!("Failed to connect on path {}: {:#}", path.name, e);
+                error = Some(e);
+            }
+        }
+    }
+    match error {
+        Some(e) => Err(e),
+        None => connect_to(&config.meter_data.ip, port, settings).await,
+    }
+}
+
+async fn connect_to(ip: &str, port: u16, settings: &ModbusSettings) -> Result<MeterClient> {
+    let endpoints = address::endpoints(ip, port, settings).map_err(|e| anyhow!("Invalid meter address: {}", e))?;
+
+    if let Some(bus) = &settings.bus {
+        let bus = Bus::open(bus, ip, port, settings).await.context("Failed to open the RS-485 bus")?;
+        return Ok(bus.client(settings));
+    }
+
+    MeterClient::connect(endpoints, settings).await.context("Failed to connect to the meter")
+}
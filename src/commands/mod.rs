@@ -0,0 +1,22 @@
+pub mod command_run;
+pub mod command_read;
+pub mod command_write;
+pub mod command_scan;
+pub mod command_check_config;
+pub mod command_dump_profile;
+pub mod command_alarms;
+pub mod command_set_flag;
+pub mod command_identify;
+pub mod command_opcua_model;
+mod connection;
+
+pub use command_run::command_run;
+pub use command_read::command_read;
+pub use command_write::command_write;
+pub use command_scan::command_scan;
+pub use command_check_config::command_check_config;
+pub use command_dump_profile::command_dump_profile;
+pub use command_alarms::command_alarms;
+pub use command_set_flag::command_set_flag;
+pub use command_identify::command_identify;
+pub use command_opcua_model::command_opcua_model;
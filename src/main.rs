@@ -1,18 +1,24 @@
+mod cli;
+mod commands;
+mod scanner;
 mod update_log_levels;
 
-use std::{str::FromStr, sync::Arc};
-use tokio::sync::Mutex;
+use std::str::FromStr;
 use config_meter_generic::config::Config;
+use common_meter_generic::Settings;
 use anyhow::{Context, Result};
+use clap::Parser;
 use log::{error, info, LevelFilter};
 use env_logger::Builder;
 use chrono::Local;
 use std::io::Write;
-use update_log_levels::update_log_levels;
+use cli::{Cli, Command, FlagState};
+use scanner::{RegisterTable, ScanOptions};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let config_path: &str = "mgw_config.yaml";
+    let cli = Cli::parse();
+    let config_path: &str = &cli.config;
     info!("Loading configuration from {}", config_path);
 
     // Load the initial configuration and handle errors with context
@@ -29,7 +35,34 @@ async fn main() -> Result<()> {
         }
     };
 
-    // Initialize the logger with a custom format and initial log levels from the config
+    let settings: Settings = Settings::from_file(config_path)?;
+
+    init_logger(&config);
+
+    match cli.command.unwrap_or(Command::Run { meters: Vec::new() }) {
+        Command::Run { meters } => commands::command_run(config_path, config, settings, &meters).await,
+        Command::Read { format } => commands::command_read(&config, &settings, format).await,
+        Command::Write { name, value, dry_run, yes } => {
+            commands::command_write(&config, &settings, &name, value, dry_run, yes).await
+        }
+        Command::SetFlag { name, flag, state, dry_run } => {
+            commands::command_set_flag(&config, &settings, &name, &flag, state == FlagState::On, dry_run).await
+        }
+        Command::Identify { format } => commands::command_identify(&config, &settings, format).await,
+        Command::Scan { start, end, block, table, output } => {
+            let tables = if table.is_empty() { vec![RegisterTable::Holding, RegisterTable::Input] } else { table };
+            let options = ScanOptions { start, end, block, tables };
+            commands::command_scan(&config, &settings, options, output.as_deref()).await
+        }
+        Command::CheckConfig => commands::command_check_config(config_path, &config, &settings),
+        Command::DumpProfile => commands::command_dump_profile(&config, &settings),
+        Command::OpcuaModel { format } => commands::command_opcua_model(&config, &settings, format),
+        Command::Alarms { format } => commands::command_alarms(&settings, format),
+    }
+}
+
+/// Initializes the logger with a custom format and initial log levels from the config.
+fn init_logger(config: &Config) {
     let mut builder = Builder::new();
     builder.format(|buf, record| {
         let module_path = record.module_path().unwrap_or("unknown");
@@ -50,50 +83,4 @@ async fn main() -> Result<()> {
     builder.filter(Some("statemachine_read"), LevelFilter::from_str(&config.debug.statemachine_read).unwrap_or(LevelFilter::Info));
 
     builder.init();
-
-    info!("Starting the application...");
-
-    let shared_config: Arc<Mutex<Config>> = Arc::new(Mutex::new(config));
-    info!("Shared configuration created");
-
-    // Start the log level update task
-    let config_path = config_path.to_string();
-    let shared_config_clone = Arc::clone(&shared_config);
-    tokio::spawn(async move {
-        if let Err(e) = update_log_levels(&config_path, shared_config_clone).await {
-            error!("Failed to update log levels: {:?}", e);
-        }
-    });
-
-    let state_machine_modbus = statemachine_modbus::StateMachine::new(Arc::clone(&shared_config));
-    let state_machine_read =
-        statemachine_read::StateMachine::new(Arc::clone(&shared_config), Arc::clone(&state_machine_modbus));
-
-    info!("State machines created");
-
-    // Start the state machines concurrently using tokio::spawn
-    let sm1: tokio::task::JoinHandle<()> = tokio::spawn({
-        let state_machine_modbus = Arc::clone(&state_machine_modbus);
-        async move {
-            info!("Starting state machine modbus");
-            let mut sm = state_machine_modbus.lock().await;
-            sm.run().await;
-        }
-    });
-
-    let sm2: tokio::task::JoinHandle<()> = tokio::spawn({
-        let state_machine_read = Arc::clone(&state_machine_read);
-        async move {
-            info!("Starting state machine read");
-            let mut sm = state_machine_read.lock().await;
-            sm.run().await;
-        }
-    });
-
-    // Await the state machines to complete
-    let _ = tokio::join!(sm1, sm2);
-
-    info!("Application finished");
-
-    Ok(())
 }
@@ -0,0 +1,240 @@
+use std::fmt;
+use tokio_modbus::prelude::Exception;
+use common_meter_generic::{MeterClient, ModbusError};
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use log::{debug, info};
+
+/// Register table a scan walks through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RegisterTable {
+    Holding,
+    Input,
+}
+
+impl fmt::Display for RegisterTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterTable::Holding => write!(f, "holding"),
+            RegisterTable::Input => write!(f, "input"),
+        }
+    }
+}
+
+/// Address range and block size of a scan.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub start: u16,
+    pub end: u16,
+    pub block: u16,
+    pub tables: Vec<RegisterTable>,
+}
+
+/// What the meter answered for one probed block.
+#[derive(Debug, Clone)]
+pub enum BlockOutcome {
+    Answered(Vec<u16>),
+    Exception(Exception),
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockResult {
+    pub table: RegisterTable,
+    pub address: u16,
+    pub count: u16,
+    pub outcome: BlockOutcome,
+}
+
+/// Data type guessed from the raw contents of a two-word register pair.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuessedType {
+    Float32(f32),
+    Int32(i32),
+    UInt16(u16),
+    Unknown,
+}
+
+impl fmt::Display for GuessedType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuessedType::Float32(v) => write!(f, "float32, sample {}", v),
+            GuessedType::Int32(v) => write!(f, "int32, sample {}", v),
+            GuessedType::UInt16(v) => write!(f, "uint16, sample {}", v),
+            GuessedType::Unknown => write!(f, "type unknown, all zero"),
+        }
+    }
+}
+
+/// Walks the configured address range on every requested table, one block at a time.
+///
+/// Exception responses are recorded per block; a transport error aborts the scan.
+pub async fn scan(client: &mut MeterClient, options: &ScanOptions) -> Result<Vec<BlockResult>> {
+    if options.start > options.end {
+        bail!("Start address {} is greater than end address {}", options.start, options.end);
+    }
+    if options.block == 0 || options.block > 125 {
+        bail!("Block size must be between 1 and 125, got {}", options.block);
+    }
+
+    let mut results = Vec::new();
+    for &table in &options.tables {
+        info!("Scanning {} registers {}..={}", table, options.start, options.end);
+        for (address, count) in blocks(options.start, options.end, options.block) {
+            let response = match table {
+                RegisterTable::Holding => client.read_holding_registers(address, count).await,
+                RegisterTable::Input => client.read_input_registers(address, count).await,
+            };
+            let outcome = match response {
+                Ok(words) => BlockOutcome::Answered(words),
+                Err(ModbusError::Exception(exception)) => BlockOutcome::Exception(exception),
+                Err(e) => bail!("Scan aborted at {} address {}: {}", table, address, e),
+            };
+            debug!("{} {} x{}: {:?}", table, address, count, outcome);
+            results.push(BlockResult { table, address, count, outcome });
+        }
+    }
+
+    Ok(results)
+}
+
+/// Splits `start..=end` into blocks of at most `block` registers, as address and count. The
+/// arithmetic is done in u32 so a range ending at 65535 neither overflows nor wraps around.
+pub fn blocks(start: u16, end: u16, block: u16) -> impl Iterator<Item = (u16, u16)> {
+    let end = u32::from(end);
+    let block = u32::from(block.max(1));
+    (u32::from(start)..=end)
+        .step_by(block as usize)
+        .map(move |address| (address as u16, (end - address + 1).min(block) as u16))
+}
+
+/// Guesses whether a big-endian word pair holds a float, a 32-bit integer or a single 16-bit value.
+pub fn guess_type(high: u16, low: u16) -> GuessedType {
+    let bits = ((high as u32) << 16) | low as u32;
+    if bits == 0 {
+        return GuessedType::Unknown;
+    }
+
+    let float = f32::from_bits(bits);
+    if float.is_normal() && (1e-3..1e7).contains(&float.abs()) {
+        return GuessedType::Float32(float);
+    }
+    if high == 0 && low != 0 {
+        return GuessedType::UInt16(low);
+    }
+    GuessedType::Int32(bits as i32)
+}
+
+/// Collapses consecutive blocks with the same kind of outcome into address ranges.
+pub fn summarize(results: &[BlockResult]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current: Option<(RegisterTable, u16, u16, String)> = None;
+
+    for result in results {
+        let label = match &result.outcome {
+            BlockOutcome::Answered(_) => "answered".to_string(),
+            BlockOutcome::Exception(e) => format!("exception: {}", e),
+        };
+        let last = result.address + (result.count - 1);
+        current = match current.take() {
+            Some((table, start, end, l)) if table == result.table && l == label && end.checked_add(1) == Some(result.address) => {
+                Some((table, start, last, l))
+            }
+            Some((table, start, end, l)) => {
+                lines.push(format!("{:<8} {:>6}..={:<6} {}", table, start, end, l));
+                Some((result.table, result.address, last, label))
+            }
+            None => Some((result.table, result.address, last, label)),
+        };
+    }
+    if let Some((table, start, end, l)) = current {
+        lines.push(format!("{:<8} {:>6}..={:<6} {}", table, start, end, l));
+    }
+
+    lines
+}
+
+/// Renders the answering blocks as a draft `read_registers` section.
+///
+/// The words of adjacent answering blocks are joined first, so pairs stay aligned whatever the
+/// block size. Every word pair becomes one entry named after its table and address, with the
+/// guessed type as a comment; a lone word at the end of a run is only noted. Input registers
+/// are emitted commented out because the read path only polls holding registers.
+pub fn draft_read_registers(results: &[BlockResult]) -> String {
+    let mut yaml = String::from("read_registers:\n");
+
+    for (table, start, words) in answered_runs(results) {
+        let prefix = match table {
+            RegisterTable::Holding => "",
+            RegisterTable::Input => "# ",
+        };
+        let mut pairs = words.chunks_exact(2);
+        for (i, pair) in pairs.by_ref().enumerate() {
+            let address = start + (i as u16) * 2;
+            let guess = guess_type(pair[0], pair[1]);
+            yaml.push_str(&format!("{}  - name: {}_{}\n", prefix, table, address));
+            yaml.push_str(&format!("{}    address: {}  # {}\n", prefix, address, guess));
+        }
+        if let [word] = pairs.remainder() {
+            let address = start + (words.len() - 1) as u16;
+            yaml.push_str(&format!("  # {} {}: lone word, uint16 sample {}\n", table, address, word));
+        }
+    }
+
+    yaml
+}
+
+/// Words of consecutive answering blocks of the same table, with the address of the first.
+fn answered_runs(results: &[BlockResult]) -> Vec<(RegisterTable, u16, Vec<u16>)> {
+    let mut runs: Vec<(RegisterTable, u16, Vec<u16>)> = Vec::new();
+    for result in results {
+        let BlockOutcome::Answered(words) = &result.outcome else {
+            continue;
+        };
+        match runs.last_mut() {
+            Some((table, start, run))
+                if *table == result.table && u32::from(*start) + run.len() as u32 == u32::from(result.address) =>
+            {
+                run.extend_from_slice(words)
+            }
+            _ => runs.push((result.table, result.address, words.clone())),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answered(address: u16, words: &[u16]) -> BlockResult {
+        BlockResult { table: RegisterTable::Holding, address, count: words.len() as u16, outcome: BlockOutcome::Answered(words.to_vec()) }
+    }
+
+    #[test]
+    fn blocks_stop_at_the_last_address() {
+        assert_eq!(blocks(65530, 65535, 4).collect::<Vec<_>>(), vec![(65530, 4), (65534, 2)]);
+        assert_eq!(blocks(65535, 65535, 2).collect::<Vec<_>>(), vec![(65535, 1)]);
+        assert_eq!(blocks(0, 65535, 125).count(), 525);
+        assert_eq!(blocks(0, 65535, 125).last(), Some((65500, 36)));
+        assert_eq!(blocks(100, 104, 2).collect::<Vec<_>>(), vec![(100, 2), (102, 2), (104, 1)]);
+    }
+
+    #[test]
+    fn odd_blocks_keep_word_pairs_aligned() {
+        let float = 230.0f32.to_bits();
+        let (high, low) = ((float >> 16) as u16, float as u16);
+        let results = [answered(100, &[high, low, high]), answered(103, &[low, high, low])];
+        let draft = draft_read_registers(&results);
+        assert_eq!(draft.matches("float32, sample 230").count(), 3);
+        for address in [100, 102, 104] {
+            assert!(draft.contains(&format!("address: {}  #", address)), "{}", draft);
+        }
+
+        // A gap ends the run, and its odd word is left over
+        let results = [answered(100, &[high, low, high]), answered(200, &[high, low])];
+        let draft = draft_read_registers(&results);
+        assert!(draft.contains("holding 102: lone word"), "{}", draft);
+        assert!(draft.contains("address: 200  # float32"), "{}", draft);
+        assert!(!draft.contains("address: 102"), "{}", draft);
+    }
+}
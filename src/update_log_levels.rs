@@ -1,11 +1,16 @@
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use config_meter_generic::config::Config;
+use common_meter_generic::Settings;
 use anyhow::Result;
 use log::{error, info, LevelFilter};
 use std::str::FromStr;
 
-pub async fn update_log_levels(config_path: &str, shared_config: Arc<Mutex<Config>>) -> Result<()> {
+pub async fn update_log_levels(
+    config_path: &str,
+    shared_config: Arc<Mutex<Config>>,
+    shared_settings: Arc<Mutex<Settings>>,
+) -> Result<()> {
     loop {
         // Load the configuration and handle errors with context
         match Config::from_file(config_path) {
@@ -28,6 +33,16 @@ pub async fn update_log_levels(config_path: &str, shared_config: Arc<Mutex<Confi
             }
         }
 
+        match Settings::from_file(config_path) {
+            Ok(new_settings) => {
+                let mut settings = shared_settings.lock().await;
+                *settings = new_settings;
+            }
+            Err(e) => {
+                error!("Failed to update gateway settings: {:?}", e);
+            }
+        }
+
         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
     }
 }
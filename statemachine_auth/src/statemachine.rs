@@ -1,14 +1,20 @@
-// statemachine_meter_generic/src/statemachine.rs
+// statemachine_auth/src/statemachine.rs
 
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 mod handlers;
 use handlers::{handle_idle, handle_read, handle_write, handle_verify};
 use config_meter_generic::config::Config;
+use common_meter_generic::{Drift, Reconciler, Settings};
+use common_meter_generic::settings::WordOrder;
+use tokio::sync::broadcast;
 use tokio_modbus::client::Context as ModbusContext;
+use fsm_meter_generic::{History, Machine, Timer, Transition};
+use fsm_meter_generic::history::DEFAULT_CAPACITY;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum State {
     Idle,
     Read,
@@ -16,33 +22,119 @@ pub enum State {
     Verify,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Poll,
+    ReadSuccessful,
+    ReadFail,
+    WriteSuccessful,
+    WriteFail,
+    VerificationPass,
+    VerificationFail,
+}
+
+/// A value written in WRITE, for VERIFY to read back.
+#[derive(Debug, Clone)]
+struct Written {
+    name: String,
+    address: u16,
+    value: f32,
+    order: WordOrder,
+}
+
 pub struct StateMachine {
     pub state: State,
+    /// Transitions taken so far, and subscriptions to the state
+    pub history: History<State, Event>,
     pub meter_data: Option<String>,
     pub write_data: Option<String>,
     pub modbus_context: Option<Arc<Mutex<ModbusContext>>>,
+    has_write_registers: bool,
+    /// Which write registers need writing, and drift reports
+    reconciler: Reconciler,
+    /// Registers written in WRITE, for VERIFY to read back
+    unverified: Vec<Written>,
     config: Arc<Mutex<Config>>,
+    settings: Arc<Mutex<Settings>>,
 }
 
 impl StateMachine {
-    pub fn new(config: Arc<Mutex<Config>>) -> Arc<Mutex<Self>>  {
+    pub fn new(config: Arc<Mutex<Config>>, settings: Arc<Mutex<Settings>>) -> Arc<Mutex<Self>>  {
         Arc::new(Mutex::new(StateMachine {
             state: State::Idle,
+            history: History::new(State::Idle, DEFAULT_CAPACITY),
             meter_data: None,
             write_data: None,
             modbus_context: None,
+            has_write_registers: false,
+            reconciler: Reconciler::default(),
+            unverified: Vec::new(),
             config,
+            settings,
         }))
     }
 
+    /// Write registers found changed on the meter from now on.
+    pub fn subscribe_drift(&self) -> broadcast::Receiver<Drift> {
+        self.reconciler.subscribe_drift()
+    }
+
     pub async fn run(&mut self) {
         loop {
-            match &self.state {
-                State::Idle => handle_idle(self).await,
-                State::Read => handle_read(self).await,
-                State::Write => handle_write(self).await,
-                State::Verify => handle_verify(self).await,
+            if let Err(e) = fsm_meter_generic::step(self).await {
+                eprintln!("{}", e);
             }
         }
     }
 }
+
+/// Guard: only go on to WRITE when there is something to write.
+fn has_write_registers(state_machine: &StateMachine) -> bool {
+    state_machine.has_write_registers
+}
+
+impl Machine for StateMachine {
+    type State = State;
+    type Event = Event;
+
+    const TRANSITIONS: &'static [Transition<Self>] = &[
+        Transition::new(State::Idle, Event::Poll, State::Read),
+        Transition::new(State::Read, Event::ReadSuccessful, State::Write).when(has_write_registers),
+        Transition::new(State::Read, Event::ReadSuccessful, State::Idle),
+        Transition::new(State::Read, Event::ReadFail, State::Idle),
+        Transition::new(State::Write, Event::WriteSuccessful, State::Verify),
+        Transition::new(State::Write, Event::WriteFail, State::Idle),
+        Transition::new(State::Verify, Event::VerificationPass, State::Idle),
+        Transition::new(State::Verify, Event::VerificationFail, State::Idle),
+    ];
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    fn history(&self) -> &History<State, Event> {
+        &self.history
+    }
+
+    async fn activity(&mut self, state: State) -> Option<Event> {
+        match state {
+            State::Idle => handle_idle(self).await,
+            State::Read => handle_read(self).await,
+            State::Write => handle_write(self).await,
+            State::Verify => handle_verify(self).await,
+        }
+    }
+
+    fn timer(&self, state: State) -> Option<Timer<Event>> {
+        match state {
+            State::Idle => Some(Timer::new(Duration::from_secs(5), Event::Poll)),
+            _ => None,
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/readme_transitions.rs"));
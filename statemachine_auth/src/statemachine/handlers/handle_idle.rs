@@ -1,9 +1,7 @@
-use tokio::time::sleep;
-use std::time::Duration;
-use crate::statemachine::StateMachine;
+use crate::statemachine::{StateMachine, Event};
 
-pub async fn handle_idle(_state_machine: &mut StateMachine) {
+/// Nothing to do while idle; the state's timer moves on to READ.
+pub async fn handle_idle(_state_machine: &mut StateMachine) -> Option<Event> {
     println!("State: IDLE");
-    sleep(Duration::from_secs(5)).await;
-
+    None
 }
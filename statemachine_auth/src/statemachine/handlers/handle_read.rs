@@ -1,37 +1,37 @@
 // mgw_generic/statemachine_meter_generic/src/statemachine/handlers/handle_read.rs
 
 use tokio_modbus::prelude::*;
-use crate::statemachine::{StateMachine, State};
+use crate::statemachine::{StateMachine, Event};
 use std::error::Error;
 use config_meter_generic::config::ConfigRegister;
 
 /// Handles the READ operation within the state machine.
-pub async fn handle_read(state_machine: &mut StateMachine) {
+pub async fn handle_read(state_machine: &mut StateMachine) -> Option<Event> {
     println!("State: READ");
 
     if let Some(_socket) = state_machine.modbus_context.as_ref() {
         println!("Using established socket connection for read operation.");
+        let has_write_registers = state_machine.config.lock().await.has_write_registers();
         match perform_read_operations(state_machine).await {
             Ok(_) => {
                 println!("Read operation completed successfully.");
-                let locked_config = state_machine.config.lock().await;
-                let config = &*locked_config; // Dereference to access the content
-                if config.has_write_registers() {
+                if has_write_registers {
                     println!("Write registers are available, transitioning to WRITE state.");
-                    state_machine.state = State::Write;
                 } else {
                     println!("No write registers available, transitioning to IDLE state.");
-                    state_machine.state = State::Idle;
                 }
+                state_machine.has_write_registers = has_write_registers;
+                Some(Event::ReadSuccessful)
             },
             Err(e) => {
                 eprintln!("Read operation failed: {}", e);
-                state_machine.state = State::Idle; // Transition to Idle on error
+                state_machine.history.note(e.to_string());
+                Some(Event::ReadFail) // Transition to Idle on error
             }
         }
     } else {
         println!("No active socket connection found, unable to perform read operation.");
-        state_machine.state = State::Idle; // Transition to Idle state
+        Some(Event::ReadFail) // Transition to Idle state
     }
 }
 
@@ -68,7 +68,7 @@ async fn perform_read_operations(state_machine: &mut StateMachine) -> Result<(),
     let locked_config: tokio::sync::MutexGuard<config_meter_generic::config::Config> = state_machine.config.lock().await;
     println!("Configuration locked successfully.");
 
-    let config: &config_meter_generic::config::Config = &*locked_config;
+    let config: &config_meter_generic::config::Config = &locked_config;
     println!("Configuration accessed.");
 
     let read_registers: Vec<ConfigRegister> = config.get_read_registers();
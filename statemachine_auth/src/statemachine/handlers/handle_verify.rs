@@ -1,18 +1,41 @@
-use crate::statemachine::{StateMachine, State};
+use crate::statemachine::{StateMachine, Event};
+use crate::statemachine::handlers::handle_write::read_current_value;
 use std::error::Error;
+use tokio::time::Instant;
+use log::warn;
 
-pub async fn handle_verify(state_machine: &mut StateMachine) {
+pub async fn handle_verify(state_machine: &mut StateMachine) -> Option<Event> {
     println!("State: VERIFY");
-    let _verify_result = verify_written_data().await;
-    state_machine.state = State::Idle;
+    match verify_written_data(state_machine).await {
+        Ok(_) => Some(Event::VerificationPass),
+        Err(e) => {
+            warn!("Verification failed: {}", e);
+            state_machine.history.note(e.to_string());
+            Some(Event::VerificationFail)
+        }
+    }
 }
 
-async fn verify_written_data() -> Result<(), Box<dyn Error>> {
-    use tokio::time::sleep;
-    use std::time::Duration;
-
-    // Simulate verifying written data
-    sleep(Duration::from_secs(2)).await;
+/// Reads back the registers written in WRITE. A value that differs is reported as drift and
+/// no longer counts as confirmed, so the next WRITE sends it again.
+async fn verify_written_data(state_machine: &mut StateMachine) -> Result<(), Box<dyn Error>> {
     println!("Verifying written data...");
+    let written = std::mem::take(&mut state_machine.unverified);
+    let Some(modbus_context) = state_machine.modbus_context.clone() else {
+        return Err("Modbus context not available".into());
+    };
+    let mut context = modbus_context.lock().await;
+    let now = Instant::now();
+    let mut mismatches = Vec::new();
+    for register in &written {
+        let current = read_current_value(&mut context, register.address, register.order).await;
+        if state_machine.reconciler.needs_write(&register.name, register.address, register.value, current, now) {
+            let read = current.map_or("nothing".to_string(), |current| current.to_string());
+            mismatches.push(format!("{} wrote {}, read back {}", register.name, register.value, read));
+        }
+    }
+    if !mismatches.is_empty() {
+        return Err(mismatches.join(", ").into());
+    }
     Ok(())
 }
@@ -0,0 +1,3 @@
+fn main() {
+    fsm_meter_generic::readme::write_check("../README.md", "### statemachine_meter_generic", "readme_transitions.rs");
+}
@@ -1,15 +1,7 @@
-use tokio::time::sleep;
-use std::time::Duration;
-use crate::statemachine::{StateMachine, State};
+use crate::statemachine::{StateMachine, Event};
 
-pub async fn handle_idle(state_machine: &mut StateMachine) {
+/// Nothing to do while idle; the state's timer decides between PING and READ.
+pub async fn handle_idle(_state_machine: &mut StateMachine) -> Option<Event> {
     println!("State: IDLE");
-    sleep(Duration::from_secs(5)).await;
-
-    if state_machine.first_idle {
-        state_machine.state = State::Ping;
-        state_machine.first_idle = false;
-    } else {
-        state_machine.state = State::Read;
-    }
+    None
 }
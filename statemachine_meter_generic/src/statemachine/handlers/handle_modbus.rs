@@ -1,37 +1,13 @@
 // statemachine_meter_generic/src/statemachine/handlers/handle_modbus.rs
-use std::error::Error;
-use std::fmt;
-use std::net::SocketAddr;
-use tokio::time::{self, Duration};
 use tokio_modbus::prelude::*;
-use tokio_modbus::client::{tcp, Context};
+use tokio_modbus::client::Context;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 
-use crate::statemachine::{StateMachine, State};
-use config_meter_generic::config::validate_ip_and_port;
-
-/// Custom error type for Modbus operations.
-#[derive(Debug)]
-#[allow(dead_code)]
-enum SocketError {
-    Timeout,
-    ConnectionFailed(String),
-    Other(Box<dyn Error>), // Consider whether this variant is necessary or should be removed
-}
-
-impl fmt::Display for SocketError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            SocketError::Timeout => write!(f, "Connection attempt timed out"),
-            SocketError::ConnectionFailed(msg) => write!(f, "Failed to connect: {}", msg),
-            SocketError::Other(e) => write!(f, "An error occurred: {}", e),
-        }
-    }
-}
-
-impl Error for SocketError {}
+use crate::statemachine::{StateMachine, Event};
+use common_meter_generic::{address, client, ModbusError};
+use common_meter_generic::settings::ModbusSettings;
 
 /// Checks if the Modbus context is still active by attempting to read a known register.
 async fn is_context_alive(context: &mut Context) -> bool {
@@ -39,44 +15,39 @@ async fn is_context_alive(context: &mut Context) -> bool {
     context.read_holding_registers(STATUS_REGISTER_ADDRESS, 1).await.is_ok()
 }
 
-/// Attempts to establish a new Modbus context to the specified IP and port, with a timeout of 5 seconds.
-async fn setup_modbus_context(ip: &str, port: u16) -> Result<Arc<Mutex<Context>>, Box<dyn std::error::Error>> {
-// async fn setup_modbus_context(ip: &str, port: u16) -> Result<Context, SocketError> {
-    let address = format!("{}:{}", ip, port).parse::<SocketAddr>().map_err(|e| SocketError::ConnectionFailed(e.to_string()))?;
-    println!("Attempting to connect to the Modbus device at {}", address);
+/// Attempts to establish a new Modbus context to the meter's addresses in turn. Each attempt is
+/// bounded by the connect timeout, so a dead address leaves time for the next one. TLS and
+/// framing follow `settings`.
+async fn setup_modbus_context(ip: &str, port: u16, settings: &ModbusSettings) -> Result<Arc<Mutex<Context>>, ModbusError> {
+    let endpoints = address::endpoints(ip, port, settings).map_err(ModbusError::Config)?;
+    println!("Attempting to connect to the Modbus device at {}", endpoints[0]);
 
-    match time::timeout(Duration::from_secs(5), tcp::connect(address)).await {
-        Ok(Ok(context)) => {
+    match client::open(&endpoints, settings).await {
+        Ok(context) => {
             println!("Modbus TCP connection established.");
             Ok(Arc::new(Mutex::new(context)))
-
         },
-        Ok(Err(e)) => {
+        Err(e) => {
             println!("Failed to connect to the Modbus device: {}", e);
-            Err(Box::new(SocketError::ConnectionFailed(e.to_string())))
-        },
-        Err(_) => {
-            println!("Connection attempt to Modbus device timed out after 5 seconds.");
-            Err(Box::new(SocketError::Timeout))
+            Err(e)
         }
     }
 }
 
 /// Handles the Modbus connection logic based on the current state of the state machine.
-
-pub async fn handle_modbus(state_machine: &mut StateMachine) {
+pub async fn handle_modbus(state_machine: &mut StateMachine) -> Option<Event> {
     println!("State: Modbus Connection Handling");
 
-    if let Some(ref modbus_context) = state_machine.modbus_context {
+    if let Some(modbus_context) = state_machine.modbus_context.clone() {
         let mut context = modbus_context.lock().await;
         if is_context_alive(&mut context).await {
             println!("Modbus context is active, transitioning to READ state.");
-            state_machine.state = State::Read;
-            return;
+            return Some(Event::SocketExists);
         } else {
             println!("Modbus context is not active, unable to perform read operation.");
-            state_machine.state = State::Idle;
-            return;
+            drop(context);
+            state_machine.modbus_context = None;
+            return Some(Event::SocketDead);
         }
     }
 
@@ -84,22 +55,23 @@ pub async fn handle_modbus(state_machine: &mut StateMachine) {
     let locked_config = state_machine.config.lock().await;
     let config = &*locked_config;
 
-    if let Err(e) = validate_ip_and_port(&config.meter_data.ip, config.meter_data.port) {
-        eprintln!("Invalid IP or port: {}", e);
-        state_machine.state = State::Idle;
-        return;
+    let settings = state_machine.settings.lock().await.modbus.clone();
+    if let Err(e) = address::endpoints(&config.meter_data.ip, config.meter_data.port, &settings) {
+        eprintln!("Invalid meter address: {}", e);
+        state_machine.history.note(e);
+        return Some(Event::SocketSetupFail);
     }
 
-    match setup_modbus_context(&config.meter_data.ip, config.meter_data.port).await {
+    match setup_modbus_context(&config.meter_data.ip, config.meter_data.port, &settings).await {
         Ok(context) => {
             println!("Modbus connection established.");
             state_machine.modbus_context = Some(context);
-            state_machine.state = State::Read;
+            Some(Event::NoSocket)
         },
         Err(e) => {
             eprintln!("Failed to establish Modbus connection: {}", e);
-            state_machine.first_idle = true;
-            state_machine.state = State::Idle;
+            state_machine.history.note(e.to_string());
+            Some(Event::SocketSetupFail)
         }
     }
 }
@@ -1,7 +1,7 @@
-use crate::statemachine::{StateMachine, State};
+use crate::statemachine::{StateMachine, Event};
 use std::error::Error;
 
-pub async fn handle_ping(state_machine: &mut StateMachine) {
+pub async fn handle_ping(state_machine: &mut StateMachine) -> Option<Event> {
     println!("State: PING");
 
     // Access the shared configuration safely
@@ -10,12 +10,10 @@ pub async fn handle_ping(state_machine: &mut StateMachine) {
 
     let ping_result = ping_meter(ip_address).await;
     match ping_result {
-        Ok(_) => {
-            state_machine.state = State::Modbus;
-        }
-        Err(_) => {
-            state_machine.first_idle = true;
-            state_machine.state = State::Idle;
+        Ok(_) => Some(Event::PingSuccessful),
+        Err(e) => {
+            state_machine.history.note(e.to_string());
+            Some(Event::PingFailed)
         }
     }
 }
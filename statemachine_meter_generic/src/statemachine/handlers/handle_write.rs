@@ -1,12 +1,16 @@
 // statemachine_meter_generic/src/statemachine/handlers/handle_write.rs
 
 use tokio_modbus::prelude::*;
-use crate::statemachine::{StateMachine, State};
+use crate::statemachine::{StateMachine, Event, Written};
 use std::error::Error;
 use config_meter_generic::config::ConfigWriteRegister;
 use tokio_modbus::client::Context;
+use tokio::time::Instant;
+use common_meter_generic::{WriteError, WriteGuard, WriteOutcome};
+use common_meter_generic::settings::WordOrder;
+use log::{error, info};
 
-pub async fn handle_write(state_machine: &mut StateMachine) {
+pub async fn handle_write(state_machine: &mut StateMachine) -> Option<Event> {
     println!("State: WRITE");
 
     if let Some(_socket) = state_machine.modbus_context.as_ref() {
@@ -14,29 +18,28 @@ pub async fn handle_write(state_machine: &mut StateMachine) {
         match perform_write_operations(state_machine).await {
             Ok(_) => {
                 println!("Write operation completed successfully.");
-                state_machine.state = State::Verify;
+                Some(Event::WriteSuccessful)
             },
             Err(e) => {
                 eprintln!("Write operation failed: {}", e);
-                state_machine.state = State::Idle; // Transition to Idle on error
+                state_machine.history.note(e.to_string());
+                Some(Event::WriteFail) // Transition to Idle on error
             }
         }
     } else {
         println!("No active socket connection found, unable to perform write operation.");
-        state_machine.state = State::Idle; // Transition to Idle state
+        Some(Event::WriteFail) // Transition to Idle state
     }
 }
 
 
 // modbus_write function capable of handling various data types
-async fn modbus_write(modbus_context: &mut Context, register: &ConfigWriteRegister) -> Result<(), Box<dyn Error>> {
+async fn modbus_write(modbus_context: &mut Context, register: &ConfigWriteRegister, order: WordOrder) -> Result<(), Box<dyn Error>> {
     let address = register.address;
     let value = register.value; // Assuming value is already in the correct format for transmission
 
-    // Convert float value to bytes assuming IEEE 754 floating-point format (common in Modbus TCP)
-    let value_as_u16 = f32::to_le_bytes(value).chunks(2)
-        .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
-        .collect::<Vec<u16>>();
+    // IEEE 754 float in the register's word order
+    let value_as_u16 = order.encode(value);
 
     // Handle the write operation, correctly mapping errors
     match modbus_context.write_multiple_registers(address, &value_as_u16).await {
@@ -47,6 +50,14 @@ async fn modbus_write(modbus_context: &mut Context, register: &ConfigWriteRegist
 
 
 
+/// Reads the current value of a write register in its word order; `None` if it cannot be read.
+pub(crate) async fn read_current_value(modbus_context: &mut Context, address: u16, order: WordOrder) -> Option<f32> {
+    match modbus_context.read_holding_registers(address, 2).await {
+        Ok(Ok(words)) => order.decode(&words),
+        _ => None,
+    }
+}
+
 /// Adjusted perform_write_operations to use modbus_write function
 async fn perform_write_operations(state_machine: &mut StateMachine) -> Result<(), Box<dyn Error>> {
     println!("Attempting to lock the configuration for writing.");
@@ -61,15 +72,47 @@ async fn perform_write_operations(state_machine: &mut StateMachine) -> Result<()
         return Err("No registers configured".into());
     }
 
+    let (guard, writes) = {
+        let settings = state_machine.settings.lock().await;
+        state_machine.reconciler.configure(&settings.writes);
+        (
+            WriteGuard::new(&settings.writes, &settings.profile(&locked_config.meter_data.meter_type), "statemachine_meter_generic"),
+            settings.writes.clone(),
+        )
+    };
+
     if let Some(ref mut modbus_context) = state_machine.modbus_context {
         let mut context = modbus_context.lock().await;
         println!("Modbus context is available, proceeding with the write operation.");
+        let reconciler = &mut state_machine.reconciler;
+        let now = Instant::now();
+        state_machine.unverified.clear();
         for reg in &write_registers {
-            println!("Writing value {} to address {}", reg.value, reg.address);
-            if let Err(e) = modbus_write(&mut context, reg).await {
-                println!("Failed to write register: {}", e);
+            let order = writes.word_order(&reg.name);
+            if !reconciler.is_due(&reg.name, reg.value, now) {
+                continue;
+            }
+            // Only values that differ on the meter are written
+            let old = read_current_value(&mut context, reg.address, order).await;
+            if !reconciler.needs_write(&reg.name, reg.address, reg.value, old, now) {
+                info!("Register {} already holds {}", reg.name, reg.value);
+                continue;
+            }
+            // Rejected and dry-run writes are audited by the guard; the other registers still go out
+            match guard.admit(&reg.name, reg.address, old, reg.value) {
+                Ok(true) => {}
+                Ok(false) | Err(WriteError::Rejected(_)) => continue,
+                Err(e) => return Err(Box::new(e)),
+            }
+            info!("Writing value {} to address {}", reg.value, reg.address);
+            if let Err(e) = modbus_write(&mut context, reg, order).await {
+                error!("Failed to write register {}: {}", reg.name, e);
+                guard.audit(&reg.name, reg.address, old, reg.value, WriteOutcome::Failed, Some(e.to_string()));
                 return Err(e);
             }
+            guard.audit(&reg.name, reg.address, old, reg.value, WriteOutcome::Written, None);
+            reconciler.confirm(&reg.name, reg.value, now);
+            state_machine.unverified.push(Written { name: reg.name.clone(), address: reg.address, value: reg.value, order });
         }
     } else {
         println!("Modbus context not available. Cannot perform write operation.");
@@ -1,2 +1,3 @@
 pub mod statemachine; 
+pub mod transport;
 pub use statemachine::StateMachine;
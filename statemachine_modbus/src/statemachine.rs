@@ -1,16 +1,23 @@
 // statemachine_meter_generic/src/statemachine.rs
 
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
 mod handlers;
+mod primary;
 use handlers::{handle_idle, handle_ping, handle_connect, handle_verify};
 use config_meter_generic::config::Config;
-use tokio_modbus::client::Context as ModbusContext;
-use tokio::time::{timeout, Duration};
+use common_meter_generic::{address, DeviceIdentification, Identity, MeterClient, Settings};
+use common_meter_generic::settings::{MeterProfile, ModbusSettings, PathSettings};
+use primary::{PrimaryProbe, Target};
+use crate::transport::{Path, PingReachability, Reachability, TcpTransport, Transport};
+use fsm_meter_generic::{History, Machine, Timer, Transition};
+use fsm_meter_generic::history::DEFAULT_CAPACITY;
+use tokio::time::{self, timeout, Duration, Instant};
 use anyhow::{Result, anyhow};
+use log::{debug, info, warn, error};
 
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum State {
     Idle,
     Ping,
@@ -18,59 +25,360 @@ pub enum State {
     Verify
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Initialization,
+    PingSuccessful,
+    PingFailed,
+    SocketExists,
+    SocketDead,
+    NoSocket,
+    SocketSetupFail,
+    VerificationPass,
+    VerificationFail,
+    ReconnectRequested,
+}
+
 pub struct StateMachine {
     pub state: State,
+    /// Transitions taken so far, and subscriptions to the state
+    pub history: History<State, Event>,
     pub meter_data: Option<String>,
-    pub modbus_context: Option<Arc<Mutex<ModbusContext>>>,
+    pub modbus_context: Option<Arc<Mutex<MeterClient>>>,
+    /// Identity read after connecting; `None` while not connected
+    identity: watch::Sender<Option<Identity>>,
+    /// What the meter reported to Read Device Identification on the current connection
+    device: Option<DeviceIdentification>,
+    /// Profile picked by device identification because `meter_type` has none
+    selected_profile: Option<String>,
     config: Arc<Mutex<Config>>,
+    settings: Arc<Mutex<Settings>>,
+    /// Ways to reach the meter, preferred one first
+    paths: Vec<Path>,
+    active_path: usize,
+    /// Name of the active path when there are several, for whoever wants to report it
+    path_name: watch::Sender<Option<String>>,
+    /// Checks on the preferred path while a fallback is active
+    primary_probe: Option<PrimaryProbe>,
+    keepalive_interval: Duration,
 }
 
 impl StateMachine {
-    pub fn new(config: Arc<Mutex<Config>>) -> Arc<Mutex<Self>>  {
+    pub fn new(config: Arc<Mutex<Config>>, settings: Arc<Mutex<Settings>>) -> Arc<Mutex<Self>>  {
+        Self::with_transport(config, settings, Arc::new(TcpTransport), Arc::new(PingReachability))
+    }
+
+    /// Creates the state machine with its own way of connecting and checking reachability,
+    /// e.g. the mocks from [`crate::transport`] in tests.
+    pub fn with_transport(
+        config: Arc<Mutex<Config>>,
+        settings: Arc<Mutex<Settings>>,
+        transport: Arc<dyn Transport>,
+        reachability: Arc<dyn Reachability>,
+    ) -> Arc<Mutex<Self>> {
+        let path = PathSettings { name: "default".to_string(), ..Default::default() };
+        Self::with_paths(config, settings, vec![Path::new(path, transport, reachability)])
+    }
+
+    /// Creates the state machine for a meter with redundant paths, preferred one first. It
+    /// fails over to the next path when the active one fails, and back to the first once that
+    /// has passed the checks in the background for `modbus.failback_secs`.
+    pub fn with_paths(config: Arc<Mutex<Config>>, settings: Arc<Mutex<Settings>>, paths: Vec<Path>) -> Arc<Mutex<Self>> {
+        assert!(!paths.is_empty(), "a meter needs at least one path");
+        let path_name = watch::channel((paths.len() > 1).then(|| paths[0].name().to_string())).0;
         Arc::new(Mutex::new(StateMachine {
             state: State::Idle,
+            history: History::new(State::Idle, DEFAULT_CAPACITY),
             meter_data: None,
             modbus_context: None,
+            identity: watch::channel(None).0,
+            device: None,
+            selected_profile: None,
             config,
+            settings,
+            paths,
+            active_path: 0,
+            path_name,
+            primary_probe: None,
+            keepalive_interval: Duration::ZERO,
         }))
     }
 
     pub async fn run(&mut self) {
         loop {
-            match &self.state {
-                State::Idle => handle_idle(self).await,
-                State::Ping => handle_ping(self).await,
-                State::Connect => handle_connect(self).await,
-                State::Verify => handle_verify(self).await,
+            self.step().await;
+        }
+    }
+
+    /// Runs the activity of the current state once and takes the resulting transition.
+    ///
+    /// Callers sharing the state machine should lock it per step, so other users such as
+    /// the read state machine can get at the Modbus context between steps.
+    pub async fn step(&mut self) {
+        if let Err(e) = fsm_meter_generic::step(self).await {
+            error!("{}", e);
+        }
+    }
+
+    /// Runs a shared state machine, locking it only for the activities and transitions. The
+    /// timers, such as the keepalive interval in Verify, run with the lock released, and the
+    /// interval counts from the start of the previous probe.
+    pub async fn run_shared(state_machine: Arc<Mutex<Self>>) {
+        loop {
+            let started = Instant::now();
+            let timer = match fsm_meter_generic::act(&mut *state_machine.lock().await).await {
+                Ok(timer) => timer,
+                Err(e) => {
+                    error!("{}", e);
+                    None
+                }
+            };
+            let Some(timer) = timer else {
+                continue;
+            };
+            time::sleep_until(started + timer.after).await;
+            if let Err(e) = fsm_meter_generic::fire(&mut *state_machine.lock().await, timer.event).await {
+                error!("{}", e);
+            }
+        }
+    }
+
+    /// Returns the Modbus settings on the active path and the profile of the meter type in use.
+    pub(crate) async fn load_settings(&self) -> (ModbusSettings, MeterProfile) {
+        let meter_type = match &self.selected_profile {
+            Some(selected) => selected.clone(),
+            None => self.config.lock().await.meter_data.meter_type.clone(),
+        };
+        let settings = self.settings.lock().await;
+        (settings.modbus.on_path(&self.path().settings), settings.profile(&meter_type))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.paths[self.active_path]
+    }
+
+    /// The meter's first address on the active path.
+    pub(crate) fn meter_ip(&self, config: &Config) -> String {
+        self.path().settings.addresses.first().unwrap_or(&config.meter_data.ip).clone()
+    }
+
+    /// Name of the path the meter is reached on, or tried next, if it has redundant ones.
+    pub fn active_path(&self) -> Option<String> {
+        self.path_name.borrow().clone()
+    }
+
+    /// The active path as it changes.
+    pub fn subscribe_active_path(&self) -> watch::Receiver<Option<String>> {
+        self.path_name.subscribe()
+    }
+
+    /// Notes why the active path failed and moves on to the next one; a single path stays.
+    pub(crate) fn path_failed(&mut self, reason: impl Into<String>) {
+        let reason = reason.into();
+        if self.paths.len() < 2 {
+            self.history.note(reason);
+            return;
+        }
+        let failed = self.paths[self.active_path].name().to_string();
+        self.switch_path((self.active_path + 1) % self.paths.len());
+        warn!("Path {} failed ({}), failing over to {}", failed, reason, self.path().name());
+        self.history.note(format!("{}; failing over from path {} to {}", reason, failed, self.path().name()));
+    }
+
+    /// Starts checking the preferred path in the background while the meter is on a fallback.
+    pub(crate) async fn watch_primary(&mut self) {
+        if self.active_path == 0 || self.primary_probe.is_some() {
+            return;
+        }
+        let primary = self.paths[0].clone();
+        let (settings, profile) = {
+            let settings = self.settings.lock().await;
+            let meter_type = match &self.selected_profile {
+                Some(selected) => selected.clone(),
+                None => self.config.lock().await.meter_data.meter_type.clone(),
+            };
+            (settings.modbus.on_path(&primary.settings), settings.profile(&meter_type))
+        };
+        let config = self.config.lock().await;
+        let ip = primary.settings.addresses.first().unwrap_or(&config.meter_data.ip).clone();
+        let port = config.meter_data.port;
+        drop(config);
+        let hosts = match address::endpoints(&ip, port, &settings) {
+            Ok(endpoints) => endpoints.into_iter().map(|endpoint| endpoint.host).collect(),
+            Err(_) => vec![ip.clone()],
+        };
+        info!("Checking path {} every {:?} while on {}", primary.name(), profile.keepalive.interval(), self.path().name());
+        let interval = profile.keepalive.interval();
+        let target = Target { hosts, ip, port, settings, keepalive: profile.keepalive };
+        self.primary_probe = Some(PrimaryProbe::start(primary, target, interval));
+    }
+
+    /// Whether the preferred path has passed its checks for long enough to fail back to it.
+    pub(crate) fn fail_back_due(&self, failback: Duration) -> bool {
+        let healthy_since = self.primary_probe.as_ref().and_then(PrimaryProbe::healthy_since);
+        self.active_path != 0 && healthy_since.is_some_and(|since| since.elapsed() >= failback)
+    }
+
+    pub(crate) fn fail_back(&mut self) {
+        info!("Path {} healthy for the failback period, failing back from {}", self.paths[0].name(), self.path().name());
+        self.history.note(format!("failing back from {} to {}", self.path().name(), self.paths[0].name()));
+        self.switch_path(0);
+    }
+
+    fn switch_path(&mut self, index: usize) {
+        self.active_path = index;
+        self.primary_probe = None;
+        self.path_name.send_replace(Some(self.paths[index].name().to_string()));
+    }
+
+    /// Drops the current Modbus context and restarts from Idle, so the next cycle reconnects.
+    pub async fn request_reconnect(&mut self, reason: &str) {
+        warn!("Reconnect requested: {}", reason);
+        self.history.note(reason);
+        if let Err(e) = fsm_meter_generic::fire(self, Event::ReconnectRequested).await {
+            info!("Ignoring reconnect request: {}", e);
+        }
+    }
+
+    /// Identity of the meter on the current connection.
+    pub fn identity(&self) -> Option<Identity> {
+        self.identity.borrow().clone()
+    }
+
+    /// The identity as it changes with each connection.
+    pub fn subscribe_identity(&self) -> watch::Receiver<Option<Identity>> {
+        self.identity.subscribe()
+    }
+
+    pub(crate) fn set_identity(&self, identity: Option<Identity>) {
+        self.identity.send_replace(identity);
+    }
+
+    /// Vendor, product code and revision of the meter on the current connection.
+    pub fn device(&self) -> Option<&DeviceIdentification> {
+        self.device.as_ref()
+    }
+
+    /// Profile device identification picked for a `meter_type` without one of its own.
+    pub fn selected_profile(&self) -> Option<&str> {
+        self.selected_profile.as_deref()
+    }
+
+    /// Checks an identified device against the profile of `meter_type`. A meter type without a
+    /// profile takes the one profile whose `device` matches, if there is exactly one.
+    pub(crate) async fn select_profile(&mut self, meter_type: &str, device: DeviceIdentification) -> Result<()> {
+        let settings = self.settings.lock().await;
+        let matching = settings.profiles_for(&device);
+        self.selected_profile = None;
+        match settings.profiles.get(meter_type) {
+            Some(profile) if !profile.device.matches(&device) => {
+                let hint = match matching.as_slice() {
+                    [] => String::new(),
+                    names => format!("; it matches profile {}", names.join(", ")),
+                };
+                return Err(anyhow!("Connected device {} is not a {}{}", device, meter_type, hint));
             }
+            Some(_) => {}
+            None => match matching.as_slice() {
+                [name] => {
+                    info!("Meter type {} has no profile, using {} for {}", meter_type, name, device);
+                    self.selected_profile = Some(name.to_string());
+                }
+                [] => warn!("No profile matches {}, using the defaults", device),
+                names => warn!("Profiles {} all match {}, using the defaults", names.join(", "), device),
+            },
         }
+        drop(settings);
+        self.device = Some(device);
+        Ok(())
     }
 
-    pub async fn access_modbus_context(&self) -> Result<Option<Arc<Mutex<ModbusContext>>>> {
+    pub async fn access_modbus_context(&self) -> Result<Option<Arc<Mutex<MeterClient>>>> {
         if self.state != State::Verify {
             return Err(anyhow!("State machine is not in VERIFY state"));
         }
     
         if let Some(modbus_context) = &self.modbus_context {
-            println!("Attempting to lock Modbus context");
+            debug!("Attempting to lock Modbus context");
     
             let lock_timeout = Duration::from_secs(5);
     
             match timeout(lock_timeout, modbus_context.lock()).await {
                 Ok(_context) => {
-                    println!("Modbus context locked, validity check passed");
+                    debug!("Modbus context locked, validity check passed");
                     // Return the context
                     Ok(Some(Arc::clone(modbus_context)))
                 }
                 Err(_) => {
-                    println!("Failed to lock Modbus context within timeout duration");
+                    debug!("Failed to lock Modbus context within timeout duration");
                     Err(anyhow!("Timeout while attempting to lock Modbus context"))
                 }
             }
         } else {
-            println!("Modbus context not found");
+            debug!("Modbus context not found");
             Ok(None)
         }
     }
+}
+
+impl Machine for StateMachine {
+    type State = State;
+    type Event = Event;
+
+    const TRANSITIONS: &'static [Transition<Self>] = &[
+        Transition::new(State::Idle, Event::Initialization, State::Ping),
+        Transition::new(State::Ping, Event::PingSuccessful, State::Connect),
+        Transition::new(State::Ping, Event::PingFailed, State::Idle),
+        Transition::new(State::Ping, Event::ReconnectRequested, State::Idle),
+        Transition::new(State::Connect, Event::SocketExists, State::Verify),
+        Transition::new(State::Connect, Event::SocketDead, State::Idle),
+        Transition::new(State::Connect, Event::NoSocket, State::Verify),
+        Transition::new(State::Connect, Event::SocketSetupFail, State::Idle),
+        Transition::new(State::Connect, Event::ReconnectRequested, State::Idle),
+        Transition::new(State::Verify, Event::VerificationPass, State::Verify),
+        Transition::new(State::Verify, Event::VerificationFail, State::Idle),
+        Transition::new(State::Verify, Event::NoSocket, State::Idle),
+        Transition::new(State::Verify, Event::ReconnectRequested, State::Idle),
+    ];
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    fn history(&self) -> &History<State, Event> {
+        &self.history
+    }
 
+    async fn activity(&mut self, state: State) -> Option<Event> {
+        match state {
+            State::Idle => handle_idle(self).await,
+            State::Ping => handle_ping(self).await,
+            State::Connect => handle_connect(self).await,
+            State::Verify => handle_verify(self).await,
+        }
+    }
+
+    fn timer(&self, state: State) -> Option<Timer<Event>> {
+        match state {
+            State::Idle => Some(Timer::new(Duration::from_secs(5), Event::Initialization)),
+            State::Verify => Some(Timer::new(self.keepalive_interval, Event::VerificationPass)),
+            State::Ping | State::Connect => None,
+        }
+    }
+
+    async fn on_entry(&mut self, state: State) {
+        info!("State: {:?}", state);
+        if state == State::Idle && self.modbus_context.take().is_some() {
+            info!("Modbus context dropped");
+            self.set_identity(None);
+            self.device = None;
+            self.selected_profile = None;
+        }
+    }
 }
+
+include!(concat!(env!("OUT_DIR"), "/readme_transitions.rs"));
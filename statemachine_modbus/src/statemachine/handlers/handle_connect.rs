@@ -1,100 +1,89 @@
-use std::error::Error;
-use std::fmt;
-use std::net::SocketAddr;
-use tokio::time::{self, Duration};
-use tokio_modbus::prelude::*;
-use tokio_modbus::client::{tcp, Context};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::statemachine::{StateMachine, State};
-use config_meter_generic::config::validate_ip_and_port;
+use crate::statemachine::{StateMachine, Event};
+use common_meter_generic::{address, Identity};
 use log::{info, warn, error};
 
-/// Custom error type for Modbus operations.
-#[derive(Debug)]
-#[allow(dead_code)]
-enum SocketError {
-    Timeout,
-    ConnectionFailed(String),
-    Other(Box<dyn Error>), // Consider whether this variant is necessary or should be removed
-}
-
-impl fmt::Display for SocketError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            SocketError::Timeout => write!(f, "Connection attempt timed out"),
-            SocketError::ConnectionFailed(msg) => write!(f, "Failed to connect: {}", msg),
-            SocketError::Other(e) => write!(f, "An error occurred: {}", e),
-        }
-    }
-}
-
-impl Error for SocketError {}
-
-/// Checks if the Modbus context is still active by attempting to read a known register.
-async fn is_context_alive(context: &mut Context) -> bool {
-    const STATUS_REGISTER_ADDRESS: u16 = 0x0001;
-    context.read_holding_registers(STATUS_REGISTER_ADDRESS, 1).await.is_ok()
-}
-
-/// Attempts to establish a new Modbus context to the specified IP and port, with a timeout of 5 seconds.
-async fn setup_modbus_context(ip: &str, port: u16) -> Result<Arc<Mutex<Context>>, Box<dyn std::error::Error>> {
-    let address = format!("{}:{}", ip, port).parse::<SocketAddr>().map_err(|e| SocketError::ConnectionFailed(e.to_string()))?;
-    info!("Attempting to connect to the Modbus device at {}", address);
-
-    match time::timeout(Duration::from_secs(5), tcp::connect(address)).await {
-        Ok(Ok(context)) => {
-            info!("Modbus TCP connection established.");
-            Ok(Arc::new(Mutex::new(context)))
-        },
-        Ok(Err(e)) => {
-            error!("Failed to connect to the Modbus device: {}", e);
-            Err(Box::new(SocketError::ConnectionFailed(e.to_string())))
-        },
-        Err(_) => {
-            error!("Connection attempt to Modbus device timed out after 5 seconds.");
-            Err(Box::new(SocketError::Timeout))
-        }
-    }
-}
-
 /// Handles the Modbus connection logic based on the current state of the state machine.
-pub async fn handle_connect(state_machine: &mut StateMachine) {
+pub async fn handle_connect(state_machine: &mut StateMachine) -> Option<Event> {
     info!("State: Modbus Connection Handling");
 
-    if let Some(ref modbus_context) = state_machine.modbus_context {
-        let mut context = modbus_context.lock().await;
-        if is_context_alive(&mut context).await {
-            info!("Modbus context is active, transitioning to Verify state.");
-            state_machine.state = State::Verify;
-            return;
-        } else {
-            warn!("Modbus context is not active, returning to idle.");
-            state_machine.state = State::Idle;
-            return;
-        }
+    let (settings, profile) = state_machine.load_settings().await;
+
+    if let Some(modbus_context) = state_machine.modbus_context.clone() {
+        let result = {
+            let mut client = modbus_context.lock().await;
+            client.configure(&settings);
+            client.probe(&profile.keepalive).await
+        };
+        return match result {
+            Ok(()) => {
+                info!("Modbus context is active, transitioning to Verify state.");
+                Some(Event::SocketExists)
+            }
+            Err(e) if profile.keepalive.keeps_connection(&e, &settings.exception_policy) => {
+                info!("Modbus context answered ({}), transitioning to Verify state.", e);
+                Some(Event::SocketExists)
+            }
+            Err(e) => {
+                warn!("Modbus context is not active ({}), returning to idle.", e);
+                state_machine.path_failed(e.to_string());
+                Some(Event::SocketDead)
+            }
+        };
     }
 
     info!("No Modbus context found, attempting to establish connection.");
     let locked_config = state_machine.config.lock().await;
     let config = &*locked_config;
 
-    if let Err(e) = validate_ip_and_port(&config.meter_data.ip, config.meter_data.port) {
-        error!("Invalid IP or port: {}", e);
-        state_machine.state = State::Idle;
-        return;
+    let ip = state_machine.meter_ip(config);
+    if let Err(e) = address::endpoints(&ip, config.meter_data.port, &settings) {
+        error!("Invalid meter address: {}", e);
+        drop(locked_config);
+        state_machine.path_failed(e);
+        return Some(Event::SocketSetupFail);
     }
 
-    match setup_modbus_context(&config.meter_data.ip, config.meter_data.port).await {
-        Ok(context) => {
+    let transport = state_machine.path().transport.clone();
+    match transport.connect(&ip, config.meter_data.port, &settings, &profile.keepalive).await {
+        Ok(mut client) => {
             info!("Modbus connection established.");
-            state_machine.modbus_context = Some(context);
-            state_machine.state = State::Verify;
+            let meter_type = config.meter_data.meter_type.clone();
+            drop(locked_config);
+            if settings.device_identification {
+                match client.read_device_identification().await {
+                    Ok(device) => {
+                        info!("Device identification: {}", device);
+                        if let Err(e) = state_machine.select_profile(&meter_type, device).await {
+                            error!("Refusing the connection: {}", e);
+                            state_machine.path_failed(e.to_string());
+                            return Some(Event::SocketSetupFail);
+                        }
+                    }
+                    Err(e) => warn!("Device identification failed ({}), keeping meter type {}", e, meter_type),
+                }
+            }
+
+            // The identity registers may come from a profile selected just now
+            let (_, profile) = state_machine.load_settings().await;
+            let mut identity = Identity::read(&mut client, &profile.identity).await;
+            if let Some(device) = state_machine.device() {
+                identity.insert_device(device);
+            }
+            if !identity.is_empty() {
+                info!("Meter identity: {}", identity);
+                state_machine.set_identity(Some(identity));
+            }
+            state_machine.modbus_context = Some(Arc::new(Mutex::new(client)));
+            Some(Event::NoSocket)
         },
         Err(e) => {
             error!("Failed to establish Modbus connection: {}", e);
-            state_machine.state = State::Idle;
+            drop(locked_config);
+            state_machine.path_failed(e.to_string());
+            Some(Event::SocketSetupFail)
         }
     }
 }
@@ -1,11 +1,8 @@
-use tokio::time::sleep;
-use std::time::Duration;
-use crate::statemachine::{StateMachine, State};
+use crate::statemachine::{StateMachine, Event};
 use log::info;
 
-pub async fn handle_idle(state_machine: &mut StateMachine) {
+/// Nothing to do while idle; the state's timer moves on to PING.
+pub async fn handle_idle(_state_machine: &mut StateMachine) -> Option<Event> {
     info!("State: IDLE");
-    sleep(Duration::from_secs(5)).await;
-    state_machine.state = State::Ping;
-    info!("Transitioning to State: PING");
+    None
 }
@@ -1,58 +1,29 @@
-use crate::statemachine::{StateMachine, State};
-use std::error::Error;
-use log::{info, warn, error};
+use crate::statemachine::{StateMachine, Event};
+use common_meter_generic::address;
+use log::{info, warn};
 
-pub async fn handle_ping(state_machine: &mut StateMachine) {
+pub async fn handle_ping(state_machine: &mut StateMachine) -> Option<Event> {
     info!("State: PING");
 
-    // Access the shared configuration safely
+    // Any of the meter's addresses on the active path answering will do; the connect tries
+    // them in the same order
+    let (settings, _) = state_machine.load_settings().await;
     let config = state_machine.config.lock().await;
-    let ip_address = &config.meter_data.ip;  // Get the IP address from the configuration
+    let ip = state_machine.meter_ip(&config);
+    let hosts: Vec<String> = match address::endpoints(&ip, config.meter_data.port, &settings) {
+        Ok(endpoints) => endpoints.into_iter().map(|endpoint| endpoint.host).collect(),
+        Err(_) => vec![ip],
+    };
+    drop(config);
 
-    let ping_result = ping_meter(ip_address).await;
-    match ping_result {
-        Ok(_) => {
-            info!("Ping successful, transitioning to State: CONNECT");
-            state_machine.state = State::Connect;
-        }
-        Err(_) => {
-            warn!("Ping failed, transitioning to State: IDLE");
-            state_machine.state = State::Idle;
-        }
-    }
-}
-
-async fn ping_meter(ip_address: &str) -> Result<(), Box<dyn Error>> {
-    use tokio::process::Command;
-    // Start of the ping operation
-    info!("Attempting to ping IP address: {}", ip_address);
-
-    // Execute the ping command using tokio::process::Command
-    let output = Command::new("ping")
-        .arg("-c")
-        .arg("1")
-        .arg(ip_address)  // Dynamically use the IP address passed to the function
-        .output()
-        .await;
-
-    match output {
-        Ok(output) => {
-            // Command executed successfully, check if the ping was successful
-            if output.status.success() {
-                // Ping command succeeded
-                info!("Ping to {} was successful.", ip_address);
-                Ok(())
-            } else {
-                // Ping command failed
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                error!("Ping to {} failed, error: {}", ip_address, stderr);
-                Err("Ping failed".into())
-            }
-        },
-        Err(e) => {
-            // Command failed to execute
-            error!("Failed to execute ping command to {}: {}", ip_address, e);
-            Err(e.into())
+    let reachability = state_machine.path().reachability.clone();
+    for host in &hosts {
+        if reachability.is_reachable(host).await {
+            info!("Ping to {} successful, transitioning to State: CONNECT", host);
+            return Some(Event::PingSuccessful);
         }
     }
+    warn!("Ping failed, transitioning to State: IDLE");
+    state_machine.path_failed(format!("{} did not answer the ping", hosts.join(", ")));
+    Some(Event::PingFailed)
 }
@@ -1,49 +1,62 @@
-use tokio::time::{sleep, Duration, timeout};
-use crate::statemachine::{StateMachine, State};
-use tokio_modbus::prelude::Reader;
+use tokio::time::{Duration, timeout};
+use crate::statemachine::{StateMachine, Event};
 use log::{info, warn, error};
 
-pub async fn handle_verify(state_machine: &mut StateMachine) {
+/// Probes the keepalive register. Returns nothing while the connection is fine, so the state's
+/// timer rechecks it after the keepalive interval.
+pub async fn handle_verify(state_machine: &mut StateMachine) -> Option<Event> {
     info!("Entering VERIFY state");
 
-    if let Some(modbus_context) = &state_machine.modbus_context {
-        info!("Modbus context found, attempting to lock");
+    let Some(modbus_context) = state_machine.modbus_context.clone() else {
+        warn!("Modbus context not found, switching state to IDLE");
+        return Some(Event::NoSocket);
+    };
+    info!("Modbus context found, attempting to lock");
 
-        // Setting a timeout duration for locking the context
-        let lock_timeout = Duration::from_secs(5);
+    // Setting a timeout duration for locking the context
+    let lock_timeout = Duration::from_secs(5);
+    let (settings, profile) = state_machine.load_settings().await;
+    state_machine.keepalive_interval = profile.keepalive.interval();
 
-        match timeout(lock_timeout, modbus_context.lock()).await {
-            Ok(mut context) => {
-                info!("Modbus context locked, attempting to read holding registers");
+    let locked = timeout(lock_timeout, modbus_context.lock()).await;
+    match locked {
+        Ok(mut client) => {
+            info!("Modbus context locked, probing keepalive register {}", profile.keepalive.address);
+            client.configure(&settings);
+            let result = client.probe(&profile.keepalive).await;
+            drop(client); // Release the lock
 
-                match context.read_holding_registers(0, 1).await {
-                    Ok(_) => {
-                        // Connection is active
-                        info!("Modbus connection is active");
-                        drop(context); // Release the lock
-                        info!("Context lock released, sleeping for 10 seconds");
-                        sleep(Duration::from_secs(10)).await;
-                        state_machine.state = State::Verify; // Remain in Verify state
-                        info!("Rechecking the connection, remaining in VERIFY state");
-                    }
-                    Err(_) => {
-                        // Connection is not active
-                        warn!("Failed to read holding registers, modbus connection is not active");
-                        state_machine.state = State::Idle;
-                        info!("Switching state to IDLE");
-                    }
+            match result {
+                Ok(_) => {
+                    // Connection is active
+                    info!("Modbus connection is active");
+                }
+                Err(e) if profile.keepalive.keeps_connection(&e, &settings.exception_policy) => {
+                    // The meter answered, so the link itself is up
+                    warn!("Verification read failed ({}), keeping the connection", e);
+                }
+                Err(e) => {
+                    // Connection is not active
+                    warn!("Keepalive probe failed, modbus connection is not active: {}", e);
+                    state_machine.path_failed(e.to_string());
+                    return Some(Event::VerificationFail);
                 }
             }
-            Err(_) => {
-                // Locking the context timed out
-                error!("Failed to lock modbus context within timeout duration, switching state to IDLE");
-                state_machine.state = State::Idle;
+
+            state_machine.watch_primary().await;
+            if state_machine.fail_back_due(settings.failback()) {
+                state_machine.fail_back();
+                return Some(Event::ReconnectRequested);
             }
+
+            info!("Context lock released, rechecking in {:?}", profile.keepalive.interval());
+            None
+        }
+        Err(_) => {
+            // Locking the context timed out
+            error!("Failed to lock modbus context within timeout duration, switching state to IDLE");
+            state_machine.history.note("timed out locking the Modbus context");
+            Some(Event::VerificationFail)
         }
-    } else {
-        warn!("Modbus context not found, switching state to IDLE");
-        state_machine.state = State::Idle;
     }
-
-    info!("Exiting VERIFY state");
 }
@@ -0,0 +1,86 @@
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration, Instant};
+use common_meter_generic::settings::{KeepaliveProbe, ModbusSettings};
+use crate::transport::Path;
+use log::{info, debug};
+
+/// Checks the preferred path in the background while the meter is reached on a fallback, the
+/// way Ping and Verify would: one of its hosts answers the ping, and a connection on it answers
+/// the keepalive probe. The task stops when this is dropped.
+pub(crate) struct PrimaryProbe {
+    task: JoinHandle<()>,
+    healthy_since: watch::Receiver<Option<Instant>>,
+}
+
+/// Where and how to check the preferred path.
+pub(crate) struct Target {
+    pub hosts: Vec<String>,
+    pub ip: String,
+    pub port: u16,
+    pub settings: ModbusSettings,
+    pub keepalive: KeepaliveProbe,
+}
+
+impl PrimaryProbe {
+    /// Starts checking `path` every `interval`, the first time right away.
+    pub fn start(path: Path, target: Target, interval: Duration) -> Self {
+        let (sender, healthy_since) = watch::channel(None);
+        let task = tokio::spawn(async move {
+            let mut ticks = time::interval(interval.max(Duration::from_secs(1)));
+            loop {
+                ticks.tick().await;
+                let healthy = match check(&path, &target).await {
+                    Ok(()) => true,
+                    Err(reason) => {
+                        debug!("Path {} still down: {}", path.name(), reason);
+                        false
+                    }
+                };
+                sender.send_if_modified(|since| match (healthy, *since) {
+                    (true, None) => {
+                        info!("Path {} answers again", path.name());
+                        *since = Some(Instant::now());
+                        true
+                    }
+                    (false, Some(_)) => {
+                        *since = None;
+                        true
+                    }
+                    _ => false,
+                });
+            }
+        });
+        PrimaryProbe { task, healthy_since }
+    }
+
+    /// Since when every check of the preferred path has passed, `None` while it is down.
+    pub fn healthy_since(&self) -> Option<Instant> {
+        *self.healthy_since.borrow()
+    }
+}
+
+impl Drop for PrimaryProbe {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn check(path: &Path, target: &Target) -> Result<(), String> {
+    let mut reachable = false;
+    for host in &target.hosts {
+        if path.reachability.is_reachable(host).await {
+            reachable = true;
+            break;
+        }
+    }
+    if !reachable {
+        return Err(format!("{} did not answer the ping", target.hosts.join(", ")));
+    }
+    let mut client = path.transport.connect(&target.ip, target.port, &target.settings, &target.keepalive).await.map_err(|e| e.to_string())?;
+    match client.probe(&target.keepalive).await {
+        Ok(()) => Ok(()),
+        Err(e) if target.keepalive.keeps_connection(&e, &target.settings.exception_policy) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
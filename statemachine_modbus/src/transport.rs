@@ -0,0 +1,286 @@
+// statemachine_modbus/src/transport.rs
+
+#[cfg(any(test, feature = "test-util"))]
+use std::collections::VecDeque;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio_modbus::client::Context;
+use socket2::{SockRef, TcpKeepalive};
+use common_meter_generic::{address, client, Bus, MeterClient, ModbusError};
+use common_meter_generic::settings::{KeepaliveProbe, ModbusSettings, PathSettings};
+use log::{info, error};
+
+/// A boxed future, so the traits below can be used as trait objects.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Opens Modbus connections for the state machine.
+pub trait Transport: Send + Sync {
+    fn connect<'a>(
+        &'a self,
+        ip: &'a str,
+        port: u16,
+        settings: &'a ModbusSettings,
+        keepalive: &'a KeepaliveProbe,
+    ) -> BoxFuture<'a, Result<MeterClient, ModbusError>>;
+}
+
+/// Tells whether the meter's host answers at all, before a connection is attempted.
+pub trait Reachability: Send + Sync {
+    fn is_reachable<'a>(&'a self, ip: &'a str) -> BoxFuture<'a, bool>;
+}
+
+/// One way to reach the meter: how to connect on it and how to tell whether it is up.
+#[derive(Clone)]
+pub struct Path {
+    pub settings: PathSettings,
+    pub transport: Arc<dyn Transport>,
+    pub reachability: Arc<dyn Reachability>,
+}
+
+impl Path {
+    pub fn new(settings: PathSettings, transport: Arc<dyn Transport>, reachability: Arc<dyn Reachability>) -> Self {
+        Path { settings, transport, reachability }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.settings.name
+    }
+}
+
+/// Modbus TCP to the first of the meter's addresses that answers, with the connect timeout from
+/// the settings and optional TCP keepalive.
+#[derive(Debug, Default)]
+pub struct TcpTransport;
+
+impl TcpTransport {
+    /// Enables TCP keepalive on the socket when `keepalive` is set.
+    fn enable_keepalive(stream: &TcpStream, keepalive: Option<Duration>) -> std::io::Result<()> {
+        if let Some(idle) = keepalive {
+            let params = TcpKeepalive::new().with_time(idle).with_interval(idle);
+            SockRef::from(stream).set_tcp_keepalive(&params)?;
+            info!("TCP keepalive enabled with {:?} idle time", idle);
+        }
+        Ok(())
+    }
+}
+
+impl TcpTransport {
+    async fn open(ip: &str, port: u16, settings: &ModbusSettings, keepalive: Option<Duration>) -> Result<Context, ModbusError> {
+        let endpoints = address::endpoints(ip, port, settings).map_err(ModbusError::Config)?;
+        let (stream, endpoint) = match address::connect(&endpoints, settings).await {
+            Ok(connected) => connected,
+            Err(e) => {
+                error!("Failed to connect to the Modbus device: {}", e);
+                return Err(e);
+            }
+        };
+        Self::enable_keepalive(&stream, keepalive).map_err(|e| ModbusError::ConnectionFailed(e.to_string()))?;
+
+        let connect_timeout = settings.transaction.connect_timeout();
+        match time::timeout(connect_timeout, client::attach(stream, &endpoint.host, settings)).await {
+            Ok(Ok(context)) => {
+                info!("Modbus TCP connection to {} established ({:?} framing).", endpoint, settings.framing);
+                Ok(context)
+            },
+            Ok(Err(e)) => {
+                error!("Failed to connect to the Modbus device: {}", e);
+                Err(e)
+            },
+            Err(_) => {
+                error!("Connection setup with {} timed out after {:?}.", endpoint, connect_timeout);
+                Err(ModbusError::Timeout)
+            }
+        }
+    }
+}
+
+impl Transport for TcpTransport {
+    fn connect<'a>(
+        &'a self,
+        ip: &'a str,
+        port: u16,
+        settings: &'a ModbusSettings,
+        keepalive: &'a KeepaliveProbe,
+    ) -> BoxFuture<'a, Result<MeterClient, ModbusError>> {
+        Box::pin(async move {
+            let context = Self::open(ip, port, settings, keepalive.tcp_keepalive()).await?;
+            // Retries after a timeout go over a new connection
+            let (ip, reopen, keepalive) = (ip.to_string(), settings.clone(), keepalive.tcp_keepalive());
+            Ok(MeterClient::new(context, settings).with_reconnect(Box::new(move || {
+                let (ip, settings) = (ip.clone(), reopen.clone());
+                Box::pin(async move { Self::open(&ip, port, &settings, keepalive).await })
+            })))
+        })
+    }
+}
+
+/// Queues the meter's requests on a shared RS-485 bus, addressed to `modbus.unit_id` with the
+/// priority of `modbus.scan_group`. The bus owns the line, so there is nothing to dial.
+#[derive(Debug, Clone)]
+pub struct BusTransport {
+    bus: Bus,
+}
+
+impl BusTransport {
+    pub fn new(bus: Bus) -> Self {
+        BusTransport { bus }
+    }
+}
+
+impl Transport for BusTransport {
+    fn connect<'a>(
+        &'a self,
+        _ip: &'a str,
+        _port: u16,
+        settings: &'a ModbusSettings,
+        _keepalive: &'a KeepaliveProbe,
+    ) -> BoxFuture<'a, Result<MeterClient, ModbusError>> {
+        Box::pin(async move {
+            if self.bus.is_closed() {
+                return Err(ModbusError::ConnectionFailed("RS-485 bus closed".to_string()));
+            }
+            info!("Queuing requests for slave {} on the RS-485 bus (scan group {})", settings.unit_id(), settings.scan_group);
+            Ok(self.bus.client(settings))
+        })
+    }
+}
+
+/// The bus is there as long as its scheduler runs; whether a slave answers is up to the
+/// scheduler to find out.
+impl Reachability for BusTransport {
+    fn is_reachable<'a>(&'a self, _ip: &'a str) -> BoxFuture<'a, bool> {
+        let reachable = !self.bus.is_closed();
+        Box::pin(async move { reachable })
+    }
+}
+
+/// Runs the system `ping` once.
+#[derive(Debug, Default)]
+pub struct PingReachability;
+
+impl PingReachability {
+    async fn ping_meter(ip_address: &str) -> Result<(), Box<dyn Error>> {
+        use tokio::process::Command;
+        // Start of the ping operation
+        info!("Attempting to ping IP address: {}", ip_address);
+
+        // Execute the ping command using tokio::process::Command
+        let output = Command::new("ping")
+            .arg("-c")
+            .arg("1")
+            .arg(ip_address)  // Dynamically use the IP address passed to the function
+            .output()
+            .await;
+
+        match output {
+            Ok(output) => {
+                // Command executed successfully, check if the ping was successful
+                if output.status.success() {
+                    // Ping command succeeded
+                    info!("Ping to {} was successful.", ip_address);
+                    Ok(())
+                } else {
+                    // Ping command failed
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    error!("Ping to {} failed, error: {}", ip_address, stderr);
+                    Err("Ping failed".into())
+                }
+            },
+            Err(e) => {
+                // Command failed to execute
+                error!("Failed to execute ping command to {}: {}", ip_address, e);
+                Err(e.into())
+            }
+        }
+    }
+}
+
+impl Reachability for PingReachability {
+    fn is_reachable<'a>(&'a self, ip: &'a str) -> BoxFuture<'a, bool> {
+        Box::pin(async move { Self::ping_meter(ip).await.is_ok() })
+    }
+}
+
+/// Transport handing out prepared contexts in order, for tests.
+///
+/// Each queued entry answers one connection attempt; with the queue empty, attempts fail.
+/// Built with the `test-util` feature, which only the dev-dependencies enable.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    outcomes: StdMutex<VecDeque<Result<Context, ModbusError>>>,
+    attempts: AtomicUsize,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues the result of the next connection attempt.
+    pub fn push(&self, outcome: Result<Context, ModbusError>) {
+        self.outcomes.lock().unwrap().push_back(outcome);
+    }
+
+    /// Number of connection attempts so far.
+    pub fn attempts(&self) -> usize {
+        self.attempts.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Transport for MockTransport {
+    fn connect<'a>(
+        &'a self,
+        _ip: &'a str,
+        _port: u16,
+        settings: &'a ModbusSettings,
+        _keepalive: &'a KeepaliveProbe,
+    ) -> BoxFuture<'a, Result<MeterClient, ModbusError>> {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        let outcome = self.outcomes.lock().unwrap().pop_front();
+        Box::pin(async move {
+            match outcome {
+                Some(Ok(context)) => Ok(MeterClient::new(context, settings)),
+                Some(Err(e)) => Err(e),
+                None => Err(ModbusError::ConnectionFailed("no connection queued".to_string())),
+            }
+        })
+    }
+}
+
+/// Reachability fixed by the test.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Default)]
+pub struct MockReachability {
+    reachable: AtomicBool,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockReachability {
+    pub fn new(reachable: bool) -> Self {
+        MockReachability { reachable: AtomicBool::new(reachable) }
+    }
+
+    pub fn set_reachable(&self, reachable: bool) {
+        self.reachable.store(reachable, Ordering::SeqCst);
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Reachability for MockReachability {
+    fn is_reachable<'a>(&'a self, _ip: &'a str) -> BoxFuture<'a, bool> {
+        let reachable = self.reachable.load(Ordering::SeqCst);
+        Box::pin(async move { reachable })
+    }
+}
@@ -0,0 +1,135 @@
+// statemachine_modbus/tests/identity.rs
+
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_modbus::client::tcp;
+use config_meter_generic::config::Config;
+use common_meter_generic::Settings;
+use simulator_meter_generic::{SimulatedDevice, SimulatedRegister, Simulator, SimulatorProfile, Waveform};
+use statemachine_modbus::statemachine::{Event, State, StateMachine};
+use statemachine_modbus::transport::{MockReachability, MockTransport};
+
+const CONFIG: &str = r#"
+meter_data:
+  ip: "10.15.1.2"
+  port: 502
+  meter_type: "METER_TYPE"
+write_registers: []
+read_registers: []
+debug:
+  mgw_generic: "off"
+  statemachine_modbus: "off"
+  statemachine_read: "off"
+"#;
+
+const SETTINGS: &str = r#"
+modbus:
+  device_identification: true
+profiles:
+  Mock:
+    keepalive:
+      address: 40100
+      count: 2
+    identity:
+      serial_number: { address: 40100, length: 4 }
+      model: { address: 40110, length: 2 }
+    device: { vendor: Phoenix Contact, product_code: EEM-MA370 }
+  Other:
+    device: { vendor: Acme, product_code: M1 }
+"#;
+
+/// Serves `text` as ASCII from `address` on, four characters per simulated register.
+fn text(address: u16, text: &str) -> Vec<SimulatedRegister> {
+    text.as_bytes()
+        .chunks(4)
+        .zip((address..).step_by(2))
+        .map(|(chunk, address)| {
+            let mut bytes = [0; 4];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            SimulatedRegister {
+                name: format!("text_{}", address),
+                address,
+                waveform: Waveform::Constant { value: f32::from_bits(u32::from_be_bytes(bytes)) },
+            }
+        })
+        .collect()
+}
+
+/// Connects a state machine for `meter_type` to a simulator identifying as `device`.
+async fn connect(meter_type: &str, device: SimulatedDevice) -> Arc<Mutex<StateMachine>> {
+    let config: Config = serde_yaml::from_str(&CONFIG.replace("METER_TYPE", meter_type)).unwrap();
+    let settings: Settings = serde_yaml::from_str(SETTINGS).unwrap();
+
+    let (client, server) = tokio::io::duplex(1024);
+    let mut registers = text(40100, "SN123456");
+    registers.extend(text(40110, "M4"));
+    let simulator = Simulator::new(SimulatorProfile { registers, device: Some(device), ..Default::default() });
+    tokio::spawn(async move { simulator.serve_connection(server).await });
+
+    let transport = Arc::new(MockTransport::new());
+    transport.push(Ok(tcp::attach(client)));
+    let state_machine = StateMachine::with_transport(
+        Arc::new(Mutex::new(config)),
+        Arc::new(Mutex::new(settings)),
+        transport,
+        Arc::new(MockReachability::new(true)),
+    );
+    state_machine.lock().await.state = State::Connect;
+    state_machine
+}
+
+fn device(vendor: &str, product_code: &str) -> SimulatedDevice {
+    SimulatedDevice { vendor: vendor.to_string(), product_code: product_code.to_string(), revision: "1.02".to_string() }
+}
+
+#[tokio::test(start_paused = true)]
+async fn identity_is_read_after_connecting_and_cleared_with_the_context() {
+    let state_machine = connect("Mock", device("PHOENIX CONTACT", "EEM-MA370")).await;
+    let mut state_machine = state_machine.lock().await;
+    let mut identity = state_machine.subscribe_identity();
+
+    state_machine.step().await;
+    assert_eq!(state_machine.state, State::Verify);
+    assert_eq!(state_machine.device().map(|device| device.revision.as_str()), Some("1.02"));
+    assert_eq!(state_machine.selected_profile(), None);
+    let read = state_machine.identity().expect("identity after connecting");
+    assert_eq!(read.get("serial_number"), Some("SN123456"));
+    assert_eq!(read.get("model"), Some("M4"));
+    assert_eq!(read.get("vendor"), Some("PHOENIX CONTACT"));
+    assert!(identity.has_changed().unwrap());
+    assert_eq!(*identity.borrow_and_update(), Some(read));
+
+    state_machine.request_reconnect("test").await;
+    assert_eq!(state_machine.state, State::Idle);
+    assert_eq!(state_machine.identity(), None);
+    assert_eq!(state_machine.device(), None);
+    assert!(identity.has_changed().unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_device_of_another_type_is_refused() {
+    let state_machine = connect("Mock", device("Acme", "M1")).await;
+    let mut state_machine = state_machine.lock().await;
+
+    state_machine.step().await;
+    assert_eq!(state_machine.state, State::Idle);
+    assert!(state_machine.modbus_context.is_none());
+    let refusal = state_machine.history.last().unwrap();
+    assert_eq!(refusal.event, Event::SocketSetupFail);
+    assert_eq!(
+        refusal.reason.as_deref(),
+        Some("Connected device Acme M1 (revision 1.02) is not a Mock; it matches profile Other")
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_meter_type_without_profile_takes_the_matching_one() {
+    let state_machine = connect("Unlisted", device("Phoenix Contact", "EEM-MA370")).await;
+    let mut state_machine = state_machine.lock().await;
+
+    state_machine.step().await;
+    assert_eq!(state_machine.state, State::Verify);
+    assert_eq!(state_machine.selected_profile(), Some("Mock"));
+    // The identity registers come from the selected profile
+    assert_eq!(state_machine.identity().unwrap().get("serial_number"), Some("SN123456"));
+}
@@ -0,0 +1,199 @@
+// statemachine_modbus/tests/paths.rs
+//
+// A meter reached on two paths, on tokio's paused clock: fail over when the keepalive fails on
+// the preferred path, fail back once the preferred path has passed its checks for failback_secs.
+
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tokio_modbus::client::{tcp, Context};
+use config_meter_generic::config::{Config, ConfigRegister};
+use common_meter_generic::Settings;
+use simulator_meter_generic::{Faults, Simulator, SimulatorProfile};
+use statemachine_modbus::statemachine::{Event, State, StateMachine};
+use statemachine_modbus::transport::{MockReachability, MockTransport, Path};
+
+const CONFIG: &str = r#"
+meter_data:
+  ip: "10.15.1.2"
+  port: 502
+  meter_type: "Mock"
+write_registers: []
+read_registers:
+  - name: voltage_L1_N
+    address: 32774
+debug:
+  mgw_generic: "off"
+  statemachine_modbus: "off"
+  statemachine_read: "off"
+"#;
+
+const SETTINGS: &str = r#"
+modbus:
+  failback_secs: 60
+  paths:
+    - name: ethernet
+    - name: serial
+      addresses: ["10.15.9.1:1502"]
+      framing: rtu_over_tcp
+profiles:
+  Mock:
+    keepalive:
+      address: 32774
+      count: 2
+      interval_secs: 10
+"#;
+
+/// A context to a fresh simulator, which is returned for injecting faults.
+fn link() -> (Context, Simulator) {
+    let (client, server) = tokio::io::duplex(1024);
+    let simulator = Simulator::new(SimulatorProfile::from_registers(&[ConfigRegister {
+        name: "voltage_L1_N".to_string(),
+        address: 32774,
+    }]));
+    let serving = simulator.clone();
+    tokio::spawn(async move { serving.serve_connection(server).await });
+    (tcp::attach(client), simulator)
+}
+
+/// Steps until the state machine is in `state`, giving up after `limit` steps.
+async fn step_until(state_machine: &Arc<Mutex<StateMachine>>, state: State, limit: usize) {
+    for _ in 0..limit {
+        let mut state_machine = state_machine.lock().await;
+        state_machine.step().await;
+        if state_machine.state == state {
+            return;
+        }
+    }
+    panic!("not in {:?} after {} steps", state, limit);
+}
+
+#[tokio::test(start_paused = true)]
+async fn fails_over_on_keepalive_failure_and_fails_back_when_stable() {
+    let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+    let settings: Settings = serde_yaml::from_str(SETTINGS).unwrap();
+    let ethernet = Arc::new(MockTransport::new());
+    let serial = Arc::new(MockTransport::new());
+    let (context, preferred) = link();
+    ethernet.push(Ok(context));
+    serial.push(Ok(link().0));
+
+    let reachability = Arc::new(MockReachability::new(true));
+    let paths = vec![
+        Path::new(settings.modbus.paths[0].clone(), ethernet.clone(), reachability.clone()),
+        Path::new(settings.modbus.paths[1].clone(), serial.clone(), reachability),
+    ];
+    let state_machine = StateMachine::with_paths(Arc::new(Mutex::new(config)), Arc::new(Mutex::new(settings)), paths);
+    state_machine.lock().await.state = State::Connect;
+    let mut active = state_machine.lock().await.subscribe_active_path();
+
+    step_until(&state_machine, State::Verify, 1).await;
+    assert_eq!(active.borrow_and_update().as_deref(), Some("ethernet"));
+
+    // The keepalive fails on Ethernet, so the next connection goes over the serial path
+    preferred.set_faults(Faults { drop_every: Some(1), ..Default::default() }).await;
+    step_until(&state_machine, State::Idle, 1).await;
+    assert!(active.has_changed().unwrap());
+    assert_eq!(active.borrow_and_update().as_deref(), Some("serial"));
+    let record = state_machine.lock().await.history.last().unwrap();
+    assert_eq!(record.event, Event::VerificationFail);
+    assert!(record.reason.unwrap().contains("failing over from path ethernet to serial"));
+
+    step_until(&state_machine, State::Verify, 3).await;
+    let connected = Instant::now();
+    assert_eq!((ethernet.attempts(), serial.attempts()), (1, 1));
+
+    // Ethernet is checked in the background every keepalive interval, and keepalives pass on
+    // the serial path until Ethernet has passed its checks for failback_secs
+    for _ in 0..10 {
+        ethernet.push(Ok(link().0));
+    }
+    step_until(&state_machine, State::Idle, 10).await;
+    assert!(connected.elapsed() >= Duration::from_secs(60));
+    assert_eq!(state_machine.lock().await.history.last().unwrap().event, Event::ReconnectRequested);
+    assert_eq!(state_machine.lock().await.active_path().as_deref(), Some("ethernet"));
+    let probes = ethernet.attempts() - 1;
+    assert!(probes >= 7, "{} checks of the Ethernet path", probes);
+
+    step_until(&state_machine, State::Verify, 3).await;
+    assert_eq!((ethernet.attempts(), serial.attempts()), (probes + 2, 1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn stays_on_the_fallback_while_the_preferred_path_is_down() {
+    let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+    let settings: Settings = serde_yaml::from_str(SETTINGS).unwrap();
+    let ethernet = Arc::new(MockTransport::new());
+    let serial = Arc::new(MockTransport::new());
+    serial.push(Ok(link().0));
+
+    let unreachable = Arc::new(MockReachability::new(false));
+    let paths = vec![
+        Path::new(settings.modbus.paths[0].clone(), ethernet.clone(), unreachable.clone()),
+        Path::new(settings.modbus.paths[1].clone(), serial.clone(), Arc::new(MockReachability::new(true))),
+    ];
+    let state_machine = StateMachine::with_paths(Arc::new(Mutex::new(config)), Arc::new(Mutex::new(settings)), paths);
+    step_until(&state_machine, State::Verify, 6).await;
+    assert_eq!(state_machine.lock().await.active_path().as_deref(), Some("serial"));
+
+    // Well past failback_secs, Ethernet does not answer the ping, so it is neither connected to
+    // nor failed back to
+    for _ in 0..30 {
+        step_until(&state_machine, State::Verify, 1).await;
+    }
+    assert_eq!(state_machine.lock().await.active_path().as_deref(), Some("serial"));
+    assert_eq!((ethernet.attempts(), serial.attempts()), (0, 1));
+
+    // Answering the ping but not the connection does not count either
+    unreachable.set_reachable(true);
+    for _ in 0..10 {
+        step_until(&state_machine, State::Verify, 1).await;
+    }
+    assert_eq!(state_machine.lock().await.active_path().as_deref(), Some("serial"));
+    assert!(ethernet.attempts() >= 9);
+
+    // Once it connects and answers the keepalive, it fails back after failback_secs
+    for _ in 0..10 {
+        ethernet.push(Ok(link().0));
+    }
+    let up = Instant::now();
+    step_until(&state_machine, State::Idle, 10).await;
+    assert!(up.elapsed() >= Duration::from_secs(60));
+    assert_eq!(state_machine.lock().await.history.last().unwrap().event, Event::ReconnectRequested);
+    assert_eq!(state_machine.lock().await.active_path().as_deref(), Some("ethernet"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn falls_back_again_when_the_preferred_path_is_still_down() {
+    let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+    let settings: Settings = serde_yaml::from_str(SETTINGS).unwrap();
+    let ethernet = Arc::new(MockTransport::new());
+    let serial = Arc::new(MockTransport::new());
+    serial.push(Ok(link().0));
+
+    let reachability = Arc::new(MockReachability::new(true));
+    let paths = vec![
+        Path::new(settings.modbus.paths[0].clone(), ethernet.clone(), reachability.clone()),
+        Path::new(settings.modbus.paths[1].clone(), serial.clone(), reachability),
+    ];
+    let state_machine = StateMachine::with_paths(Arc::new(Mutex::new(config)), Arc::new(Mutex::new(settings)), paths);
+    state_machine.lock().await.state = State::Connect;
+
+    // Nothing queued on Ethernet, so the attempt fails and the serial path takes over
+    step_until(&state_machine, State::Idle, 1).await;
+    assert_eq!(state_machine.lock().await.active_path().as_deref(), Some("serial"));
+    step_until(&state_machine, State::Verify, 3).await;
+    assert_eq!((ethernet.attempts(), serial.attempts()), (1, 1));
+}
+
+#[tokio::test]
+async fn a_single_path_is_not_reported() {
+    let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+    let state_machine = StateMachine::with_transport(
+        Arc::new(Mutex::new(config)),
+        Arc::new(Mutex::new(Settings::default())),
+        Arc::new(MockTransport::new()),
+        Arc::new(MockReachability::new(true)),
+    );
+    assert_eq!(state_machine.lock().await.active_path(), None);
+}
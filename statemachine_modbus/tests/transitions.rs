@@ -0,0 +1,154 @@
+// statemachine_modbus/tests/transitions.rs
+//
+// One case per row of the statemachine_modbus table in the README, run on tokio's paused
+// clock with a mock transport, so sleeps take no real time and no network is touched.
+
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tokio_modbus::client::{tcp, Context};
+use config_meter_generic::config::{Config, ConfigRegister};
+use common_meter_generic::{MeterClient, ModbusError, Settings};
+use simulator_meter_generic::{Simulator, SimulatorProfile};
+use statemachine_modbus::statemachine::{State, StateMachine};
+use statemachine_modbus::transport::{MockReachability, MockTransport};
+
+const CONFIG: &str = r#"
+meter_data:
+  ip: "10.15.1.2"
+  port: 502
+  meter_type: "Mock"
+write_registers: []
+read_registers:
+  - name: voltage_L1_N
+    address: 32774
+debug:
+  mgw_generic: "off"
+  statemachine_modbus: "off"
+  statemachine_read: "off"
+"#;
+
+const SETTINGS: &str = r#"
+profiles:
+  Mock:
+    keepalive:
+      address: 32774
+      count: 2
+      interval_secs: 10
+"#;
+
+/// What sits at the other end of a Modbus context.
+#[derive(Debug, Clone, Copy)]
+enum Link {
+    /// The simulator, answering every request
+    Answering,
+    /// A peer that has gone away
+    Broken,
+}
+
+impl Link {
+    fn context(self) -> Context {
+        let (client, server) = tokio::io::duplex(1024);
+        match self {
+            Link::Answering => {
+                let simulator = Simulator::new(SimulatorProfile::from_registers(&[ConfigRegister {
+                    name: "voltage_L1_N".to_string(),
+                    address: 32774,
+                }]));
+                tokio::spawn(async move { simulator.serve_connection(server).await });
+            }
+            Link::Broken => drop(server),
+        }
+        tcp::attach(client)
+    }
+}
+
+struct Case {
+    row: &'static str,
+    from: State,
+    reachable: bool,
+    /// Context present before the step
+    existing: Option<Link>,
+    /// Result of a connection attempt; `None` makes it fail
+    connect: Option<Link>,
+    expected: State,
+    connected: bool,
+    /// Virtual time the step must take at least
+    waits: Duration,
+}
+
+fn cases() -> Vec<Case> {
+    let case = |row, from, expected| Case {
+        row,
+        from,
+        reachable: true,
+        existing: None,
+        connect: None,
+        expected,
+        connected: false,
+        waits: Duration::ZERO,
+    };
+    vec![
+        Case { waits: Duration::from_secs(5), ..case("Idle / Initialization", State::Idle, State::Ping) },
+        case("Ping / Ping Successful", State::Ping, State::Connect),
+        Case { reachable: false, ..case("Ping / Ping Failed", State::Ping, State::Idle) },
+        Case {
+            existing: Some(Link::Answering),
+            connected: true,
+            ..case("Connect / Socket Exists", State::Connect, State::Verify)
+        },
+        Case { existing: Some(Link::Broken), ..case("Connect / Socket Dead", State::Connect, State::Idle) },
+        Case {
+            connect: Some(Link::Answering),
+            connected: true,
+            ..case("Connect / No Socket", State::Connect, State::Verify)
+        },
+        case("Connect / Socket Setup Fail", State::Connect, State::Idle),
+        Case {
+            existing: Some(Link::Answering),
+            connected: true,
+            waits: Duration::from_secs(10),
+            ..case("Verify / Verification Pass", State::Verify, State::Verify)
+        },
+        Case { existing: Some(Link::Broken), ..case("Verify / Verification Fail", State::Verify, State::Idle) },
+        case("Verify / No Socket", State::Verify, State::Idle),
+    ]
+}
+
+#[tokio::test(start_paused = true)]
+async fn transitions_match_the_readme_table() {
+    let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+    let settings: Settings = serde_yaml::from_str(SETTINGS).unwrap();
+    let config = Arc::new(Mutex::new(config));
+
+    for case in cases() {
+        let transport = Arc::new(MockTransport::new());
+        match case.connect {
+            Some(link) => transport.push(Ok(link.context())),
+            None => transport.push(Err(ModbusError::ConnectionFailed("connection refused".to_string()))),
+        }
+        let reachability = Arc::new(MockReachability::new(case.reachable));
+        let state_machine = StateMachine::with_transport(
+            config.clone(),
+            Arc::new(Mutex::new(settings.clone())),
+            transport.clone(),
+            reachability,
+        );
+
+        // Only Connect without an existing context dials out
+        let dials = case.from == State::Connect && case.existing.is_none();
+        let mut state_machine = state_machine.lock().await;
+        state_machine.state = case.from;
+        state_machine.modbus_context = case
+            .existing
+            .map(|link| Arc::new(Mutex::new(MeterClient::new(link.context(), &settings.modbus))));
+
+        let started = Instant::now();
+        state_machine.step().await;
+
+        assert_eq!(state_machine.state, case.expected, "{}", case.row);
+        assert_eq!(state_machine.modbus_context.is_some(), case.connected, "{}: connection", case.row);
+        assert!(started.elapsed() >= case.waits, "{}: took {:?}", case.row, started.elapsed());
+        assert_eq!(transport.attempts(), dials as usize, "{}: connection attempts", case.row);
+    }
+}
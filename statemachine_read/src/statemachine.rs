@@ -1,50 +1,161 @@
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex};
 mod handlers;
 use handlers::{handle_idle, handle_read};
-use config_meter_generic::config::Config;
+use config_meter_generic::config::{Config, ConfigRegister};
+use common_meter_generic::{Endpoint, Reading, Settings, Snapshot};
+use common_meter_generic::settings::{DerivedChannel, MeterProfile};
 use statemachine_modbus::statemachine::StateMachine as StateMachineModbus;
-use log::info;
+use fsm_meter_generic::{History, Machine, Timer, Transition};
+use fsm_meter_generic::history::DEFAULT_CAPACITY;
+use log::{info, error};
 
-#[derive(Debug)]
+/// Snapshots a subscriber may fall behind before it misses some.
+const SNAPSHOT_CAPACITY: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum State {
     Idle,
     Read,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Timer,
+    ReadSuccessful,
+    ReadFail,
+    NoSocket,
+}
+
 pub struct StateMachine {
     pub state: State,
+    /// Transitions taken so far, and subscriptions to the state
+    pub history: History<State, Event>,
     pub meter_data: Option<String>,
-    first_idle: bool,
+    backoff: Option<Duration>,
+    snapshots: broadcast::Sender<Snapshot>,
+    /// Latest reading per register, carried over as stale when a register cannot be read
+    last_readings: HashMap<String, Reading>,
     config: Arc<Mutex<Config>>,
+    settings: Arc<Mutex<Settings>>,
     modbus_statemachine: Arc<Mutex<StateMachineModbus>>,
 }
 
 impl StateMachine {
-    pub fn new(config: Arc<Mutex<Config>>, modbus_statemachine: Arc<Mutex<StateMachineModbus>>) -> Arc<Mutex<Self>> {
+    pub fn new(
+        config: Arc<Mutex<Config>>,
+        settings: Arc<Mutex<Settings>>,
+        modbus_statemachine: Arc<Mutex<StateMachineModbus>>,
+    ) -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(StateMachine {
             state: State::Idle,
+            history: History::new(State::Idle, DEFAULT_CAPACITY),
             meter_data: None,
-            first_idle: true,
+            backoff: None,
+            snapshots: broadcast::channel(SNAPSHOT_CAPACITY).0,
+            last_readings: HashMap::new(),
             config,
+            settings,
             modbus_statemachine,
         }))
     }
 
     pub async fn run(&mut self) {
         loop {
-            info!("Current state: {:?}", self.state);
-            match &self.state {
-                State::Idle => {
-                    info!("Entering Idle state");
-                    handle_idle(self).await;
-                }
-                State::Read => {
-                    info!("Entering Read state");
-                    handle_read(self).await;
-                }
-            }
-            info!("Transitioning to next state: {:?}", self.state);
+            self.step().await;
+        }
+    }
+
+    /// Snapshots of every read cycle from now on.
+    pub fn subscribe_snapshots(&self) -> broadcast::Receiver<Snapshot> {
+        self.snapshots.subscribe()
+    }
+
+    /// Completes the fresh readings of `snapshot` with a stand-in for every register missing
+    /// from them, appends the derived channels and sends the snapshot to the subscribers.
+    pub(crate) fn publish(&mut self, mut snapshot: Snapshot, registers: &[ConfigRegister], profile: &MeterProfile, derived: &[DerivedChannel]) {
+        let meter_id = snapshot.meter_id.as_str();
+        let mut fresh: HashMap<String, Reading> = std::mem::take(&mut snapshot.readings).into_iter().map(|reading| (reading.name.clone(), reading)).collect();
+        let mut readings: Vec<Reading> = registers
+            .iter()
+            .map(|register| {
+                fresh.remove(&register.name).unwrap_or_else(|| {
+                    let info = profile.register(&register.name);
+                    Reading::missing(meter_id, &register.name, register.address, &info, self.last_readings.get(&register.name))
+                })
+            })
+            .collect();
+        // Each channel sees the ones before it
+        for channel in derived {
+            let reading = Reading::derive(meter_id, channel, &readings);
+            info!("{}", reading);
+            readings.push(reading);
         }
+
+        self.last_readings = readings.iter().map(|reading| (reading.name.clone(), reading.clone())).collect();
+        // Nobody listening is fine
+        snapshot.readings = readings;
+        let _ = self.snapshots.send(snapshot);
+    }
+
+    /// Runs the activity of the current state once and takes the resulting transition.
+    pub async fn step(&mut self) {
+        info!("Current state: {:?}", self.state);
+        if let Err(e) = fsm_meter_generic::step(self).await {
+            error!("{}", e);
+        }
+        info!("Transitioning to next state: {:?}", self.state);
     }
 }
+
+/// Identifies the configured meter in readings.
+pub(crate) fn meter_id(config: &Config) -> String {
+    // Brackets keep the port apart from IPv6 addresses
+    match Endpoint::parse(&config.meter_data.ip, config.meter_data.port) {
+        Ok(endpoint) => endpoint.to_string(),
+        Err(_) => format!("{}:{}", config.meter_data.ip, config.meter_data.port),
+    }
+}
+
+impl Machine for StateMachine {
+    type State = State;
+    type Event = Event;
+
+    const TRANSITIONS: &'static [Transition<Self>] = &[
+        Transition::new(State::Idle, Event::Timer, State::Read),
+        Transition::new(State::Read, Event::ReadSuccessful, State::Idle),
+        Transition::new(State::Read, Event::ReadFail, State::Idle),
+        Transition::new(State::Read, Event::NoSocket, State::Idle),
+    ];
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    fn history(&self) -> &History<State, Event> {
+        &self.history
+    }
+
+    async fn activity(&mut self, state: State) -> Option<Event> {
+        match state {
+            State::Idle => handle_idle(self).await,
+            State::Read => handle_read(self).await,
+        }
+    }
+
+    fn timer(&self, state: State) -> Option<Timer<Event>> {
+        match state {
+            // Idle period, extended while the meter asked us to back off
+            State::Idle => Some(Timer::new(Duration::from_secs(5) + self.backoff.unwrap_or_default(), Event::Timer)),
+            State::Read => None,
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/readme_transitions.rs"));
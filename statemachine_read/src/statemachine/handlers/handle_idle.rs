@@ -1,20 +1,11 @@
-use tokio::time::sleep;
-use std::time::Duration;
-use crate::statemachine::{StateMachine, State};
+use crate::statemachine::{StateMachine, Event};
 use log::info;
 
-pub async fn handle_idle(state_machine: &mut StateMachine) {
+/// Nothing to do while idle; the state's timer returns to READ.
+pub async fn handle_idle(state_machine: &mut StateMachine) -> Option<Event> {
     info!("Entering IDLE state");
-
-    // Simulating idle period
-    sleep(Duration::from_secs(5)).await;
-
-    if state_machine.first_idle {
-        info!("First time in IDLE state, transitioning to READ state");
-        state_machine.state = State::Read;
-        state_machine.first_idle = false;
-    } else {
-        info!("Returning to READ state");
-        state_machine.state = State::Read;
+    if let Some(backoff) = state_machine.backoff {
+        info!("Backing off for an extra {:?}", backoff);
     }
+    None
 }
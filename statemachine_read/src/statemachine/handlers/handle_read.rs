@@ -1,85 +1,185 @@
-use crate::statemachine::{StateMachine, State};
-use std::error::Error;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+use crate::statemachine::{meter_id, StateMachine, Event};
 use config_meter_generic::config::ConfigRegister;
-use tokio_modbus::client::Context as ModbusContext;
-use tokio_modbus::prelude::Reader;
-use log::{info, error};
+use common_meter_generic::{MeterClient, ModbusError, Reading, Snapshot};
+use common_meter_generic::settings::{ExceptionPolicy, MeterProfile, PolicyAction};
+use log::{info, warn, error};
 
 /// Handles the READ operation within the state machine.
-pub async fn handle_read(state_machine: &mut StateMachine) {
+///
+/// Publishes a snapshot of all read registers every cycle; registers that could not be read
+/// carry their last good value marked stale, or a comm error.
+pub async fn handle_read(state_machine: &mut StateMachine) -> Option<Event> {
     info!("State: READ");
 
     // Limit the scope of the immutable borrow of state_machine
-    let modbus_context_option = {
+    let (modbus_context_option, identity, selected_profile, path) = {
         let lock = state_machine.modbus_statemachine.lock().await;
-        lock.access_modbus_context().await
+        (lock.access_modbus_context().await, lock.identity(), lock.selected_profile().map(str::to_string), lock.active_path())
     };
 
+    let (settings, profile, derived) = {
+        let meter_type = match selected_profile {
+            Some(selected) => selected,
+            None => state_machine.config.lock().await.meter_data.meter_type.clone(),
+        };
+        let settings = state_machine.settings.lock().await;
+        (settings.modbus.clone(), settings.profile(&meter_type), settings.derived.clone())
+    };
+    let policy = &settings.exception_policy;
+    let started = SystemTime::now();
+    let cycle = Instant::now();
+
     // Await the future and handle the Result
-    match modbus_context_option {
+    let (readings, event) = match modbus_context_option {
         Ok(Some(modbus_context)) => {
-            let mut context = modbus_context.lock().await;
             info!("Using established socket connection for read operation.");
-            match perform_read_operations(state_machine, &mut context).await {
-                Ok(_) => {
+            let result = {
+                let mut client = modbus_context.lock().await;
+                client.configure(&settings);
+                perform_read_operations(state_machine, &mut client, policy, &profile).await
+            };
+            match result {
+                Ok(readings) => {
                     info!("Read operation completed successfully.");
-                    state_machine.state = State::Idle;
+                    state_machine.backoff = None;
+                    (readings, Event::ReadSuccessful)
                 },
                 Err(e) => {
                     error!("Read operation failed: {}", e);
-                    state_machine.state = State::Idle; // Transition to Idle on error
+                    state_machine.history.note(e.to_string());
+                    apply_policy(state_machine, policy, &e).await;
+                    (Vec::new(), Event::ReadFail)
                 }
             }
         },
         Ok(None) => {
             info!("No active socket connection found, unable to perform read operation.");
-            state_machine.state = State::Idle; // Transition to Idle state
+            (Vec::new(), Event::NoSocket)
         },
         Err(e) => {
             error!("Error accessing Modbus context: {}", e);
-            state_machine.state = State::Idle; // Transition to Idle on error
+            state_machine.history.note(e.to_string());
+            (Vec::new(), Event::NoSocket)
+        }
+    };
+
+    let (meter_id, registers) = {
+        let config = state_machine.config.lock().await;
+        (meter_id(&config), config.get_read_registers())
+    };
+    let snapshot = Snapshot { meter_id, timestamp: started, latency: cycle.elapsed(), identity, path, readings };
+    state_machine.publish(snapshot, &registers, &profile, &derived);
+    Some(event)
+}
+
+/// Applies the configured policy for a read that failed after retries and skips.
+async fn apply_policy(state_machine: &mut StateMachine, policy: &ExceptionPolicy, error: &ModbusError) {
+    match policy.action_for(error) {
+        PolicyAction::Backoff => {
+            let backoff = policy.next_backoff(state_machine.backoff);
+            warn!("Backing off for an extra {:?} before the next read", backoff);
+            state_machine.backoff = Some(backoff);
         }
+        PolicyAction::Reconnect => {
+            state_machine.backoff = None;
+            let mut modbus_statemachine = state_machine.modbus_statemachine.lock().await;
+            modbus_statemachine.request_reconnect(&error.to_string()).await;
+        }
+        PolicyAction::Retry | PolicyAction::Skip => {}
     }
 }
 
-fn decode_and_display_values(
+/// Decodes the registers covered by a block read starting at `start_address`.
+fn decode_readings(
+    meter_id: &str,
+    profile: &MeterProfile,
     read_registers: &[ConfigRegister],
     values_vec: &[u16],
     start_address: u16,
-) -> Result<(), Box<dyn Error>> {
+    timestamp: SystemTime,
+    latency: Duration,
+) -> Result<Vec<Reading>, ModbusError> {
+    let mut readings = Vec::with_capacity(read_registers.len());
     for register in read_registers {
+        let info = profile.register(&register.name);
         let offset = (register.address - start_address) as usize;
-        if offset + 1 >= values_vec.len() {
-            return Err(format!("Index out of bounds for register: {}", register.name).into());
+        let end = offset + info.words();
+        if end > values_vec.len() {
+            return Err(ModbusError::Decode(format!("Index out of bounds for register: {}", register.name)));
         }
-        let high = values_vec[offset] as u32;
-        let low = values_vec[offset + 1] as u32;
-        let value = f32::from_bits((high << 16) | low);
-        info!("Name: {}, Address: {}, Value: {}", register.name, register.address, value);
+        let reading = Reading::decode(meter_id, &register.name, register.address, &values_vec[offset..end], &info, timestamp, latency);
+        info!("{}", reading);
+        readings.push(reading);
     }
-    Ok(())
+    Ok(readings)
+}
+
+/// Reads the registers one by one, leaving out those whose errors the policy says to skip.
+async fn read_registers_individually(
+    meter_id: &str,
+    profile: &MeterProfile,
+    client: &mut MeterClient,
+    policy: &ExceptionPolicy,
+    read_registers: &[ConfigRegister],
+) -> Result<Vec<Reading>, ModbusError> {
+    let mut readings = Vec::with_capacity(read_registers.len());
+    for register in read_registers {
+        let timestamp = SystemTime::now();
+        let sent = Instant::now();
+        let words = profile.register(&register.name).words() as u16;
+        match client.read_holding_registers(register.address, words).await {
+            Ok(values) => readings.extend(decode_readings(
+                meter_id,
+                profile,
+                std::slice::from_ref(register),
+                &values,
+                register.address,
+                timestamp,
+                sent.elapsed(),
+            )?),
+            Err(e) if policy.action_for(&e) == PolicyAction::Skip => {
+                warn!("Skipping register {} at address {}: {}", register.name, register.address, e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(readings)
 }
 
 /// Performs read operations for the state machine, managing configurations and Modbus interactions.
-async fn perform_read_operations(state_machine: &mut StateMachine, context: &mut ModbusContext) -> Result<(), Box<dyn Error>> {
+async fn perform_read_operations(
+    state_machine: &mut StateMachine,
+    client: &mut MeterClient,
+    policy: &ExceptionPolicy,
+    profile: &MeterProfile,
+) -> Result<Vec<Reading>, ModbusError> {
     info!("Attempting to lock the configuration for reading.");
     let locked_config = state_machine.config.lock().await;
     info!("Configuration locked successfully.");
 
     let config = &*locked_config;
     info!("Configuration accessed.");
+    let meter_id = meter_id(config);
 
     let read_registers: Vec<ConfigRegister> = config.get_read_registers();
     info!("Read registers retrieved: {:?}", read_registers);
 
     if read_registers.is_empty() {
         error!("No registers configured for reading.");
-        return Err("No registers configured".into());
+        return Err(ModbusError::Config("No registers configured".to_string()));
     }
 
+    // Each register takes as many words as its format; strings can be longer than the rest
     let start_address = read_registers.iter().map(|r| r.address).min().unwrap();
-    let end_address = read_registers.iter().map(|r| r.address).max().unwrap();
-    let quantity = (end_address - start_address + 2) as u16;
+    let end_address = read_registers
+        .iter()
+        .map(|r| u32::from(r.address) + profile.register(&r.name).words() as u32)
+        .max()
+        .unwrap();
+    let quantity = u16::try_from(end_address - u32::from(start_address))
+        .map_err(|_| ModbusError::Config(format!("registers from {} to {} do not fit one read", start_address, end_address)))?;
 
     info!("Determined range of registers to read:");
     info!("  - Start address: {}", start_address);
@@ -87,17 +187,23 @@ async fn perform_read_operations(state_machine: &mut StateMachine, context: &mut
     info!("  - Quantity to read: {}", quantity);
 
     info!("Modbus context is available, proceeding with the read operation.");
-    match context.read_holding_registers(start_address, quantity).await {
+    let timestamp = SystemTime::now();
+    let sent = Instant::now();
+    let readings = match client.read_holding_registers(start_address, quantity).await {
         Ok(values) => {
             info!("Successfully read values from Modbus device:");
-            decode_and_display_values(&read_registers, &values?, start_address)?;
+            decode_readings(&meter_id, profile, &read_registers, &values, start_address, timestamp, sent.elapsed())?
+        },
+        Err(e) if policy.action_for(&e) == PolicyAction::Skip => {
+            warn!("Block read failed ({}), falling back to reading registers one by one", e);
+            read_registers_individually(&meter_id, profile, client, policy, &read_registers).await?
         },
         Err(e) => {
             error!("Failed to read registers: {}", e);
-            return Err(e.into());
+            return Err(e);
         }
-    }
+    };
 
     info!("Read operation completed successfully.");
-    Ok(())
+    Ok(readings)
 }
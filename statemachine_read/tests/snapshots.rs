@@ -0,0 +1,180 @@
+// statemachine_read/tests/snapshots.rs
+//
+// Snapshots published by the read state machine, against the simulator over an in-memory
+// stream on tokio's paused clock.
+
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_modbus::client::tcp;
+use config_meter_generic::config::Config;
+use common_meter_generic::{Decoded, MeterClient, Quality, Settings, Snapshot};
+use simulator_meter_generic::{ExceptionFault, Faults, SimulatedRegister, Simulator, SimulatorProfile, Waveform};
+use statemachine_modbus::statemachine::{State as ModbusState, StateMachine as ModbusStateMachine};
+use statemachine_modbus::transport::{MockReachability, MockTransport};
+use statemachine_read::statemachine::{State, StateMachine};
+
+const CONFIG: &str = r#"
+meter_data:
+  ip: "10.15.1.2"
+  port: 502
+  meter_type: "Mock"
+write_registers: []
+read_registers:
+  - name: voltage_L1_N
+    address: 32774
+  - name: voltage_L2_N
+    address: 32776
+debug:
+  mgw_generic: "off"
+  statemachine_modbus: "off"
+  statemachine_read: "off"
+"#;
+
+const SETTINGS: &str = r#"
+profiles:
+  Mock:
+    registers:
+      voltage_L1_N:
+        unit: V
+        min: 0
+        max: 300
+      voltage_L2_N:
+        unit: V
+        max: 300
+derived:
+  - name: voltage_L1_L2
+    expression: sqrt(voltage_L1_N^2 + voltage_L2_N^2 + voltage_L1_N * voltage_L2_N)
+    unit: V
+  - name: voltage_L1_L2_ratio
+    expression: voltage_L1_L2 / voltage_L1_N
+"#;
+
+fn simulator() -> Simulator {
+    let register = |name: &str, address, value| SimulatedRegister {
+        name: name.to_string(),
+        address,
+        waveform: Waveform::Constant { value },
+    };
+    Simulator::new(SimulatorProfile {
+        registers: vec![register("voltage_L1_N", 32774, 230.0), register("voltage_L2_N", 32776, 400.0)],
+        ..Default::default()
+    })
+}
+
+/// A read state machine whose Modbus state machine holds a connection to `simulator`, if any.
+async fn state_machine(simulator: Option<&Simulator>) -> Arc<Mutex<StateMachine>> {
+    state_machine_with(CONFIG, SETTINGS, simulator).await
+}
+
+async fn state_machine_with(config: &str, settings: &str, simulator: Option<&Simulator>) -> Arc<Mutex<StateMachine>> {
+    let config: Config = serde_yaml::from_str(config).unwrap();
+    let settings: Settings = serde_yaml::from_str(settings).unwrap();
+    let context = simulator.map(|simulator| {
+        let (client, server) = tokio::io::duplex(1024);
+        let simulator = simulator.clone();
+        tokio::spawn(async move { simulator.serve_connection(server).await });
+        Arc::new(Mutex::new(MeterClient::new(tcp::attach(client), &settings.modbus)))
+    });
+
+    let config = Arc::new(Mutex::new(config));
+    let settings = Arc::new(Mutex::new(settings));
+    let modbus = ModbusStateMachine::with_transport(
+        config.clone(),
+        settings.clone(),
+        Arc::new(MockTransport::new()),
+        Arc::new(MockReachability::new(true)),
+    );
+    {
+        let mut modbus = modbus.lock().await;
+        modbus.state = ModbusState::Verify;
+        modbus.modbus_context = context;
+    }
+    StateMachine::new(config, settings, modbus)
+}
+
+async fn read_once(state_machine: &Arc<Mutex<StateMachine>>) -> Snapshot {
+    let mut state_machine = state_machine.lock().await;
+    let mut snapshots = state_machine.subscribe_snapshots();
+    state_machine.state = State::Read;
+    state_machine.step().await;
+    snapshots.try_recv().expect("a snapshot per read cycle")
+}
+
+#[tokio::test(start_paused = true)]
+async fn grades_fresh_readings_against_the_profile() {
+    let simulator = simulator();
+    let snapshot = read_once(&state_machine(Some(&simulator)).await).await;
+
+    assert_eq!(snapshot.meter_id, "10.15.1.2:502");
+    assert_eq!(snapshot.readings.len(), 4);
+
+    let l1 = snapshot.get("voltage_L1_N").unwrap();
+    assert_eq!(l1.value, Some(230.0));
+    assert_eq!(l1.raw, vec![(230f32.to_bits() >> 16) as u16, 230f32.to_bits() as u16]);
+    assert_eq!(l1.unit.as_deref(), Some("V"));
+    assert_eq!(l1.quality, Quality::Good);
+
+    let l2 = snapshot.get("voltage_L2_N").unwrap();
+    assert_eq!(l2.value, Some(400.0));
+    assert_eq!(l2.quality, Quality::OutOfRange);
+    assert!(!snapshot.is_good());
+
+    // Derived from an out-of-range input, and from that derived channel in turn
+    let l1_l2 = snapshot.get("voltage_L1_L2").unwrap();
+    assert_eq!(l1_l2.value.map(f32::round), Some(552.0));
+    assert_eq!((l1_l2.unit.as_deref(), l1_l2.quality), (Some("V"), Quality::OutOfRange));
+    assert_eq!(snapshot.get("voltage_L1_L2_ratio").unwrap().quality, Quality::OutOfRange);
+}
+
+#[tokio::test(start_paused = true)]
+async fn carries_the_last_good_value_over_a_failed_cycle() {
+    let simulator = simulator();
+    let state_machine = state_machine(Some(&simulator)).await;
+    read_once(&state_machine).await;
+
+    let busy = ExceptionFault { function: None, start: 0, end: u16::MAX, code: 0x06 };
+    simulator.set_faults(Faults { exceptions: vec![busy], ..Default::default() }).await;
+    let snapshot = read_once(&state_machine).await;
+
+    let l1 = snapshot.get("voltage_L1_N").unwrap();
+    assert_eq!((l1.value, l1.quality), (Some(230.0), Quality::Stale));
+    // Out of range is no value to fall back on
+    let l2 = snapshot.get("voltage_L2_N").unwrap();
+    assert_eq!((l2.value, l2.quality), (None, Quality::CommError));
+}
+
+#[tokio::test(start_paused = true)]
+async fn reports_comm_errors_without_a_connection() {
+    let snapshot = read_once(&state_machine(None).await).await;
+
+    assert_eq!(snapshot.readings.len(), 4);
+    assert!(snapshot.readings.iter().all(|reading| reading.quality == Quality::CommError && reading.value.is_none()));
+}
+
+#[tokio::test(start_paused = true)]
+async fn reads_string_registers_as_text() {
+    let config = CONFIG.replace("debug:", "  - name: serial_number\n    address: 32778\ndebug:");
+    let settings = SETTINGS.replace("    registers:\n", "    registers:\n      serial_number: { format: string, length: 4 }\n");
+    // "SN123456", two characters per word and two words per simulated register
+    let text = |name: &str, address, words: [u16; 2]| SimulatedRegister {
+        name: name.to_string(),
+        address,
+        waveform: Waveform::Constant { value: f32::from_bits(u32::from(words[0]) << 16 | u32::from(words[1])) },
+    };
+    let simulator = Simulator::new(SimulatorProfile {
+        registers: vec![
+            SimulatedRegister { name: "voltage_L1_N".to_string(), address: 32774, waveform: Waveform::Constant { value: 230.0 } },
+            text("serial_1", 32778, [0x534E, 0x3132]),
+            text("serial_2", 32780, [0x3334, 0x3536]),
+        ],
+        ..Default::default()
+    });
+    let snapshot = read_once(&state_machine_with(&config, &settings, Some(&simulator)).await).await;
+
+    let serial = snapshot.get("serial_number").unwrap();
+    assert_eq!(serial.decoded, Some(Decoded::Text("SN123456".to_string())));
+    assert_eq!((serial.value, serial.quality), (None, Quality::Good));
+    assert_eq!(serial.raw, vec![0x534E, 0x3132, 0x3334, 0x3536]);
+    // The block read spans the whole string next to the floats
+    assert_eq!(snapshot.get("voltage_L1_N").unwrap().value, Some(230.0));
+}
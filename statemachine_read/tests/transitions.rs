@@ -0,0 +1,115 @@
+// statemachine_read/tests/transitions.rs
+//
+// One case per row of the statemachine_read table in the README, run on tokio's paused
+// clock against the simulator over an in-memory stream.
+
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tokio_modbus::client::tcp;
+use config_meter_generic::config::{Config, ConfigRegister};
+use common_meter_generic::{MeterClient, Settings};
+use simulator_meter_generic::{ExceptionFault, Faults, Simulator, SimulatorProfile};
+use statemachine_modbus::statemachine::{State as ModbusState, StateMachine as ModbusStateMachine};
+use statemachine_modbus::transport::{MockReachability, MockTransport};
+use statemachine_read::statemachine::{State, StateMachine};
+
+const CONFIG: &str = r#"
+meter_data:
+  ip: "10.15.1.2"
+  port: 502
+  meter_type: "Mock"
+write_registers: []
+read_registers:
+  - name: voltage_L1_N
+    address: 32774
+  - name: voltage_L2_N
+    address: 32776
+debug:
+  mgw_generic: "off"
+  statemachine_modbus: "off"
+  statemachine_read: "off"
+"#;
+
+/// How the meter behind the Modbus context behaves during the step.
+#[derive(Debug, Clone, Copy)]
+enum Meter {
+    Answering,
+    Busy,
+    Gone,
+    /// The Modbus state machine has no context
+    NotConnected,
+}
+
+struct Case {
+    row: &'static str,
+    from: State,
+    meter: Meter,
+    expected: State,
+    /// Whether the Modbus state machine still holds its context afterwards
+    connected: bool,
+    waits: Duration,
+}
+
+fn cases() -> Vec<Case> {
+    let case = |row, from, meter, expected, connected| Case { row, from, meter, expected, connected, waits: Duration::ZERO };
+    vec![
+        Case { waits: Duration::from_secs(5), ..case("Idle / Timer", State::Idle, Meter::Answering, State::Read, true) },
+        case("Read / Read Successful", State::Read, Meter::Answering, State::Idle, true),
+        case("Read / Read Fail (busy)", State::Read, Meter::Busy, State::Idle, true),
+        case("Read / Read Fail (connection lost)", State::Read, Meter::Gone, State::Idle, false),
+        case("Read / No Socket", State::Read, Meter::NotConnected, State::Idle, false),
+    ]
+}
+
+#[tokio::test(start_paused = true)]
+async fn transitions_match_the_readme_table() {
+    let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+    let registers = config.get_read_registers();
+    let config = Arc::new(Mutex::new(config));
+    let settings = Arc::new(Mutex::new(Settings::default()));
+
+    for case in cases() {
+        let modbus = ModbusStateMachine::with_transport(
+            config.clone(),
+            settings.clone(),
+            Arc::new(MockTransport::new()),
+            Arc::new(MockReachability::new(true)),
+        );
+        {
+            let mut modbus = modbus.lock().await;
+            modbus.state = ModbusState::Verify;
+            modbus.modbus_context = connect(case.meter, &registers).await;
+        }
+
+        let state_machine = StateMachine::new(config.clone(), settings.clone(), modbus.clone());
+        let mut state_machine = state_machine.lock().await;
+        state_machine.state = case.from;
+
+        let started = Instant::now();
+        state_machine.step().await;
+
+        assert_eq!(state_machine.state, case.expected, "{}", case.row);
+        assert_eq!(modbus.lock().await.modbus_context.is_some(), case.connected, "{}: connection", case.row);
+        assert!(started.elapsed() >= case.waits, "{}: took {:?}", case.row, started.elapsed());
+    }
+}
+
+async fn connect(meter: Meter, registers: &[ConfigRegister]) -> Option<Arc<Mutex<MeterClient>>> {
+    let (client, server) = tokio::io::duplex(1024);
+    let simulator = Simulator::new(SimulatorProfile::from_registers(registers));
+    match meter {
+        Meter::NotConnected => return None,
+        Meter::Gone => drop(server),
+        Meter::Busy => {
+            let busy = ExceptionFault { function: None, start: 0, end: u16::MAX, code: 0x06 };
+            simulator.set_faults(Faults { exceptions: vec![busy], ..Default::default() }).await;
+            tokio::spawn(async move { simulator.serve_connection(server).await });
+        }
+        Meter::Answering => {
+            tokio::spawn(async move { simulator.serve_connection(server).await });
+        }
+    }
+    let client = MeterClient::new(tcp::attach(client), &Settings::default().modbus);
+    Some(Arc::new(Mutex::new(client)))
+}